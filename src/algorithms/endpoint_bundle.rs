@@ -0,0 +1,169 @@
+use crate::algorithms::json_diff::{json_diff, JsonChange};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+/// Identifies an endpoint across servers, where ids differ
+pub fn endpoint_key(method: &str, path: &str) -> String {
+    format!("{} /{}", method, path.trim_matches('/'))
+}
+
+/// Definition stored in `__B_endpoints`
+pub struct StoredDefinition {
+    pub id: i32,
+    pub key: String,
+    pub definition: Value,
+}
+
+/// Definition of an imported bundle
+pub struct BundledDefinition {
+    pub key: String,
+    pub definition: Value,
+}
+
+/// `index` is the position of the definition in the bundle
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum BundleChange {
+    Create {
+        endpoint: String,
+        #[serde(skip)]
+        index: usize,
+    },
+    Update {
+        endpoint: String,
+        id: i32,
+        changes: Vec<JsonChange>,
+        #[serde(skip)]
+        index: usize,
+    },
+    Delete {
+        endpoint: String,
+        id: i32,
+    },
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ImportPlan {
+    pub changes: Vec<BundleChange>,
+    pub unchanged: usize,
+}
+
+/// Changes making the stored endpoints equal to the bundle,
+/// stored endpoints missing in the bundle are deleted
+pub fn plan_import(
+    stored: &[StoredDefinition],
+    bundle: &[BundledDefinition],
+) -> Result<ImportPlan> {
+    let mut keys = HashSet::new();
+    for bundled in bundle {
+        if !keys.insert(bundled.key.as_str()) {
+            return Err(anyhow!("{} is in the bundle twice", bundled.key));
+        }
+    }
+
+    let stored_by_key = stored
+        .iter()
+        .map(|it| (it.key.as_str(), it))
+        .collect::<HashMap<_, _>>();
+
+    let mut changes = Vec::new();
+    let mut unchanged = 0;
+
+    for (index, bundled) in bundle.iter().enumerate() {
+        match stored_by_key.get(bundled.key.as_str()) {
+            None => changes.push(BundleChange::Create {
+                endpoint: bundled.key.clone(),
+                index,
+            }),
+            Some(current) => {
+                let diff = json_diff(&current.definition, &bundled.definition);
+
+                if diff.is_empty() {
+                    unchanged += 1;
+                } else {
+                    changes.push(BundleChange::Update {
+                        endpoint: bundled.key.clone(),
+                        id: current.id,
+                        changes: diff,
+                        index,
+                    });
+                }
+            }
+        }
+    }
+
+    for current in stored {
+        if !keys.contains(current.key.as_str()) {
+            changes.push(BundleChange::Delete {
+                endpoint: current.key.clone(),
+                id: current.id,
+            });
+        }
+    }
+
+    Ok(ImportPlan { changes, unchanged })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn planning() {
+        let stored = |id: i32, path: &str, sql: &str| StoredDefinition {
+            id,
+            key: endpoint_key("GET", path),
+            definition: json!({ "path": path, "sql": sql }),
+        };
+        let bundled = |path: &str, sql: &str| BundledDefinition {
+            key: endpoint_key("GET", path),
+            definition: json!({ "path": path, "sql": sql }),
+        };
+
+        let plan = plan_import(
+            &[
+                stored(1, "users", "SELECT 1"),
+                stored(2, "posts", "SELECT 2"),
+                stored(3, "old", "SELECT 3"),
+            ],
+            &[
+                bundled("users", "SELECT 1"),
+                bundled("posts", "SELECT 22"),
+                bundled("new", "SELECT 4"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            plan,
+            ImportPlan {
+                changes: vec![
+                    BundleChange::Update {
+                        endpoint: "GET /posts".into(),
+                        id: 2,
+                        changes: vec![JsonChange {
+                            path: "sql".into(),
+                            old: Some("SELECT 2".into()),
+                            new: Some("SELECT 22".into()),
+                        }],
+                        index: 1,
+                    },
+                    BundleChange::Create {
+                        endpoint: "GET /new".into(),
+                        index: 2,
+                    },
+                    BundleChange::Delete {
+                        endpoint: "GET /old".into(),
+                        id: 3,
+                    },
+                ],
+                unchanged: 1,
+            }
+        );
+
+        assert!(plan_import(&[], &[bundled("users", "1"), bundled("/users/", "2")]).is_err());
+    }
+}
@@ -1,54 +1,145 @@
-use crate::{
-    algorithms::sql_variable_parser::EndpointInfo, types::arbitrary_sql_row::ArbitrarySqlRow,
+use crate::algorithms::{
+    execution_limits::ExecutionLimits,
+    json_path::resolve_path,
+    pagination::{encode_cursor, Pagination},
+    sql_variable_parser::{
+        is_ordered, replace_parameters, statement_kind, Cardinality, EndpointInfo, NodeKind,
+        StatementKind,
+    },
 };
+use crate::err_utils::status_error;
+use crate::types::arbitrary_sql_row::ArbitrarySqlRow;
+use crate::types::sql_value::SqlValue;
 use anyhow::{anyhow, Result};
 use async_recursion::async_recursion;
-use serde::Serialize;
-use sqlx::{Postgres, Transaction};
+use axum::http::StatusCode;
+use serde::ser::{SerializeMap, SerializeStruct};
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use sqlx::postgres::PgArguments;
+use sqlx::query::Query;
+use sqlx::{FromRow, Postgres, Transaction};
 use std::collections::HashMap;
+use std::sync::Arc;
 
-#[derive(Serialize, Debug, PartialEq)]
+#[derive(Debug, PartialEq)]
 pub struct ExecutionResult {
-    pub data: HashMap<String, String>,
-    pub children: HashMap<String, Vec<ExecutionResult>>,
+    pub data: HashMap<String, Value>,
+    pub children: HashMap<String, NodeResult>,
+    /// Children are serialized as fields of the row instead of `data` and `children`
+    pub flattened: bool,
+}
+
+impl Serialize for ExecutionResult {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if self.flattened {
+            let mut map = serializer.serialize_map(Some(self.data.len() + self.children.len()))?;
+            for (key, value) in &self.data {
+                map.serialize_entry(key, value)?;
+            }
+            for (key, value) in &self.children {
+                map.serialize_entry(key, value)?;
+            }
+            map.end()
+        } else {
+            let mut result = serializer.serialize_struct("ExecutionResult", 2)?;
+            result.serialize_field("data", &self.data)?;
+            result.serialize_field("children", &self.children)?;
+            result.end()
+        }
+    }
+}
+
+/// Rows returned by one query of the tree
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum NodeResult {
+    Rows(Vec<ExecutionResult>),
+    /// Rows of a paginated query, `next_cursor` is `None` on the last page
+    Page {
+        rows: Vec<ExecutionResult>,
+        next_cursor: Option<String>,
+    },
+    /// Row of a `one` or `optional-one` query
+    One(Option<ExecutionResult>),
+    /// The only column of a `scalar` query, null when there's no row
+    Scalar(Value),
 }
 
+/// Rows of the ancestor queries of an execution, the parent row last
+type ExecutionContext = Vec<Arc<HashMap<String, Value>>>;
+
 #[derive(Debug)]
 pub struct EndpointExecutionRuntime {
-    request_map: HashMap<String, String>,
-    execution_maps: Vec<HashMap<String, String>>,
+    request: Value,
+    path_params: HashMap<String, String>,
+    user: Option<Value>,
+    execution_maps: ExecutionContext,
+    max_rows: Option<usize>,
+    max_response_bytes: Option<u64>,
+    /// Size of the rows returned so far, serialized as json
+    response_bytes: u64,
 }
 
 impl EndpointExecutionRuntime {
-    pub fn new(request_variables: HashMap<String, String>) -> Self {
+    pub fn new(request: Value) -> Self {
         Self {
-            request_map: request_variables,
+            request,
+            path_params: HashMap::new(),
+            user: None,
             execution_maps: vec![],
+            max_rows: None,
+            max_response_bytes: None,
+            response_bytes: 0,
         }
     }
 
-    fn push_execution_map(&mut self, map: HashMap<String, String>) {
-        self.execution_maps.push(map);
+    /// Row and size limits, the statement timeout is applied to the transaction
+    pub fn with_limits(mut self, limits: &ExecutionLimits) -> Self {
+        self.max_rows = limits.max_rows.map(|it| it as usize);
+        self.max_response_bytes = limits.max_response_bytes;
+        self
+    }
+
+    /// Values of the `user.` variables, `None` when the caller isn't logged in
+    pub fn with_user(mut self, user: Option<Value>) -> Self {
+        self.user = user;
+        self
     }
 
-    fn pop_execution_map(&mut self) -> Option<HashMap<String, String>> {
-        self.execution_maps.pop()
+    /// Values captured from the `:param` segments of the endpoint path
+    pub fn with_path_params(mut self, path_params: HashMap<String, String>) -> Self {
+        self.path_params = path_params;
+        self
     }
 
-    fn get_variable_clone(&self, key: &str) -> Result<String> {
-        if key.len() >= 4 && &key[0..4] == "req." {
-            let key = &key[4..];
-            return self
-                .request_map
+    fn get_variable_clone(&self, key: &str) -> Result<Value> {
+        if let Some(key) = key.strip_prefix("req.") {
+            resolve_path(&self.request, key)?
+                .cloned()
+                .ok_or(anyhow!("Request key {} not found", key))
+        } else if let Some(key) = key.strip_prefix("path.") {
+            self.path_params
                 .get(key)
-                .map(|it| it.clone())
-                .ok_or(anyhow!("Request key {} not found", key));
-        } else if key.len() >= 6 && &key[0..6] == "super." {
+                .map(|it| Value::String(it.clone()))
+                .ok_or(anyhow!("Path parameter {} not found", key))
+        } else if let Some(key) = key.strip_prefix("user.") {
+            let user = self.user.as_ref().ok_or_else(|| {
+                status_error(
+                    StatusCode::UNAUTHORIZED,
+                    "This endpoint requires an authenticated user",
+                )
+            })?;
+            user.get(key)
+                .filter(|it| !it.is_null())
+                .cloned()
+                .ok_or(anyhow!("User variable {} not found", key))
+        } else if key.starts_with("super.") {
             let mut counter = 0_usize;
             let mut inner_key = key;
 
-            while inner_key.len() >= 6 && &inner_key[0..6] == "super." {
-                inner_key = &inner_key[6..];
+            while let Some(rest) = inner_key.strip_prefix("super.") {
+                inner_key = rest;
                 counter += 1;
             }
 
@@ -61,98 +152,428 @@ impl EndpointExecutionRuntime {
 
             let vec_index = self.execution_maps.len() - counter;
             let map = &self.execution_maps[vec_index];
-            return map
-                .get(inner_key)
-                .map(|it| it.clone())
-                .ok_or(anyhow!("Execution key {} not found", key));
+            map.get(inner_key)
+                .cloned()
+                .ok_or(anyhow!("Execution key {} not found", key))
         } else {
-            return Err(anyhow!(
-                "Bad variable name ({}). Should begin with super. or req.",
+            Err(anyhow!(
+                "Bad variable name ({}). Should begin with super., req., path. or user.",
                 key
-            ));
+            ))
+        }
+    }
+
+    /// Values of the query variables in the current execution context
+    fn query_params(&self, query: &EndpointInfo) -> Result<Vec<SqlValue>> {
+        let mut params = Vec::with_capacity(query.variables.len());
+
+        for (i, var_name) in query.variables.iter().enumerate() {
+            let value = self.get_variable_clone(var_name)?;
+
+            let val = match query.variable_types.get(i).and_then(Option::as_ref) {
+                Some(ty) => SqlValue::convert(value, ty).map_err(|e| {
+                    status_error(
+                        StatusCode::BAD_REQUEST,
+                        format!("Bad value of variable {}: {}", var_name, e),
+                    )
+                })?,
+                None => SqlValue::from_json(value),
+            };
+
+            params.push(val);
+        }
+
+        Ok(params)
+    }
+
+    /// Wraps a paginated query and appends the cursor and the limit to its parameters.
+    ///
+    /// One row more than the page size is fetched to find out whether a next page exists,
+    /// the returned limit is the page size.
+    fn paginate(
+        &self,
+        query: &EndpointInfo,
+        pagination: &Pagination,
+        params: &mut Vec<SqlValue>,
+    ) -> Result<(String, usize)> {
+        let limit = pagination
+            .limit(&self.request)
+            .map_err(|e| status_error(StatusCode::BAD_REQUEST, e))?;
+        let cursor = pagination
+            .cursor(&self.request)
+            .map_err(|e| status_error(StatusCode::BAD_REQUEST, e))?;
+
+        let cursor_param = cursor.map(|value| {
+            params.push(value);
+            params.len()
+        });
+        params.push(SqlValue::BigInt(limit as i64 + 1));
+
+        let sql = pagination.paginated_sql(&query.parsed_sql, cursor_param, params.len());
+
+        Ok((sql, limit as usize))
+    }
+
+    /// Fails with 413 when the rows returned by a query for one context exceed the limit
+    fn count_rows(&self, query: &EndpointInfo, rows: usize) -> Result<()> {
+        match self.max_rows {
+            Some(max_rows) if rows > max_rows => Err(status_error(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("Query {} returned more than {} rows", query.name, max_rows),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails with 413 when the rows of the response, without private columns, exceed the limit
+    fn count_bytes(&mut self, row: &HashMap<String, Value>) -> Result<()> {
+        if let Some(max_response_bytes) = self.max_response_bytes {
+            self.response_bytes += serde_json::to_vec(row)?.len() as u64;
+
+            if self.response_bytes > max_response_bytes {
+                return Err(status_error(
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    format!("Response is larger than {} bytes", max_response_bytes),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs the query of a guard or a condition, it passes when it returns
+    /// a row which isn't a single `false` or null
+    async fn evaluate(
+        &mut self,
+        #[cfg(test)] mock_exec_service: &mut ExecutionMockService,
+        #[cfg(not(test))] transaction: &mut Transaction<'_, Postgres>,
+        query: &EndpointInfo,
+        context: &ExecutionContext,
+    ) -> Result<bool> {
+        self.execution_maps = context.clone();
+        let params = self.query_params(query)?;
+
+        #[cfg(test)]
+        let rows = mock_exec_service.fetch(&query.parsed_sql, params);
+        #[cfg(not(test))]
+        let rows = fetch(transaction, &query.parsed_sql, params).await?;
+
+        let passed = match rows.first() {
+            None => false,
+            Some(row) if row.len() == 1 => {
+                !matches!(row.values().next(), Some(Value::Bool(false) | Value::Null))
+            }
+            Some(_) => true,
+        };
+
+        Ok(passed)
+    }
+
+    /// Parameters for executing the query once for all contexts, one array per variable.
+    ///
+    /// `None` when the query has to be executed separately for every context:
+    /// it isn't a select, it sorts its rows, it uses other than `super.` variables
+    /// or values of some variable don't have a common type.
+    fn batch_params(
+        &mut self,
+        query: &EndpointInfo,
+        contexts: &[ExecutionContext],
+    ) -> Result<Option<Vec<SqlValue>>> {
+        if contexts.len() < 2
+            || !is_select(query)
+            || is_ordered(&query.parsed_sql)?
+            || query.pagination.is_some()
+            || query.cardinality == Cardinality::AffectedRows
+            || query.variables.is_empty()
+            || !query.variables.iter().all(|it| it.starts_with("super."))
+        {
+            return Ok(None);
+        }
+
+        let mut columns = vec![Vec::with_capacity(contexts.len()); query.variables.len()];
+
+        for context in contexts {
+            self.execution_maps = context.clone();
+
+            for (column, value) in columns.iter_mut().zip(self.query_params(query)?) {
+                column.push(value);
+            }
         }
+
+        Ok(columns.into_iter().map(SqlValue::array_of).collect())
     }
 
+    #[cfg_attr(test, allow(unused_variables))]
     pub async fn execute(
         &mut self,
         transaction: &mut Transaction<'_, Postgres>,
-        endpoint_infos: &Vec<EndpointInfo>,
-    ) -> Result<HashMap<String, Vec<ExecutionResult>>> {
+        endpoint_infos: &[EndpointInfo],
+    ) -> Result<HashMap<String, NodeResult>> {
         #[cfg(not(test))]
         return self.execute_impl(transaction, endpoint_infos).await;
         #[cfg(test)]
         panic!("Function should not be called in test configuration");
     }
 
-    #[async_recursion]
     pub async fn execute_impl(
         &mut self,
         #[cfg(test)] mock_exec_service: &mut ExecutionMockService,
         #[cfg(not(test))] transaction: &mut Transaction<'_, Postgres>,
-        endpoint_infos: &Vec<EndpointInfo>,
-    ) -> Result<HashMap<String, Vec<ExecutionResult>>> {
-        let mut final_results = HashMap::<String, Vec<ExecutionResult>>::new();
+        endpoint_infos: &[EndpointInfo],
+    ) -> Result<HashMap<String, NodeResult>> {
+        let contexts = vec![self.execution_maps.clone()];
 
-        for query in endpoint_infos {
-            #[cfg(not(test))]
-            let mut exec = sqlx::query_as::<Postgres, ArbitrarySqlRow>(&query.parsed_sql);
-            for var_name in &query.variables {
-                let val = self.get_variable_clone(var_name)?;
+        #[cfg(test)]
+        let mut results = self
+            .execute_level(mock_exec_service, endpoint_infos, &contexts)
+            .await?;
+        #[cfg(not(test))]
+        let mut results = self
+            .execute_level(transaction, endpoint_infos, &contexts)
+            .await?;
+
+        Ok(results.pop().unwrap_or_default())
+    }
+
+    /// Executes the queries in every context. Children of all returned rows
+    /// are executed together, so a child query runs once per level of the tree
+    /// instead of once per parent row whenever it can be batched.
+    ///
+    /// Queries with side effects are executed depth-first, the whole tree
+    /// for one parent row before the next one, as they would be without batching.
+    #[async_recursion]
+    async fn execute_level(
+        &mut self,
+        #[cfg(test)] mock_exec_service: &mut ExecutionMockService,
+        #[cfg(not(test))] transaction: &mut Transaction<'_, Postgres>,
+        endpoint_infos: &[EndpointInfo],
+        contexts: &[ExecutionContext],
+    ) -> Result<Vec<HashMap<String, NodeResult>>> {
+        if contexts.len() > 1 && endpoint_infos.iter().any(has_side_effects) {
+            let mut results = Vec::with_capacity(contexts.len());
 
+            for context in contexts {
+                let context = std::slice::from_ref(context);
+
+                #[cfg(test)]
+                let context_results = self
+                    .execute_level(mock_exec_service, endpoint_infos, context)
+                    .await?;
                 #[cfg(not(test))]
-                {
-                    exec = exec.bind(val);
+                let context_results = self
+                    .execute_level(transaction, endpoint_infos, context)
+                    .await?;
+
+                results.extend(context_results);
+            }
+
+            return Ok(results);
+        }
+
+        let mut final_results = contexts
+            .iter()
+            .map(|_| HashMap::<String, NodeResult>::new())
+            .collect::<Vec<_>>();
+
+        for query in endpoint_infos {
+            match &query.kind {
+                NodeKind::Query => {}
+                NodeKind::Guard { status, message } => {
+                    for context in contexts {
+                        #[cfg(test)]
+                        let passed = self.evaluate(mock_exec_service, query, context).await?;
+                        #[cfg(not(test))]
+                        let passed = self.evaluate(transaction, query, context).await?;
+
+                        if !passed {
+                            let status =
+                                StatusCode::from_u16(*status).unwrap_or(StatusCode::FORBIDDEN);
+                            return Err(status_error(status, message));
+                        }
+                    }
+                    continue;
                 }
-                #[cfg(test)]
-                {
-                    mock_exec_service.bind(&val);
+                NodeKind::Condition => {
+                    let mut passed_indices = Vec::new();
+
+                    for (index, context) in contexts.iter().enumerate() {
+                        #[cfg(test)]
+                        let passed = self.evaluate(mock_exec_service, query, context).await?;
+                        #[cfg(not(test))]
+                        let passed = self.evaluate(transaction, query, context).await?;
+
+                        if passed {
+                            passed_indices.push(index);
+                        }
+                    }
+
+                    let passed_contexts = passed_indices
+                        .iter()
+                        .map(|it| contexts[*it].clone())
+                        .collect::<Vec<_>>();
+
+                    // children run in the context of the condition
+                    // and their results are merged into its level
+                    #[cfg(test)]
+                    let branch_results = self
+                        .execute_level(mock_exec_service, &query.children, &passed_contexts)
+                        .await?;
+                    #[cfg(not(test))]
+                    let branch_results = self
+                        .execute_level(transaction, &query.children, &passed_contexts)
+                        .await?;
+
+                    for (index, results) in passed_indices.into_iter().zip(branch_results) {
+                        final_results[index].extend(results);
+                    }
+                    continue;
                 }
             }
 
-            #[cfg(test)]
-            let results = mock_exec_service.simulate_call(&query.parsed_sql);
+            let mut rows_per_context = Vec::with_capacity(contexts.len());
+            let mut cursors_per_context = Vec::with_capacity(contexts.len());
+            let mut affected_per_context = Vec::with_capacity(contexts.len());
 
-            #[cfg(not(test))]
-            let results = exec
-                .fetch_all(&mut *transaction)
-                .await?
-                .into_iter()
-                .map(|it| it.into_map())
+            match self.batch_params(query, contexts)? {
+                Some(params) => {
+                    let sql = batched_sql(&query.parsed_sql, params.len(), self.max_rows)?;
+
+                    #[cfg(test)]
+                    let rows = mock_exec_service.fetch(&sql, params);
+                    #[cfg(not(test))]
+                    let rows = fetch(transaction, &sql, params).await?;
+
+                    rows_per_context.resize_with(contexts.len(), Vec::new);
+
+                    for mut row in rows {
+                        let parent_rows = row
+                            .remove("__b_index")
+                            .and_then(|it| it.as_u64())
+                            .and_then(|it| rows_per_context.get_mut(it as usize - 1))
+                            .ok_or(anyhow!("Bad row index in batched query"))?;
+                        parent_rows.push(Arc::new(row));
+                    }
+
+                    for rows in &rows_per_context {
+                        self.count_rows(query, rows.len())?;
+                    }
+                }
+                None => {
+                    for context in contexts {
+                        self.execution_maps = context.clone();
+                        let mut params = self.query_params(query)?;
+
+                        if query.cardinality == Cardinality::AffectedRows {
+                            #[cfg(test)]
+                            let affected_rows =
+                                mock_exec_service.execute(&query.parsed_sql, params);
+                            #[cfg(not(test))]
+                            let affected_rows =
+                                execute_statement(transaction, &query.parsed_sql, params).await?;
+
+                            rows_per_context.push(vec![]);
+                            affected_per_context.push(affected_rows);
+                            continue;
+                        }
+
+                        let (sql, limit) = match &query.pagination {
+                            Some(pagination) => {
+                                let (sql, limit) = self.paginate(query, pagination, &mut params)?;
+                                (sql, Some(limit))
+                            }
+                            None => (query.parsed_sql.clone(), None),
+                        };
+                        let sql = limited_sql(&sql, self.max_rows)?;
+
+                        #[cfg(test)]
+                        let mut rows = mock_exec_service.fetch(&sql, params);
+                        #[cfg(not(test))]
+                        let mut rows = fetch(transaction, &sql, params).await?;
+
+                        let next_cursor = match (limit, &query.pagination) {
+                            (Some(limit), Some(pagination)) if rows.len() > limit => {
+                                rows.truncate(limit);
+                                let last = rows.last().and_then(|it| it.get(&pagination.order_by));
+                                let value = last.ok_or_else(|| {
+                                    anyhow!(
+                                        "Order column {} is not returned by the query",
+                                        pagination.order_by
+                                    )
+                                })?;
+                                Some(encode_cursor(value))
+                            }
+                            _ => None,
+                        };
+
+                        self.count_rows(query, rows.len())?;
+                        rows_per_context.push(rows.into_iter().map(Arc::new).collect());
+                        cursors_per_context.push(next_cursor);
+                    }
+                }
+            }
+
+            let child_contexts = contexts
+                .iter()
+                .zip(&rows_per_context)
+                .flat_map(|(context, rows)| {
+                    rows.iter().map(move |row| {
+                        let mut child_context = context.clone();
+                        child_context.push(row.clone());
+                        child_context
+                    })
+                })
                 .collect::<Vec<_>>();
 
-            for result in results.into_iter() {
-                self.push_execution_map(result);
+            #[cfg(test)]
+            let children_results = self
+                .execute_level(mock_exec_service, &query.children, &child_contexts)
+                .await?;
+            #[cfg(not(test))]
+            let children_results = self
+                .execute_level(transaction, &query.children, &child_contexts)
+                .await?;
 
-                #[cfg(test)]
-                let children_results = self
-                    .execute_impl(mock_exec_service, &query.children)
-                    .await?;
-                #[cfg(not(test))]
-                let children_results = self.execute_impl(transaction, &query.children).await?;
-
-                let mut result_map = self
-                    .pop_execution_map()
-                    .ok_or(anyhow!("Could not pop execution map"))?;
-
-                // delete private fields
-                result_map.retain(|key, _value| key.len() < 8 || &key[0..8] != "private_");
-
-                if final_results.contains_key(&query.name) {
-                    final_results
-                        .get_mut(&query.name)
-                        .unwrap()
-                        .push(ExecutionResult {
-                            data: result_map,
-                            children: children_results,
-                        })
-                } else {
-                    final_results.insert(
-                        query.name.clone(),
-                        vec![ExecutionResult {
-                            data: result_map,
-                            children: children_results,
-                        }],
-                    );
+            drop(child_contexts);
+            let mut children_results = children_results.into_iter();
+
+            let mut cursors_per_context = cursors_per_context.into_iter();
+            let mut affected_per_context = affected_per_context.into_iter();
+
+            for (results, rows) in final_results.iter_mut().zip(rows_per_context) {
+                let mut node_rows = Vec::with_capacity(rows.len());
+
+                for row in rows {
+                    let mut result_map = Arc::try_unwrap(row).unwrap_or_else(|it| (*it).clone());
+
+                    // delete private fields
+                    result_map.retain(|key, _value| !key.starts_with("private_"));
+                    self.count_bytes(&result_map)?;
+
+                    let children = children_results
+                        .next()
+                        .ok_or(anyhow!("Missing results of children"))?;
+
+                    node_rows.push(ExecutionResult {
+                        data: result_map,
+                        children,
+                        flattened: query.flatten,
+                    });
+                }
+
+                let node_result = match query.pagination {
+                    // a page is returned even when it's empty
+                    Some(_) => Some(NodeResult::Page {
+                        rows: node_rows,
+                        next_cursor: cursors_per_context.next().flatten(),
+                    }),
+                    None if query.cardinality == Cardinality::AffectedRows => {
+                        shape_rows(query, node_rows, affected_per_context.next())?
+                    }
+                    None => shape_rows(query, node_rows, None)?,
+                };
+
+                if let Some(node_result) = node_result {
+                    results.insert(query.name.clone(), node_result);
                 }
             }
         }
@@ -161,15 +582,168 @@ impl EndpointExecutionRuntime {
     }
 }
 
+fn is_select(query: &EndpointInfo) -> bool {
+    query
+        .parsed_sql
+        .trim_start()
+        .get(0..6)
+        .is_some_and(|it| it.eq_ignore_ascii_case("select"))
+}
+
+/// Whether the query or some of its descendants may change data
+fn has_side_effects(query: &EndpointInfo) -> bool {
+    !is_select(query) || query.children.iter().any(has_side_effects)
+}
+
+/// Converts the rows of a query to the result its cardinality declares,
+/// `None` when a query returning many rows found nothing.
+fn shape_rows(
+    query: &EndpointInfo,
+    mut rows: Vec<ExecutionResult>,
+    affected_rows: Option<u64>,
+) -> Result<Option<NodeResult>> {
+    if query.cardinality != Cardinality::Many && rows.len() > 1 {
+        return Err(anyhow!(
+            "Query {} returned {} rows, expected at most one",
+            query.name,
+            rows.len()
+        ));
+    }
+
+    let result = match query.cardinality {
+        Cardinality::Many if rows.is_empty() => None,
+        Cardinality::Many => Some(NodeResult::Rows(rows)),
+        Cardinality::One if rows.is_empty() => {
+            return Err(status_error(
+                StatusCode::NOT_FOUND,
+                format!("{} not found", query.name),
+            ))
+        }
+        Cardinality::One | Cardinality::OptionalOne => Some(NodeResult::One(rows.pop())),
+        Cardinality::Scalar => {
+            let value = match rows.pop() {
+                None => Value::Null,
+                Some(row) if row.data.len() == 1 => {
+                    row.data.into_values().next().unwrap_or_default()
+                }
+                Some(_) => {
+                    return Err(anyhow!(
+                        "Scalar query {} must return exactly one column",
+                        query.name
+                    ))
+                }
+            };
+            Some(NodeResult::Scalar(value))
+        }
+        Cardinality::AffectedRows => {
+            Some(NodeResult::Scalar(affected_rows.unwrap_or_default().into()))
+        }
+    };
+
+    Ok(result)
+}
+
+/// Fetches one row more than `max_rows`, which is enough to find out
+/// that a query returns too many, instead of all of them
+fn limited_sql(sql: &str, max_rows: Option<usize>) -> Result<String> {
+    let max_rows = match max_rows {
+        Some(max_rows) => max_rows,
+        None => return Ok(sql.to_owned()),
+    };
+    let trimmed = sql.trim_end().trim_end_matches(';');
+
+    let limited = match statement_kind(sql)? {
+        StatementKind::Query => format!(
+            "SELECT * FROM (\n{}\n) AS __b_limited LIMIT {}",
+            trimmed,
+            max_rows + 1
+        ),
+        // Modifications in WITH run to completion, only the returned rows are limited
+        StatementKind::Modification => format!(
+            "WITH __b_limited AS (\n{}\n) SELECT * FROM __b_limited LIMIT {}",
+            trimmed,
+            max_rows + 1
+        ),
+        // Rows of other statements are counted after fetching all of them
+        StatementKind::Other => sql.to_owned(),
+    };
+
+    Ok(limited)
+}
+
+/// Runs the query once for every element of the parameter arrays.
+///
+/// The query is joined laterally, rather than rewritten to `= ANY($1)`,
+/// so that its LIMIT, like `max_rows`, still applies to each parent row separately.
+/// Rows of a lateral join have no guaranteed order, sorted queries aren't batched.
+fn batched_sql(parsed_sql: &str, param_count: usize, max_rows: Option<usize>) -> Result<String> {
+    let inner = replace_parameters(parsed_sql, |n| format!("__b_batch.__b_v{}", n))?;
+    let inner = limited_sql(inner.trim_end().trim_end_matches(';'), max_rows)?;
+
+    let params = (1..=param_count)
+        .map(|n| format!("${}", n))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let columns = (1..=param_count)
+        .map(|n| format!("__b_v{}", n))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    Ok(format!(
+        "SELECT __b_batch.__b_index, __b_child.* \
+        FROM unnest({params}) WITH ORDINALITY AS __b_batch({columns}, __b_index) \
+        CROSS JOIN LATERAL (\n{inner}\n) AS __b_child",
+        params = params,
+        columns = columns,
+        inner = inner,
+    ))
+}
+
+/// Statements aren't cached, the parameter types are taken from the values
+/// of each call and the first call would otherwise fix them for the connection.
+fn dynamic_query(sql: &str, params: Vec<SqlValue>) -> Query<'_, Postgres, PgArguments> {
+    let mut exec = sqlx::query::<Postgres>(sql).persistent(false);
+    for param in params {
+        exec = exec.bind(param);
+    }
+    exec
+}
+
+async fn fetch(
+    transaction: &mut Transaction<'_, Postgres>,
+    sql: &str,
+    params: Vec<SqlValue>,
+) -> Result<Vec<HashMap<String, Value>>> {
+    dynamic_query(sql, params)
+        .fetch_all(&mut *transaction)
+        .await?
+        .iter()
+        .map(|row| Ok(ArbitrarySqlRow::from_row(row)?.into_map()))
+        .collect()
+}
+
+async fn execute_statement(
+    transaction: &mut Transaction<'_, Postgres>,
+    sql: &str,
+    params: Vec<SqlValue>,
+) -> Result<u64> {
+    let result = dynamic_query(sql, params)
+        .execute(&mut *transaction)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
 #[derive(Debug, PartialEq)]
 pub struct ExecutionMockService {
-    pub bound_params: Vec<String>,
+    pub bound_params: Vec<SqlValue>,
     pub called_queries: Vec<String>,
-    pub result_stack: Vec<Vec<HashMap<String, String>>>,
+    pub result_stack: Vec<Vec<HashMap<String, Value>>>,
 }
 
+#[cfg(test)]
 impl ExecutionMockService {
-    pub fn new(result_stack: Vec<Vec<HashMap<String, String>>>) -> Self {
+    pub fn new(result_stack: Vec<Vec<HashMap<String, Value>>>) -> Self {
         Self {
             result_stack,
             called_queries: vec![],
@@ -177,20 +751,36 @@ impl ExecutionMockService {
         }
     }
 
-    pub fn bind(&mut self, param: impl std::fmt::Display) {
-        self.bound_params.push(format!("{}", param));
+    pub fn bind(&mut self, param: SqlValue) {
+        self.bound_params.push(param);
     }
 
-    pub fn simulate_call(&mut self, query: &str) -> Vec<HashMap<String, String>> {
+    pub fn simulate_call(&mut self, query: &str) -> Vec<HashMap<String, Value>> {
         self.called_queries.push(query.to_owned());
         self.result_stack.pop().unwrap()
     }
+
+    pub fn fetch(&mut self, query: &str, params: Vec<SqlValue>) -> Vec<HashMap<String, Value>> {
+        for param in params {
+            self.bind(param);
+        }
+        self.simulate_call(query)
+    }
+
+    /// Affected rows are the number of rows of the simulated result
+    pub fn execute(&mut self, query: &str, params: Vec<SqlValue>) -> u64 {
+        self.fetch(query, params).len() as u64
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::err_utils::to_status;
+    use crate::test_database::TestDatabase;
+    use crate::types::sql_value::SqlType;
     use maplit::hashmap;
+    use serde_json::json;
 
     #[tokio::test]
     async fn it_works() {
@@ -198,13 +788,18 @@ mod test {
            "test".into() => "test".into()
         }]]);
 
-        let request_variables = hashmap! {};
+        let request_variables = json!({});
 
         let endpoint_infos = vec![EndpointInfo {
             name: "test".into(),
             variables: vec![],
             parsed_sql: "this sql should be executed".into(),
             original_sql: "".into(),
+            kind: NodeKind::Query,
+            pagination: None,
+            cardinality: Cardinality::Many,
+            flatten: false,
+            variable_types: vec![],
             children: vec![],
         }];
 
@@ -217,19 +812,20 @@ mod test {
 
         assert_eq!(
             final_result,
-            hashmap! {"test".into() => vec![ExecutionResult{
+            hashmap! {"test".into() => NodeResult::Rows(vec![ExecutionResult{
+                flattened: false,
                 data: hashmap! {
                     "test".into() => "test".into(),
                 },
                 children: hashmap! {}
-            }]}
+            }])}
         );
 
         assert_eq!(
             mock_service.called_queries,
             vec!["this sql should be executed"]
         );
-        assert_eq!(mock_service.bound_params, Vec::<&str>::new());
+        assert_eq!(mock_service.bound_params, vec![]);
     }
 
     #[tokio::test]
@@ -238,13 +834,18 @@ mod test {
            "test".into() => "test".into()
         }]]);
 
-        let request_variables = hashmap! {};
+        let request_variables = json!({});
 
         let endpoint_infos = vec![EndpointInfo {
             name: "test".into(),
             variables: vec!["req.test_key".into()],
             parsed_sql: "".into(),
             original_sql: "".into(),
+            kind: NodeKind::Query,
+            pagination: None,
+            cardinality: Cardinality::Many,
+            flatten: false,
+            variable_types: vec![],
             children: vec![],
         }];
 
@@ -264,13 +865,18 @@ mod test {
            "test".into() => "test".into()
         }]]);
 
-        let request_variables = hashmap! {};
+        let request_variables = json!({});
 
         let endpoint_infos = vec![EndpointInfo {
             name: "test".into(),
             variables: vec!["super.test_key".into()],
             parsed_sql: "should not be executed".into(),
             original_sql: "".into(),
+            kind: NodeKind::Query,
+            pagination: None,
+            cardinality: Cardinality::Many,
+            flatten: false,
+            variable_types: vec![],
             children: vec![],
         }];
 
@@ -296,19 +902,29 @@ mod test {
            "test".into() => "test".into()
         }]]);
 
-        let request_variables = hashmap! {};
+        let request_variables = json!({});
 
         let endpoint_infos = vec![EndpointInfo {
             name: "test".into(),
             variables: vec![],
             parsed_sql: "Should be executed".into(),
             original_sql: "".into(),
+            kind: NodeKind::Query,
+            pagination: None,
+            cardinality: Cardinality::Many,
+            flatten: false,
+            variable_types: vec![],
 
             children: vec![EndpointInfo {
                 name: "test_inner".into(),
                 variables: vec!["super.key_that_doesnt_exist".into()],
                 parsed_sql: "Should not be executed".into(),
                 original_sql: "".into(),
+                kind: NodeKind::Query,
+                pagination: None,
+                cardinality: Cardinality::Many,
+                flatten: false,
+                variable_types: vec![],
                 children: vec![],
             }],
         }];
@@ -332,21 +948,67 @@ mod test {
         assert_eq!(mock_service.called_queries, vec!["Should be executed"]);
     }
 
+    #[tokio::test]
+    async fn multibyte_variable_names() {
+        let mut mock_service = ExecutionMockService::new(vec![
+            vec![hashmap! {"inner".into() => 2.into()}],
+            vec![hashmap! {"ażółćx".into() => 1.into()}],
+        ]);
+
+        let endpoint_infos = vec![EndpointInfo {
+            name: "test".into(),
+            parsed_sql: "SELECT 1 AS \"ażółćx\"".into(),
+            children: vec![EndpointInfo {
+                name: "test_inner".into(),
+                variables: vec!["super.ażółćx".into()],
+                parsed_sql: "SELECT $1 AS inner".into(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }];
+
+        EndpointExecutionRuntime::new(json!({}))
+            .execute_impl(&mut mock_service, &endpoint_infos)
+            .await
+            .unwrap();
+        assert_eq!(mock_service.bound_params, vec![SqlValue::BigInt(1)]);
+
+        let endpoint_infos = vec![EndpointInfo {
+            name: "test".into(),
+            variables: vec!["abcż".into()],
+            parsed_sql: "SELECT $1".into(),
+            ..Default::default()
+        }];
+
+        let error = EndpointExecutionRuntime::new(json!({}))
+            .execute_impl(&mut ExecutionMockService::new(vec![]), &endpoint_infos)
+            .await
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Bad variable name (abcż). Should begin with"));
+    }
+
     #[tokio::test]
     async fn request_variables_work() {
         let mut mock_service = ExecutionMockService::new(vec![vec![hashmap! {
            "test".into() => "test".into()
         }]]);
 
-        let request_variables = hashmap! {
-            "age".to_owned() => "41".to_owned()
-        };
+        let request_variables = json!({
+            "age": "41"
+        });
 
         let endpoint_infos = vec![EndpointInfo {
             name: "test".into(),
             variables: vec!["req.age".into()],
             parsed_sql: "select $1".into(),
             original_sql: "".into(),
+            kind: NodeKind::Query,
+            pagination: None,
+            cardinality: Cardinality::Many,
+            flatten: false,
+            variable_types: vec![],
             children: vec![],
         }];
 
@@ -359,16 +1021,20 @@ mod test {
 
         assert_eq!(
             final_result,
-            hashmap! {"test".into() => vec![ExecutionResult{
+            hashmap! {"test".into() => NodeResult::Rows(vec![ExecutionResult{
+                flattened: false,
                 data: hashmap! {
                     "test".into() => "test".into(),
                 },
                 children: hashmap! {}
-            }]}
+            }])}
         );
 
         assert_eq!(mock_service.called_queries, vec!["select $1".to_owned()]);
-        assert_eq!(mock_service.bound_params, vec!["41".to_owned()]);
+        assert_eq!(
+            mock_service.bound_params,
+            vec![SqlValue::Text("41".to_owned())]
+        );
     }
 
     #[tokio::test]
@@ -390,19 +1056,29 @@ mod test {
             ],
         ]);
 
-        let request_variables = hashmap! {};
+        let request_variables = json!({});
 
         let endpoint_infos = vec![EndpointInfo {
             name: "test".into(),
             variables: vec![],
             parsed_sql: "outer sql".into(),
             original_sql: "".into(),
+            kind: NodeKind::Query,
+            pagination: None,
+            cardinality: Cardinality::Many,
+            flatten: false,
+            variable_types: vec![],
 
             children: vec![EndpointInfo {
                 name: "test_inner".into(),
                 variables: vec!["super.test".into()],
                 parsed_sql: "inner sql".into(),
                 original_sql: "".into(),
+                kind: NodeKind::Query,
+                pagination: None,
+                cardinality: Cardinality::Many,
+                flatten: false,
+                variable_types: vec![],
                 children: vec![],
             }],
         }];
@@ -416,30 +1092,1052 @@ mod test {
 
         assert_eq!(
             final_result,
-            hashmap! {"test".into() => vec![
+            hashmap! {"test".into() => NodeResult::Rows(vec![
                 ExecutionResult{
+                    flattened: false,
                     data: hashmap! {"test".into() => "test 1".into()},
                     children: hashmap! {
-                        "test_inner".into() => vec![
+                        "test_inner".into() => NodeResult::Rows(vec![
                             ExecutionResult {
+                                flattened: false,
                                 data: hashmap! {"inner_test".into() => "child of test 1".into()},
                                 children: hashmap! {},
                             }
-                        ]
+                        ])
                     }
                 },
                 ExecutionResult {
+                    flattened: false,
                     data: hashmap! {"test".into() => "test 2".into()},
                     children: hashmap! {
-                        "test_inner".into() => vec![
+                        "test_inner".into() => NodeResult::Rows(vec![
                             ExecutionResult {
+                                flattened: false,
                                 data: hashmap! {"inner_test".into() => "child of test 2".into()},
                                 children: hashmap! {},
                             }
-                        ]
+                        ])
                     }
                 }
-            ]}
+            ])}
+        );
+    }
+
+    #[tokio::test]
+    async fn typed_super_variables_keep_their_type() {
+        let mut mock_service = ExecutionMockService::new(vec![
+            vec![hashmap! {
+                "title".into() => "first post".into()
+            }],
+            vec![hashmap! {
+                "private_id".into() => 41.into(),
+                "is_admin".into() => true.into(),
+            }],
+        ]);
+
+        let endpoint_infos = vec![EndpointInfo {
+            name: "user".into(),
+            variables: vec![],
+            parsed_sql: "outer sql".into(),
+            original_sql: "".into(),
+            kind: NodeKind::Query,
+            pagination: None,
+            cardinality: Cardinality::Many,
+            flatten: false,
+            variable_types: vec![],
+
+            children: vec![EndpointInfo {
+                name: "posts".into(),
+                variables: vec!["super.private_id".into()],
+                parsed_sql: "inner sql".into(),
+                original_sql: "".into(),
+                kind: NodeKind::Query,
+                pagination: None,
+                cardinality: Cardinality::Many,
+                flatten: false,
+                variable_types: vec![],
+                children: vec![],
+            }],
+        }];
+
+        let mut execution_runtime = EndpointExecutionRuntime::new(json!({}));
+
+        let final_result = execution_runtime
+            .execute_impl(&mut mock_service, &endpoint_infos)
+            .await
+            .unwrap();
+
+        assert_eq!(mock_service.bound_params, vec![SqlValue::BigInt(41)]);
+        assert_eq!(
+            final_result,
+            hashmap! {"user".into() => NodeResult::Rows(vec![ExecutionResult {
+                flattened: false,
+                data: hashmap! {"is_admin".into() => true.into()},
+                children: hashmap! {
+                    "posts".into() => NodeResult::Rows(vec![ExecutionResult {
+                        flattened: false,
+                        data: hashmap! {"title".into() => "first post".into()},
+                        children: hashmap! {},
+                    }])
+                },
+            }])}
+        );
+    }
+
+    #[tokio::test]
+    async fn declared_types_are_converted() {
+        let mut mock_service = ExecutionMockService::new(vec![vec![]]);
+
+        let request_variables = json!({
+            "id": "41",
+            "tags": r#"["a", "b"]"#,
+        });
+
+        let endpoint_infos = vec![EndpointInfo {
+            name: "test".into(),
+            variables: vec!["req.id".into(), "req.tags".into()],
+            variable_types: vec![
+                Some(SqlType::Int),
+                Some(SqlType::Array(Box::new(SqlType::Text))),
+            ],
+            parsed_sql: "select $1, $2".into(),
+            original_sql: "".into(),
+            kind: NodeKind::Query,
+            pagination: None,
+            cardinality: Cardinality::Many,
+            flatten: false,
+            children: vec![],
+        }];
+
+        let mut execution_runtime = EndpointExecutionRuntime::new(request_variables);
+
+        execution_runtime
+            .execute_impl(&mut mock_service, &endpoint_infos)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            mock_service.bound_params,
+            vec![
+                SqlValue::Int(41),
+                SqlValue::Array(
+                    SqlType::Text,
+                    vec![SqlValue::Text("a".into()), SqlValue::Text("b".into())]
+                )
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn bad_request_when_value_cant_be_converted() {
+        let mut mock_service = ExecutionMockService::new(vec![vec![]]);
+
+        let request_variables = json!({
+            "id": "forty one",
+        });
+
+        let endpoint_infos = vec![EndpointInfo {
+            name: "test".into(),
+            variables: vec!["req.id".into()],
+            variable_types: vec![Some(SqlType::Int)],
+            parsed_sql: "should not be executed".into(),
+            original_sql: "".into(),
+            kind: NodeKind::Query,
+            pagination: None,
+            cardinality: Cardinality::Many,
+            flatten: false,
+            children: vec![],
+        }];
+
+        let mut execution_runtime = EndpointExecutionRuntime::new(request_variables);
+
+        let error = execution_runtime
+            .execute_impl(&mut mock_service, &endpoint_infos)
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            to_status(error),
+            (
+                StatusCode::BAD_REQUEST,
+                r#"Bad value of variable req.id: Expected int, got "forty one""#.to_owned()
+            )
+        );
+        assert_eq!(mock_service.called_queries, Vec::<&str>::new());
+    }
+
+    #[tokio::test]
+    async fn nested_request_variables_work() {
+        let mut mock_service = ExecutionMockService::new(vec![vec![]]);
+
+        let request_variables = json!({
+            "age": 41,
+            "user": {"id": 3, "admin": true},
+            "items": [{"sku": "A-1"}],
+        });
+
+        let endpoint_infos = vec![EndpointInfo {
+            name: "test".into(),
+            variables: vec![
+                "req.age".into(),
+                "req.user.id".into(),
+                "req.user.admin".into(),
+                "req.items[0].sku".into(),
+            ],
+            parsed_sql: "select $1, $2, $3, $4".into(),
+            ..Default::default()
+        }];
+
+        let mut execution_runtime = EndpointExecutionRuntime::new(request_variables);
+
+        execution_runtime
+            .execute_impl(&mut mock_service, &endpoint_infos)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            mock_service.bound_params,
+            vec![
+                SqlValue::BigInt(41),
+                SqlValue::BigInt(3),
+                SqlValue::Bool(true),
+                SqlValue::Text("A-1".into()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn error_when_cant_find_nested_req_variable() {
+        let mut mock_service = ExecutionMockService::new(vec![vec![]]);
+
+        let endpoint_infos = vec![EndpointInfo {
+            name: "test".into(),
+            variables: vec!["req.items[1].sku".into()],
+            parsed_sql: "should not be executed".into(),
+            ..Default::default()
+        }];
+
+        let mut execution_runtime =
+            EndpointExecutionRuntime::new(json!({"items": [{"sku": "A-1"}]}));
+
+        let error = execution_runtime
+            .execute_impl(&mut mock_service, &endpoint_infos)
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.to_string(), "Request key items[1].sku not found");
+    }
+
+    #[tokio::test]
+    async fn path_variables_work() {
+        let mut mock_service = ExecutionMockService::new(vec![vec![]]);
+
+        let endpoint_infos = vec![EndpointInfo {
+            name: "test".into(),
+            variables: vec!["path.id".into(), "path.slug".into()],
+            variable_types: vec![Some(SqlType::Int), None],
+            parsed_sql: "select $1, $2".into(),
+            ..Default::default()
+        }];
+
+        let mut execution_runtime =
+            EndpointExecutionRuntime::new(json!({})).with_path_params(hashmap! {
+                "id".into() => "7".into(),
+                "slug".into() => "hello".into(),
+            });
+
+        execution_runtime
+            .execute_impl(&mut mock_service, &endpoint_infos)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            mock_service.bound_params,
+            vec![SqlValue::Int(7), SqlValue::Text("hello".into())]
+        );
+    }
+
+    #[tokio::test]
+    async fn error_when_cant_find_path_variable() {
+        let mut mock_service = ExecutionMockService::new(vec![vec![]]);
+
+        let endpoint_infos = vec![EndpointInfo {
+            name: "test".into(),
+            variables: vec!["path.id".into()],
+            parsed_sql: "should not be executed".into(),
+            ..Default::default()
+        }];
+
+        let mut execution_runtime = EndpointExecutionRuntime::new(json!({}));
+
+        let error = execution_runtime
+            .execute_impl(&mut mock_service, &endpoint_infos)
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.to_string(), "Path parameter id not found");
+    }
+
+    #[tokio::test]
+    async fn user_variables_work() {
+        let mut mock_service = ExecutionMockService::new(vec![vec![]]);
+
+        let endpoint_infos = vec![EndpointInfo {
+            name: "orders".into(),
+            variables: vec!["user.id".into(), "user.username".into()],
+            variable_types: vec![None, None],
+            parsed_sql: "select * from orders where user_fk = $1 or owner = $2".into(),
+            ..Default::default()
+        }];
+
+        let mut execution_runtime = EndpointExecutionRuntime::new(json!({}))
+            .with_user(Some(json!({"id": 4, "username": "ann", "group": "USER"})));
+
+        execution_runtime
+            .execute_impl(&mut mock_service, &endpoint_infos)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            mock_service.bound_params,
+            vec![SqlValue::BigInt(4), SqlValue::Text("ann".into())]
+        );
+
+        let error = EndpointExecutionRuntime::new(json!({}))
+            .execute_impl(&mut mock_service, &endpoint_infos)
+            .await
+            .unwrap_err();
+
+        assert_eq!(to_status(error).0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn limits_are_enforced() {
+        let rows = || {
+            vec![
+                hashmap! {"name".into() => "first".into(), "private_id".into() => 1.into()},
+                hashmap! {"name".into() => "second".into(), "private_id".into() => 2.into()},
+            ]
+        };
+        let endpoint_infos = vec![EndpointInfo {
+            name: "users".into(),
+            parsed_sql: "select name from users".into(),
+            ..Default::default()
+        }];
+
+        let execute = |limits: ExecutionLimits| {
+            let endpoint_infos = &endpoint_infos;
+            let mut mock_service = ExecutionMockService::new(vec![rows()]);
+            async move {
+                let result = EndpointExecutionRuntime::new(json!({}))
+                    .with_limits(&limits)
+                    .execute_impl(&mut mock_service, endpoint_infos)
+                    .await;
+
+                // the query returns one row more than max_rows at most
+                if let Some(max_rows) = limits.max_rows {
+                    let limit = format!("LIMIT {}", max_rows + 1);
+                    assert!(mock_service.called_queries[0].ends_with(&limit));
+                }
+
+                result
+            }
+        };
+
+        let error = execute(ExecutionLimits {
+            max_rows: Some(1),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(
+            to_status(error),
+            (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "Query users returned more than 1 rows".into()
+            )
+        );
+
+        // {"name":"first"} and {"name":"second"}, private columns aren't in the response
+        let fitting = ExecutionLimits {
+            max_rows: Some(2),
+            max_response_bytes: Some(33),
+            ..Default::default()
+        };
+        assert!(execute(fitting.clone()).await.is_ok());
+
+        let error = execute(ExecutionLimits {
+            max_response_bytes: Some(32),
+            ..fitting
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(to_status(error).0, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test]
+    fn batched_sql_joins_query_laterally() {
+        assert_eq!(
+            batched_sql(
+                "select * from posts where user_fk = $1 and kind = $2 limit 3;",
+                2,
+                None
+            )
+            .unwrap(),
+            "SELECT __b_batch.__b_index, __b_child.* \
+            FROM unnest($1, $2) WITH ORDINALITY AS __b_batch(__b_v1, __b_v2, __b_index) \
+            CROSS JOIN LATERAL (\n\
+            select * from posts where user_fk = __b_batch.__b_v1 and kind = __b_batch.__b_v2 limit 3\n\
+            ) AS __b_child"
+        );
+
+        // max_rows applies to each parent row
+        assert_eq!(
+            batched_sql("select * from posts where user_fk = $1", 1, Some(10)).unwrap(),
+            "SELECT __b_batch.__b_index, __b_child.* \
+            FROM unnest($1) WITH ORDINALITY AS __b_batch(__b_v1, __b_index) \
+            CROSS JOIN LATERAL (\n\
+            SELECT * FROM (\n\
+            select * from posts where user_fk = __b_batch.__b_v1\n\
+            ) AS __b_limited LIMIT 11\n\
+            ) AS __b_child"
+        );
+    }
+
+    #[test]
+    fn limited_sql_fetches_one_row_more() {
+        assert_eq!(
+            limited_sql("select * from posts;", Some(5)).unwrap(),
+            "SELECT * FROM (\nselect * from posts\n) AS __b_limited LIMIT 6"
+        );
+        assert_eq!(
+            limited_sql("delete from posts returning id", Some(5)).unwrap(),
+            "WITH __b_limited AS (\ndelete from posts returning id\n) \
+            SELECT * FROM __b_limited LIMIT 6"
+        );
+
+        let nested = "with t as (delete from posts returning id) select * from t";
+        assert_eq!(limited_sql(nested, Some(5)).unwrap(), nested);
+        assert_eq!(limited_sql("select 1", None).unwrap(), "select 1");
+    }
+
+    #[tokio::test]
+    async fn children_are_batched() {
+        let mut mock_service = ExecutionMockService::new(vec![
+            vec![hashmap! {
+                "__b_index".into() => 3.into(),
+                "body".into() => "nice".into(),
+            }],
+            vec![
+                hashmap! {
+                    "__b_index".into() => 1.into(),
+                    "private_id".into() => 10.into(),
+                    "title".into() => "a".into(),
+                },
+                hashmap! {
+                    "__b_index".into() => 1.into(),
+                    "private_id".into() => 11.into(),
+                    "title".into() => "b".into(),
+                },
+                hashmap! {
+                    "__b_index".into() => 2.into(),
+                    "private_id".into() => 20.into(),
+                    "title".into() => "c".into(),
+                },
+            ],
+            vec![
+                hashmap! {"private_id".into() => 1.into(), "name".into() => "ann".into()},
+                hashmap! {"private_id".into() => 2.into(), "name".into() => "bob".into()},
+                hashmap! {"private_id".into() => 3.into(), "name".into() => "cid".into()},
+            ],
+        ]);
+
+        let comments_sql = "select body from comments where post_fk = $1 and author = $2";
+        let posts_sql = "select id as private_id, title from posts where user_fk = $1";
+
+        let endpoint_infos = vec![EndpointInfo {
+            name: "users".into(),
+            parsed_sql: "select id as private_id, name from users".into(),
+            children: vec![EndpointInfo {
+                name: "posts".into(),
+                variables: vec!["super.private_id".into()],
+                parsed_sql: posts_sql.into(),
+                children: vec![EndpointInfo {
+                    name: "comments".into(),
+                    variables: vec!["super.private_id".into(), "super.super.name".into()],
+                    parsed_sql: comments_sql.into(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }];
+
+        let mut execution_runtime = EndpointExecutionRuntime::new(json!({}));
+
+        let final_result = execution_runtime
+            .execute_impl(&mut mock_service, &endpoint_infos)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            mock_service.called_queries,
+            vec![
+                "select id as private_id, name from users".to_owned(),
+                batched_sql(posts_sql, 1, None).unwrap(),
+                batched_sql(comments_sql, 2, None).unwrap(),
+            ]
+        );
+
+        let ints = |values: &[i64]| {
+            SqlValue::Array(
+                SqlType::BigInt,
+                values.iter().map(|it| SqlValue::BigInt(*it)).collect(),
+            )
+        };
+        let texts = |values: &[&str]| {
+            SqlValue::Array(
+                SqlType::Text,
+                values
+                    .iter()
+                    .map(|it| SqlValue::Text(it.to_string()))
+                    .collect(),
+            )
+        };
+        assert_eq!(
+            mock_service.bound_params,
+            vec![
+                ints(&[1, 2, 3]),
+                ints(&[10, 11, 20]),
+                texts(&["ann", "ann", "bob"]),
+            ]
+        );
+
+        let post = |title: &str, comments: Vec<ExecutionResult>| ExecutionResult {
+            flattened: false,
+            data: hashmap! {"title".into() => title.into()},
+            children: if comments.is_empty() {
+                hashmap! {}
+            } else {
+                hashmap! {"comments".into() => NodeResult::Rows(comments)}
+            },
+        };
+
+        assert_eq!(
+            final_result,
+            hashmap! {"users".into() => NodeResult::Rows(vec![
+                ExecutionResult {
+                    flattened: false,
+                    data: hashmap! {"name".into() => "ann".into()},
+                    children: hashmap! {"posts".into() => NodeResult::Rows(vec![post("a", vec![]), post("b", vec![])])},
+                },
+                ExecutionResult {
+                    flattened: false,
+                    data: hashmap! {"name".into() => "bob".into()},
+                    children: hashmap! {"posts".into() => NodeResult::Rows(vec![post("c", vec![ExecutionResult {
+                        flattened: false,
+                        data: hashmap! {"body".into() => "nice".into()},
+                        children: hashmap! {},
+                    }])])},
+                },
+                ExecutionResult {
+                    flattened: false,
+                    data: hashmap! {"name".into() => "cid".into()},
+                    children: hashmap! {},
+                },
+            ])}
+        );
+    }
+
+    #[tokio::test]
+    async fn unbatchable_children_run_per_row() {
+        let mut mock_service = ExecutionMockService::new(vec![
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![
+                hashmap! {"private_id".into() => 1.into()},
+                hashmap! {"private_id".into() => "x".into()},
+            ],
+        ]);
+
+        let endpoint_infos = vec![EndpointInfo {
+            name: "users".into(),
+            parsed_sql: "select id as private_id from users".into(),
+            children: vec![
+                // values of different types
+                EndpointInfo {
+                    name: "posts".into(),
+                    variables: vec!["super.private_id".into()],
+                    parsed_sql: "select * from posts where user_fk = $1".into(),
+                    ..Default::default()
+                },
+                // not a select
+                EndpointInfo {
+                    name: "visits".into(),
+                    variables: vec!["super.private_id".into()],
+                    variable_types: vec![Some(SqlType::Text)],
+                    parsed_sql: "insert into visits values ($1) returning *".into(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }];
+
+        let mut execution_runtime = EndpointExecutionRuntime::new(json!({}));
+
+        execution_runtime
+            .execute_impl(&mut mock_service, &endpoint_infos)
+            .await
+            .unwrap();
+
+        // the insert makes the children run depth-first, per user
+        assert_eq!(
+            mock_service.called_queries,
+            vec![
+                "select id as private_id from users",
+                "select * from posts where user_fk = $1",
+                "insert into visits values ($1) returning *",
+                "select * from posts where user_fk = $1",
+                "insert into visits values ($1) returning *",
+            ]
+        );
+        assert_eq!(
+            mock_service.bound_params,
+            vec![
+                SqlValue::BigInt(1),
+                SqlValue::Text("1".into()),
+                SqlValue::Text("x".into()),
+                SqlValue::Text("x".into()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn paginated_queries() {
+        let mut mock_service = ExecutionMockService::new(vec![
+            vec![],
+            vec![
+                hashmap! {"id".into() => 8.into()},
+                hashmap! {"id".into() => 9.into()},
+                hashmap! {"id".into() => 10.into()},
+            ],
+        ]);
+
+        let posts_sql = "select id from posts where user_fk = $1";
+        let pagination: Pagination = serde_json::from_value(json!({"order_by": "id"})).unwrap();
+
+        let endpoint_infos = vec![EndpointInfo {
+            name: "posts".into(),
+            variables: vec!["req.user".into()],
+            parsed_sql: posts_sql.into(),
+            pagination: Some(pagination.clone()),
+            ..Default::default()
+        }];
+
+        let mut execution_runtime = EndpointExecutionRuntime::new(json!({
+            "user": 1,
+            "limit": 2,
+            "cursor": encode_cursor(&json!(7)),
+        }));
+
+        let final_result = execution_runtime
+            .execute_impl(&mut mock_service, &endpoint_infos)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            mock_service.called_queries,
+            vec![pagination.paginated_sql(posts_sql, Some(2), 3)]
+        );
+        assert_eq!(
+            mock_service.bound_params,
+            vec![
+                SqlValue::BigInt(1),
+                SqlValue::BigInt(7),
+                SqlValue::BigInt(3)
+            ]
+        );
+        assert_eq!(
+            final_result,
+            hashmap! {
+                "posts".into() => NodeResult::Page {
+                    rows: vec![
+                        ExecutionResult {
+                            flattened: false,
+                            data: hashmap! {"id".into() => 8.into()},
+                            children: hashmap! {},
+                        },
+                        ExecutionResult {
+                            flattened: false,
+                            data: hashmap! {"id".into() => 9.into()},
+                            children: hashmap! {},
+                        },
+                    ],
+                    next_cursor: Some(encode_cursor(&json!(9))),
+                }
+            }
+        );
+
+        let mut execution_runtime = EndpointExecutionRuntime::new(json!({"user": 1}));
+
+        let final_result = execution_runtime
+            .execute_impl(&mut mock_service, &endpoint_infos)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            final_result,
+            hashmap! {
+                "posts".into() => NodeResult::Page {
+                    rows: vec![],
+                    next_cursor: None,
+                }
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn sorted_and_mutating_children_keep_their_order() {
+        let mut mock_service = ExecutionMockService::new(vec![
+            vec![],
+            vec![hashmap! {"id".into() => 21.into()}],
+            vec![],
+            vec![],
+            vec![hashmap! {"id".into() => 11.into()}],
+            vec![],
+            vec![
+                hashmap! {"private_id".into() => 1.into()},
+                hashmap! {"private_id".into() => 2.into()},
+            ],
+        ]);
+
+        let posts_sql = "select title from posts where user_fk = $1 order by created desc";
+        let insert_sql = "insert into orders (user_fk) values ($1) returning id";
+        let items_sql = "insert into items (order_fk) values ($1)";
+
+        let endpoint_infos = vec![EndpointInfo {
+            name: "users".into(),
+            parsed_sql: "select id as private_id from users".into(),
+            children: vec![
+                EndpointInfo {
+                    name: "posts".into(),
+                    variables: vec!["super.private_id".into()],
+                    parsed_sql: posts_sql.into(),
+                    ..Default::default()
+                },
+                EndpointInfo {
+                    name: "orders".into(),
+                    variables: vec!["super.private_id".into()],
+                    parsed_sql: insert_sql.into(),
+                    children: vec![EndpointInfo {
+                        name: "items".into(),
+                        variables: vec!["super.id".into()],
+                        parsed_sql: items_sql.into(),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }];
+
+        let mut execution_runtime = EndpointExecutionRuntime::new(json!({}));
+
+        execution_runtime
+            .execute_impl(&mut mock_service, &endpoint_infos)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            mock_service.called_queries,
+            vec![
+                "select id as private_id from users",
+                posts_sql,
+                insert_sql,
+                items_sql,
+                posts_sql,
+                insert_sql,
+                items_sql,
+            ]
+        );
+        assert_eq!(
+            mock_service.bound_params,
+            [1, 1, 11, 2, 2, 21]
+                .into_iter()
+                .map(SqlValue::BigInt)
+                .collect::<Vec<_>>()
+        );
+
+        // without side effects a sorted query still runs once per parent row
+        let mut mock_service = ExecutionMockService::new(vec![
+            vec![],
+            vec![],
+            vec![
+                hashmap! {"private_id".into() => 1.into()},
+                hashmap! {"private_id".into() => 2.into()},
+            ],
+        ]);
+
+        let endpoint_infos = vec![EndpointInfo {
+            name: "users".into(),
+            parsed_sql: "select id as private_id from users".into(),
+            children: vec![EndpointInfo {
+                name: "posts".into(),
+                variables: vec!["super.private_id".into()],
+                parsed_sql: posts_sql.into(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }];
+
+        execution_runtime
+            .execute_impl(&mut mock_service, &endpoint_infos)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            mock_service.called_queries,
+            vec!["select id as private_id from users", posts_sql, posts_sql]
+        );
+    }
+
+    #[tokio::test]
+    async fn parameter_types_can_change_between_calls() {
+        let db = TestDatabase::create().await;
+        let mut transaction = db.pool.begin().await.unwrap();
+
+        // the type of null is inferred as int4 from the comparison
+        let sql = "SELECT 3::int4 = $1 AS matches";
+        for param in [SqlValue::Null, SqlValue::BigInt(3)] {
+            fetch(&mut transaction, sql, vec![param]).await.unwrap();
+        }
+        let affected = execute_statement(&mut transaction, sql, vec![SqlValue::BigInt(3)])
+            .await
+            .unwrap();
+        assert_eq!(affected, 1);
+
+        let sql = "SELECT $1::int8 AS id";
+        let mut ids = Vec::new();
+        for param in [SqlValue::BigInt(3), SqlValue::Text("3".into())] {
+            let rows = fetch(&mut transaction, sql, vec![param]).await.unwrap();
+            ids.push(rows[0]["id"].clone());
+        }
+        assert_eq!(ids, vec![json!(3), json!(3)]);
+
+        transaction.rollback().await.unwrap();
+        db.drop().await;
+    }
+
+    async fn execute_user_query(
+        mock_service: &mut ExecutionMockService,
+        cardinality: Cardinality,
+    ) -> Result<HashMap<String, NodeResult>> {
+        let endpoint_infos = vec![EndpointInfo {
+            name: "user".into(),
+            parsed_sql: "select name from users".into(),
+            cardinality,
+            ..Default::default()
+        }];
+
+        EndpointExecutionRuntime::new(json!({}))
+            .execute_impl(mock_service, &endpoint_infos)
+            .await
+    }
+
+    #[tokio::test]
+    async fn results_are_shaped_by_cardinality() {
+        let row = || hashmap! {"name".into() => "ann".into()};
+
+        let mut mock_service = ExecutionMockService::new(vec![
+            vec![],
+            vec![row(), row()],
+            vec![],
+            vec![row()],
+            vec![],
+            vec![row()],
+        ]);
+        let mock = &mut mock_service;
+
+        assert_eq!(
+            execute_user_query(mock, Cardinality::One).await.unwrap(),
+            hashmap! {
+                "user".into() => NodeResult::One(Some(ExecutionResult {
+                    data: row(),
+                    children: hashmap! {},
+                    flattened: false,
+                }))
+            }
+        );
+        assert_eq!(
+            to_status(
+                execute_user_query(mock, Cardinality::One)
+                    .await
+                    .unwrap_err()
+            )
+            .0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            execute_user_query(mock, Cardinality::Scalar).await.unwrap(),
+            hashmap! {"user".into() => NodeResult::Scalar("ann".into())}
+        );
+        assert_eq!(
+            execute_user_query(mock, Cardinality::OptionalOne)
+                .await
+                .unwrap(),
+            hashmap! {"user".into() => NodeResult::One(None)}
+        );
+        assert!(execute_user_query(mock, Cardinality::OptionalOne)
+            .await
+            .is_err());
+        assert_eq!(
+            execute_user_query(mock, Cardinality::Scalar).await.unwrap(),
+            hashmap! {"user".into() => NodeResult::Scalar(Value::Null)}
+        );
+    }
+
+    #[test]
+    fn flattened_results() {
+        let result = ExecutionResult {
+            data: hashmap! {"name".into() => "ann".into()},
+            children: hashmap! {
+                "posts".into() => NodeResult::Rows(vec![ExecutionResult {
+                    data: hashmap! {"title".into() => "a".into()},
+                    children: hashmap! {},
+                    flattened: true,
+                }]),
+                "post_count".into() => NodeResult::Scalar(1.into()),
+            },
+            flattened: true,
+        };
+
+        assert_eq!(
+            serde_json::to_value(&result).unwrap(),
+            json!({"name": "ann", "posts": [{"title": "a"}], "post_count": 1})
+        );
+    }
+
+    #[tokio::test]
+    async fn affected_rows_are_counted() {
+        let mut mock_service = ExecutionMockService::new(vec![vec![hashmap! {}, hashmap! {}]]);
+
+        let endpoint_infos = vec![EndpointInfo {
+            name: "deleted".into(),
+            parsed_sql: "delete from posts where user_fk = $1".into(),
+            variables: vec!["req.user".into()],
+            variable_types: vec![None],
+            cardinality: Cardinality::AffectedRows,
+            ..Default::default()
+        }];
+
+        let mut execution_runtime = EndpointExecutionRuntime::new(json!({"user": 3}));
+
+        let final_result = execution_runtime
+            .execute_impl(&mut mock_service, &endpoint_infos)
+            .await
+            .unwrap();
+
+        assert_eq!(mock_service.bound_params, vec![SqlValue::BigInt(3)]);
+        assert_eq!(
+            final_result,
+            hashmap! {"deleted".into() => NodeResult::Scalar(2.into())}
+        );
+    }
+
+    #[tokio::test]
+    async fn guards_and_conditions() {
+        let endpoint_infos = vec![
+            EndpointInfo {
+                name: "is_allowed".into(),
+                parsed_sql: "select $1 > 0".into(),
+                variables: vec!["req.user".into()],
+                variable_types: vec![None],
+                kind: NodeKind::Guard {
+                    status: 403,
+                    message: "Not allowed".into(),
+                },
+                ..Default::default()
+            },
+            EndpointInfo {
+                name: "post".into(),
+                parsed_sql: "select id from posts".into(),
+                children: vec![EndpointInfo {
+                    name: "with_comments".into(),
+                    parsed_sql: "select $1".into(),
+                    variables: vec!["req.include_comments".into()],
+                    variable_types: vec![None],
+                    kind: NodeKind::Condition,
+                    children: vec![EndpointInfo {
+                        name: "comments".into(),
+                        parsed_sql: "select body from comments where post_fk = $1".into(),
+                        variables: vec!["super.id".into()],
+                        variable_types: vec![None],
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        ];
+        let passed = || vec![hashmap! {"?column?".into() => true.into()}];
+        let failed = || vec![hashmap! {"?column?".into() => false.into()}];
+        let post = || vec![hashmap! {"id".into() => 1.into()}];
+
+        let mut mock_service = ExecutionMockService::new(vec![
+            failed(),
+            post(),
+            passed(),
+            failed(),
+            vec![hashmap! {"body".into() => "nice".into()}],
+            passed(),
+            post(),
+            passed(),
+        ]);
+
+        assert_eq!(
+            EndpointExecutionRuntime::new(json!({"user": 1, "include_comments": true}))
+                .execute_impl(&mut mock_service, &endpoint_infos)
+                .await
+                .unwrap(),
+            hashmap! {
+                "post".into() => NodeResult::Rows(vec![ExecutionResult {
+                    data: hashmap! {"id".into() => 1.into()},
+                    children: hashmap! {
+                        "comments".into() => NodeResult::Rows(vec![ExecutionResult {
+                            data: hashmap! {"body".into() => "nice".into()},
+                            children: hashmap! {},
+                            flattened: false,
+                        }]),
+                    },
+                    flattened: false,
+                }])
+            }
+        );
+
+        assert_eq!(
+            to_status(
+                EndpointExecutionRuntime::new(json!({"user": 0}))
+                    .execute_impl(&mut mock_service, &endpoint_infos)
+                    .await
+                    .unwrap_err()
+            ),
+            (StatusCode::FORBIDDEN, "Not allowed".into())
+        );
+
+        assert_eq!(
+            EndpointExecutionRuntime::new(json!({"user": 1, "include_comments": false}))
+                .execute_impl(&mut mock_service, &endpoint_infos)
+                .await
+                .unwrap(),
+            hashmap! {
+                "post".into() => NodeResult::Rows(vec![ExecutionResult {
+                    data: hashmap! {"id".into() => 1.into()},
+                    children: hashmap! {},
+                    flattened: false,
+                }])
+            }
         );
     }
 }
@@ -0,0 +1,274 @@
+use crate::algorithms::{endpoint_execution::NodeResult, json_path::resolve_path};
+use anyhow::{anyhow, Result};
+use axum::http::StatusCode;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Characters that are kept as they are in substituted values
+const VALUE_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// How a successful call of an endpoint is answered
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EndpointResponse {
+    /// 200 when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Template of the `Location` header, e.g. `/endpoint/users/${result.user.id}`.
+    ///
+    /// Variables are `req.` and `path.` values or columns of the first row
+    /// returned by a top-level query, as `result.<query>.<column>`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum TemplatePart<'a> {
+    Text(&'a str),
+    Variable(&'a str),
+}
+
+fn parse_template(template: &str) -> Result<Vec<TemplatePart<'_>>> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("${") {
+        if start > 0 {
+            parts.push(TemplatePart::Text(&rest[..start]));
+        }
+
+        let (variable, continuation) = rest[start + 2..]
+            .split_once('}')
+            .ok_or_else(|| anyhow!("Variable not closed in {}", template))?;
+
+        if variable.trim().is_empty() {
+            return Err(anyhow!("Empty variable name in {}", template));
+        }
+
+        parts.push(TemplatePart::Variable(variable.trim()));
+        rest = continuation;
+    }
+
+    if !rest.is_empty() {
+        parts.push(TemplatePart::Text(rest));
+    }
+
+    Ok(parts)
+}
+
+impl EndpointResponse {
+    pub fn status_code(&self) -> StatusCode {
+        self.status
+            .and_then(|it| StatusCode::from_u16(it).ok())
+            .unwrap_or(StatusCode::OK)
+    }
+
+    /// Whether responses have a body, HTTP forbids one with 204, 205 and 304
+    pub fn has_body(&self) -> bool {
+        !matches!(
+            self.status_code(),
+            StatusCode::NO_CONTENT | StatusCode::RESET_CONTENT | StatusCode::NOT_MODIFIED
+        )
+    }
+
+    /// Problems with the settings, `query_names` are the names of top-level queries
+    pub fn check(&self, query_names: &[&str]) -> Vec<String> {
+        let mut messages = Vec::new();
+
+        if let Some(status) = self.status {
+            if !(200..=399).contains(&status) || StatusCode::from_u16(status).is_err() {
+                messages.push(format!(
+                    "Status {} isn't a success or redirection status",
+                    status
+                ));
+            }
+        }
+
+        let parts = match self.location.as_deref().map(parse_template) {
+            Some(Ok(parts)) => parts,
+            Some(Err(e)) => {
+                messages.push(e.to_string());
+                return messages;
+            }
+            None => return messages,
+        };
+
+        for part in parts {
+            if let TemplatePart::Variable(variable) = part {
+                let known = match variable.split_once('.') {
+                    Some(("req" | "path", _)) => true,
+                    Some(("result", rest)) => rest
+                        .split_once('.')
+                        .is_some_and(|(query, _)| query_names.contains(&query)),
+                    _ => false,
+                };
+
+                if !known {
+                    messages.push(format!(
+                        "${{{}}} in location should begin with req., path. or result. and the name of a top-level query",
+                        variable
+                    ));
+                }
+            }
+        }
+
+        messages
+    }
+
+    /// Fills in the location template, `None` when there is no template
+    /// or a referenced query didn't return any row
+    pub fn location(
+        &self,
+        results: &HashMap<String, NodeResult>,
+        request: &Value,
+        path_params: &HashMap<String, String>,
+    ) -> Result<Option<String>> {
+        let template = match &self.location {
+            Some(template) => template,
+            None => return Ok(None),
+        };
+
+        let mut location = String::new();
+
+        for part in parse_template(template)? {
+            let variable = match part {
+                TemplatePart::Text(text) => {
+                    location.push_str(text);
+                    continue;
+                }
+                TemplatePart::Variable(variable) => variable,
+            };
+
+            let value = match variable.split_once('.') {
+                Some(("req", path)) => resolve_path(request, path)?.cloned(),
+                Some(("path", name)) => path_params.get(name).cloned().map(Value::String),
+                Some(("result", rest)) => match rest.split_once('.') {
+                    Some((query, column)) => match first_row(results.get(query)) {
+                        Some(data) => data.get(column).cloned(),
+                        None => return Ok(None),
+                    },
+                    None => None,
+                },
+                _ => None,
+            };
+
+            let text = match value {
+                Some(Value::String(s)) => s,
+                Some(Value::Null) | None => {
+                    return Err(anyhow!("Value of ${{{}}} in location not found", variable))
+                }
+                Some(other) => other.to_string(),
+            };
+
+            location.extend(utf8_percent_encode(&text, VALUE_ENCODE_SET));
+        }
+
+        Ok(Some(location))
+    }
+}
+
+fn first_row(result: Option<&NodeResult>) -> Option<&HashMap<String, Value>> {
+    match result? {
+        NodeResult::Rows(rows) | NodeResult::Page { rows, .. } => rows.first().map(|it| &it.data),
+        NodeResult::One(row) => row.as_ref().map(|it| &it.data),
+        NodeResult::Scalar(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::endpoint_execution::ExecutionResult;
+    use maplit::hashmap;
+    use serde_json::json;
+
+    fn response(location: &str) -> EndpointResponse {
+        EndpointResponse {
+            status: Some(201),
+            location: Some(location.into()),
+        }
+    }
+
+    #[test]
+    fn checking() {
+        assert_eq!(
+            response("/endpoint/users/${result.user.id}/${path.x}").check(&["user"]),
+            Vec::<String>::new()
+        );
+        let unknown = |variable: &str| {
+            format!(
+                "${{{}}} in location should begin with req., path. or result. and the name of a top-level query",
+                variable
+            )
+        };
+        assert_eq!(
+            response("/endpoint/users/${result.post.id}").check(&["user"]),
+            vec![unknown("result.post.id")]
+        );
+        // user. is the caller in endpoint sql, the query is under result.
+        assert_eq!(
+            response("/endpoint/users/${user.id}").check(&["user"]),
+            vec![unknown("user.id")]
+        );
+        assert_eq!(
+            response("/endpoint/users/${result.user}").check(&["user"]),
+            vec![unknown("result.user")]
+        );
+        assert_eq!(
+            response("/endpoint/users/${result.user.id").check(&["user"]),
+            vec!["Variable not closed in /endpoint/users/${result.user.id"]
+        );
+
+        assert!(response("/").has_body());
+        assert!(!EndpointResponse {
+            status: Some(204),
+            location: None
+        }
+        .has_body());
+
+        let mut bad_status = response("/");
+        bad_status.status = Some(404);
+        assert_eq!(
+            bad_status.check(&[]),
+            vec!["Status 404 isn't a success or redirection status"]
+        );
+    }
+
+    #[test]
+    fn filling_location() {
+        let results = hashmap! {
+            "user".to_owned() => NodeResult::One(Some(ExecutionResult {
+                data: hashmap! {"id".into() => 7.into(), "name".into() => "a b".into()},
+                children: hashmap! {},
+                flattened: false,
+            })),
+            "posts".to_owned() => NodeResult::Rows(vec![]),
+        };
+        let request = json!({"group": {"slug": "x/y"}});
+        let path_params = hashmap! {"org".to_owned() => "acme".to_owned()};
+
+        let location = |template: &str| {
+            response(template)
+                .location(&results, &request, &path_params)
+                .unwrap()
+        };
+
+        assert_eq!(
+            location("/endpoint/${path.org}/users/${result.user.id}?name=${result.user.name}"),
+            Some("/endpoint/acme/users/7?name=a%20b".into())
+        );
+        assert_eq!(
+            location("/groups/${req.group.slug}"),
+            Some("/groups/x%2Fy".into())
+        );
+        assert_eq!(location("/posts/${result.posts.id}"), None);
+        assert!(response("/users/${result.user.age}")
+            .location(&results, &request, &path_params)
+            .is_err());
+    }
+}
@@ -0,0 +1,477 @@
+use crate::algorithms::{
+    pagination::Pagination,
+    sql_variable_parser::{Cardinality, NodeKind},
+};
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// Query of an endpoint tree after it was prepared against the database
+#[derive(Debug, Default)]
+pub struct ValidationNode {
+    pub name: String,
+    pub variables: Vec<String>,
+    /// Result columns, `None` when the query couldn't be parsed or prepared
+    pub columns: Option<Vec<String>>,
+    pub kind: NodeKind,
+    pub pagination: Option<Pagination>,
+    pub cardinality: Cardinality,
+    pub flatten: bool,
+    pub children: Vec<ValidationNode>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ValidationError {
+    /// Names of the queries leading to the faulty one, e.g. `user.posts`
+    pub node: String,
+    pub message: String,
+}
+
+pub fn node_path(parent_path: &str, name: &str) -> String {
+    if parent_path.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}.{}", parent_path, name)
+    }
+}
+
+/// Checks names and variables of the tree, the columns of ancestors
+/// are used to check `super.` references.
+pub fn check_tree(nodes: &[ValidationNode]) -> Vec<ValidationError> {
+    let mut errors = Vec::new();
+    check_nodes(nodes, "", &mut vec![], &mut HashSet::new(), &mut errors);
+    errors
+}
+
+/// `names` are the names used on the level so far,
+/// children of conditions belong to the level of the condition
+fn check_nodes<'a>(
+    nodes: &'a [ValidationNode],
+    parent_path: &str,
+    ancestors: &mut Vec<Option<&'a [String]>>,
+    names: &mut HashSet<&'a str>,
+    errors: &mut Vec<ValidationError>,
+) {
+    for node in nodes {
+        let path = node_path(parent_path, &node.name);
+
+        if node.name.is_empty() {
+            errors.push(ValidationError {
+                node: path.clone(),
+                message: "Query name can't be empty".into(),
+            });
+        } else if !names.insert(&node.name) {
+            errors.push(ValidationError {
+                node: path.clone(),
+                message: format!("Duplicate query name {}", node.name),
+            });
+        }
+
+        for variable in &node.variables {
+            if let Some(message) = check_variable(variable, ancestors) {
+                errors.push(ValidationError {
+                    node: path.clone(),
+                    message,
+                });
+            }
+        }
+
+        if let Some(pagination) = &node.pagination {
+            if let Some(message) = check_pagination(pagination, node, ancestors) {
+                errors.push(ValidationError {
+                    node: path.clone(),
+                    message,
+                });
+            }
+        }
+
+        for message in check_shape(node) {
+            errors.push(ValidationError {
+                node: path.clone(),
+                message,
+            });
+        }
+
+        if node.kind == NodeKind::Condition {
+            check_nodes(&node.children, &path, ancestors, names, errors);
+        } else {
+            ancestors.push(node.columns.as_deref());
+            check_nodes(
+                &node.children,
+                &path,
+                ancestors,
+                &mut HashSet::new(),
+                errors,
+            );
+            ancestors.pop();
+        }
+    }
+}
+
+fn check_variable(variable: &str, ancestors: &[Option<&[String]>]) -> Option<String> {
+    if variable.starts_with("req.") || variable.starts_with("path.") {
+        return None;
+    }
+
+    if let Some(name) = variable.strip_prefix("user.") {
+        return match name {
+            "id" | "username" | "group" => None,
+            _ => Some(format!(
+                "Unknown user variable ${{{}}}, use user.id, user.username or user.group",
+                variable
+            )),
+        };
+    }
+
+    if !variable.starts_with("super.") {
+        return Some(format!(
+            "Bad variable name ${{{}}}, should begin with super., req., path. or user.",
+            variable
+        ));
+    }
+
+    let mut column = variable;
+    let mut counter = 0_usize;
+
+    while let Some(rest) = column.strip_prefix("super.") {
+        column = rest;
+        counter += 1;
+    }
+
+    if ancestors.is_empty() {
+        return Some(format!(
+            "${{{}}} can't be used in a top-level query",
+            variable
+        ));
+    }
+
+    if counter > ancestors.len() {
+        return Some(format!(
+            "${{{}}} reaches above the top-level query",
+            variable
+        ));
+    }
+
+    match ancestors[ancestors.len() - counter] {
+        Some(columns) if !columns.iter().any(|it| it == column) => Some(format!(
+            "${{{}}} refers to column {} which the query doesn't return",
+            variable, column
+        )),
+        // Unknown columns were already reported as an error of the ancestor
+        _ => None,
+    }
+}
+
+fn check_shape(node: &ValidationNode) -> Vec<String> {
+    let mut messages = Vec::new();
+
+    if let NodeKind::Guard { status, .. } = &node.kind {
+        if !(400..=599).contains(status) {
+            messages.push(format!("Guard status {} isn't an error status", status));
+        }
+
+        if !node.children.is_empty() {
+            messages.push("Guards can't have children".into());
+        }
+    }
+
+    if node.kind != NodeKind::Query
+        && (node.pagination.is_some() || node.cardinality != Cardinality::Many || node.flatten)
+    {
+        messages.push("Pagination, cardinality and flatten only apply to query nodes".into());
+        return messages;
+    }
+
+    if node.cardinality == Cardinality::Scalar {
+        if !node.children.is_empty() {
+            messages.push("Scalar queries can't have children".into());
+        }
+
+        let public_columns = node
+            .columns
+            .iter()
+            .flatten()
+            .filter(|it| !it.starts_with("private_"))
+            .count();
+
+        if node.columns.is_some() && public_columns != 1 {
+            messages.push(format!(
+                "Scalar queries must return exactly one column, the query returns {}",
+                public_columns
+            ));
+        }
+    }
+
+    if node.cardinality == Cardinality::AffectedRows && !node.children.is_empty() {
+        messages.push("Queries counting affected rows can't have children".into());
+    }
+
+    if node.pagination.is_some() && node.cardinality != Cardinality::Many {
+        messages.push("Only queries returning many rows can be paginated".into());
+    }
+
+    if node.flatten {
+        for child in &node.children {
+            if node.columns.iter().flatten().any(|it| *it == child.name) {
+                messages.push(format!(
+                    "Child query {} has the same name as a column of the flattened query",
+                    child.name
+                ));
+            }
+        }
+    }
+
+    messages
+}
+
+fn check_pagination(
+    pagination: &Pagination,
+    node: &ValidationNode,
+    ancestors: &[Option<&[String]>],
+) -> Option<String> {
+    if !ancestors.is_empty() {
+        return Some("Only top-level queries can be paginated".into());
+    }
+
+    if pagination.default_page_size == 0 || pagination.max_page_size == 0 {
+        return Some("Page sizes must be positive".into());
+    }
+
+    match &node.columns {
+        Some(columns) if !columns.contains(&pagination.order_by) => Some(format!(
+            "Pagination column {} isn't returned by the query",
+            pagination.order_by
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, variables: &[&str], columns: &[&str]) -> ValidationNode {
+        ValidationNode {
+            name: name.into(),
+            variables: variables.iter().map(|it| it.to_string()).collect(),
+            columns: Some(columns.iter().map(|it| it.to_string()).collect()),
+            kind: NodeKind::Query,
+            pagination: None,
+            cardinality: Cardinality::Many,
+            flatten: false,
+            children: vec![],
+        }
+    }
+
+    fn with_children(mut node: ValidationNode, children: Vec<ValidationNode>) -> ValidationNode {
+        node.children = children;
+        node
+    }
+
+    #[test]
+    fn valid_tree() {
+        let tree = vec![with_children(
+            node(
+                "user",
+                &["req.id", "path.id", "user.id", "user.group"],
+                &["private_id", "name"],
+            ),
+            vec![with_children(
+                node("posts", &["super.private_id"], &["id"]),
+                vec![node("comments", &["super.id", "super.super.name"], &[])],
+            )],
+        )];
+
+        assert_eq!(check_tree(&tree), vec![]);
+    }
+
+    #[test]
+    fn super_references() {
+        let tree = vec![
+            node("top", &["super.id"], &["id"]),
+            with_children(
+                node("user", &[], &["id"]),
+                vec![node("posts", &["super.name", "super.super.id"], &[])],
+            ),
+        ];
+
+        assert_eq!(
+            check_tree(&tree),
+            vec![
+                ValidationError {
+                    node: "top".into(),
+                    message: "${super.id} can't be used in a top-level query".into(),
+                },
+                ValidationError {
+                    node: "user.posts".into(),
+                    message: "${super.name} refers to column name which the query doesn't return"
+                        .into(),
+                },
+                ValidationError {
+                    node: "user.posts".into(),
+                    message: "${super.super.id} reaches above the top-level query".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_columns_are_not_checked() {
+        let mut user = node("user", &[], &[]);
+        user.columns = None;
+        let tree = vec![with_children(user, vec![node("posts", &["super.id"], &[])])];
+
+        assert_eq!(check_tree(&tree), vec![]);
+    }
+
+    #[test]
+    fn names_and_variables() {
+        let tree = vec![
+            node("user", &["request.id", "user.name"], &[]),
+            with_children(
+                node("user", &[], &[]),
+                vec![node("", &[], &[]), node("posts", &[], &[])],
+            ),
+        ];
+
+        assert_eq!(
+            check_tree(&tree),
+            vec![
+                ValidationError {
+                    node: "user".into(),
+                    message:
+                        "Bad variable name ${request.id}, should begin with super., req., path. or user."
+                            .into(),
+                },
+                ValidationError {
+                    node: "user".into(),
+                    message:
+                        "Unknown user variable ${user.name}, use user.id, user.username or user.group"
+                            .into(),
+                },
+                ValidationError {
+                    node: "user".into(),
+                    message: "Duplicate query name user".into(),
+                },
+                ValidationError {
+                    node: "user.".into(),
+                    message: "Query name can't be empty".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn pagination() {
+        let paginated = |name: &str, order_by: &str| {
+            let mut node = node(name, &[], &["id", "title"]);
+            node.pagination =
+                Some(serde_json::from_value(serde_json::json!({ "order_by": order_by })).unwrap());
+            node
+        };
+
+        let tree = vec![
+            paginated("posts", "id"),
+            paginated("drafts", "created"),
+            with_children(node("user", &[], &["id"]), vec![paginated("posts", "id")]),
+        ];
+
+        assert_eq!(
+            check_tree(&tree),
+            vec![
+                ValidationError {
+                    node: "drafts".into(),
+                    message: "Pagination column created isn't returned by the query".into(),
+                },
+                ValidationError {
+                    node: "user.posts".into(),
+                    message: "Only top-level queries can be paginated".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn shapes() {
+        let mut count = node("count", &[], &["count", "private_id"]);
+        count.cardinality = Cardinality::Scalar;
+
+        let mut names = with_children(
+            node("names", &[], &["id", "name"]),
+            vec![node("id", &[], &[])],
+        );
+        names.cardinality = Cardinality::Scalar;
+        names.flatten = true;
+
+        let tree = vec![count, names];
+
+        assert_eq!(
+            check_tree(&tree),
+            vec![
+                ValidationError {
+                    node: "names".into(),
+                    message: "Scalar queries can't have children".into(),
+                },
+                ValidationError {
+                    node: "names".into(),
+                    message: "Scalar queries must return exactly one column, the query returns 2"
+                        .into(),
+                },
+                ValidationError {
+                    node: "names".into(),
+                    message: "Child query id has the same name as a column of the flattened query"
+                        .into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn guards_and_conditions() {
+        let mut guard = with_children(
+            node("is_owner", &["req.user"], &[]),
+            vec![node("x", &[], &[])],
+        );
+        guard.kind = NodeKind::Guard {
+            status: 200,
+            message: "Not an owner".into(),
+        };
+
+        let mut condition = with_children(
+            node("with_comments", &["req.include_comments"], &["?column?"]),
+            vec![node("comments", &["super.id", "super.comment"], &[])],
+        );
+        condition.kind = NodeKind::Condition;
+
+        let tree = vec![
+            guard,
+            with_children(
+                node("post", &[], &["id", "title"]),
+                vec![condition, node("comments", &[], &[])],
+            ),
+        ];
+
+        assert_eq!(
+            check_tree(&tree),
+            vec![
+                ValidationError {
+                    node: "is_owner".into(),
+                    message: "Guard status 200 isn't an error status".into(),
+                },
+                ValidationError {
+                    node: "is_owner".into(),
+                    message: "Guards can't have children".into(),
+                },
+                ValidationError {
+                    node: "post.with_comments.comments".into(),
+                    message:
+                        "${super.comment} refers to column comment which the query doesn't return"
+                            .into(),
+                },
+                ValidationError {
+                    node: "post.comments".into(),
+                    message: "Duplicate query name comments".into(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+
+/// Bounds of one call of an endpoint, unset values fall back to the server defaults
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExecutionLimits {
+    /// Milliseconds a statement may run, answered with 504 when exceeded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub statement_timeout_ms: Option<u32>,
+    /// Rows a query may return for one parent row, answered with 413 when exceeded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_rows: Option<u32>,
+    /// Approximate size of the whole json response, answered with 413 when exceeded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_response_bytes: Option<u64>,
+}
+
+impl ExecutionLimits {
+    pub fn is_unset(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Limits of the endpoint, with values it doesn't set taken from `defaults`
+    pub fn or(&self, defaults: &Self) -> Self {
+        Self {
+            statement_timeout_ms: self.statement_timeout_ms.or(defaults.statement_timeout_ms),
+            max_rows: self.max_rows.or(defaults.max_rows),
+            max_response_bytes: self.max_response_bytes.or(defaults.max_response_bytes),
+        }
+    }
+
+    /// Problems with the settings
+    pub fn check(&self) -> Vec<String> {
+        let mut messages = Vec::new();
+
+        // 0 disables the timeout in postgres, which is what the limit prevents
+        if self.statement_timeout_ms == Some(0) {
+            messages.push("statement_timeout_ms must be at least 1".to_owned());
+        }
+        if self.max_rows == Some(0) {
+            messages.push("max_rows must be at least 1".to_owned());
+        }
+        if self.max_response_bytes == Some(0) {
+            messages.push("max_response_bytes must be at least 1".to_owned());
+        }
+
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falling_back_to_defaults() {
+        let defaults = ExecutionLimits {
+            statement_timeout_ms: Some(30_000),
+            max_rows: None,
+            max_response_bytes: Some(1024),
+        };
+        let limits = ExecutionLimits {
+            statement_timeout_ms: Some(500),
+            max_rows: Some(100),
+            max_response_bytes: None,
+        };
+
+        assert_eq!(
+            limits.or(&defaults),
+            ExecutionLimits {
+                statement_timeout_ms: Some(500),
+                max_rows: Some(100),
+                max_response_bytes: Some(1024),
+            }
+        );
+        assert_eq!(ExecutionLimits::default().or(&defaults), defaults);
+        assert!(ExecutionLimits::default().is_unset());
+
+        let zero = ExecutionLimits {
+            statement_timeout_ms: Some(0),
+            ..limits
+        };
+        assert_eq!(
+            zero.check(),
+            vec!["statement_timeout_ms must be at least 1"]
+        );
+    }
+}
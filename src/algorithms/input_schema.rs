@@ -0,0 +1,382 @@
+use crate::algorithms::json_path::resolve_path;
+use anyhow::Context;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    String,
+    Integer,
+    Number,
+    Boolean,
+    Object,
+    Array,
+}
+
+/// Checks of one request field.
+///
+/// Form fields are always strings, so numbers and booleans
+/// are also accepted as strings which parse to them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FieldSchema {
+    #[serde(default)]
+    pub required: bool,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub field_type: Option<FieldType>,
+    /// Bounds of numbers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    /// Bounds of the length of strings and arrays
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_length: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<usize>,
+    /// Regex a string has to match, it isn't anchored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    #[serde(rename = "enum", default, skip_serializing_if = "Option::is_none")]
+    pub allowed_values: Option<Vec<Value>>,
+    /// `pattern` compiled by `InputSchema::compile`
+    #[serde(skip)]
+    compiled_pattern: Option<CompiledPattern>,
+}
+
+#[derive(Debug, Clone)]
+struct CompiledPattern(Regex);
+
+impl PartialEq for CompiledPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+/// Checks of the request of an endpoint, keyed by paths like `user.name`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct InputSchema {
+    pub fields: BTreeMap<String, FieldSchema>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Returned with 422 when the request doesn't match the schema
+#[derive(Debug, Serialize)]
+pub struct InputErrors {
+    pub errors: Vec<FieldError>,
+}
+
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn has_type(value: &Value, field_type: FieldType) -> bool {
+    match field_type {
+        FieldType::String => value.is_string(),
+        FieldType::Integer => match value {
+            Value::Number(n) => n.is_i64() || n.is_u64(),
+            Value::String(s) => s.trim().parse::<i64>().is_ok(),
+            _ => false,
+        },
+        FieldType::Number => as_number(value).is_some(),
+        FieldType::Boolean => match value {
+            Value::Bool(_) => true,
+            Value::String(s) => s == "true" || s == "false",
+            _ => false,
+        },
+        FieldType::Object => value.is_object(),
+        FieldType::Array => value.is_array(),
+    }
+}
+
+fn type_name(field_type: FieldType) -> &'static str {
+    match field_type {
+        FieldType::String => "a string",
+        FieldType::Integer => "an integer",
+        FieldType::Number => "a number",
+        FieldType::Boolean => "a boolean",
+        FieldType::Object => "an object",
+        FieldType::Array => "an array",
+    }
+}
+
+impl FieldSchema {
+    /// Messages about the value, empty when it's valid
+    fn check_value(&self, value: &Value) -> Vec<String> {
+        if let Some(field_type) = self.field_type {
+            if !has_type(value, field_type) {
+                return vec![format!("Must be {}", type_name(field_type))];
+            }
+        }
+
+        let mut messages = Vec::new();
+
+        if self.min.is_some() || self.max.is_some() {
+            match as_number(value) {
+                Some(n) if self.min.is_some_and(|min| n < min) => {
+                    messages.push(format!("Must be at least {}", self.min.unwrap_or_default()))
+                }
+                Some(n) if self.max.is_some_and(|max| n > max) => {
+                    messages.push(format!("Must be at most {}", self.max.unwrap_or_default()))
+                }
+                Some(_) => {}
+                None => messages.push("Must be a number".into()),
+            }
+        }
+
+        let length = match value {
+            Value::String(s) => Some(s.chars().count()),
+            Value::Array(a) => Some(a.len()),
+            _ => None,
+        };
+
+        if let Some(length) = length {
+            if let Some(min_length) = self.min_length.filter(|it| length < *it) {
+                messages.push(format!("Must be at least {} long", min_length));
+            }
+            if let Some(max_length) = self.max_length.filter(|it| length > *it) {
+                messages.push(format!("Must be at most {} long", max_length));
+            }
+        }
+
+        if let Some(pattern) = &self.pattern {
+            let matches = match (value.as_str(), &self.compiled_pattern) {
+                (Some(s), Some(CompiledPattern(regex))) => regex.is_match(s),
+                // schemas which weren't compiled
+                (Some(s), None) => Regex::new(pattern).is_ok_and(|regex| regex.is_match(s)),
+                (None, _) => false,
+            };
+
+            if !matches {
+                messages.push(format!("Must match {}", pattern));
+            }
+        }
+
+        if let Some(allowed_values) = &self.allowed_values {
+            // form values are strings, so "3" is allowed when 3 is
+            let allowed = allowed_values.iter().any(|allowed| match (allowed, value) {
+                (Value::Number(_) | Value::Bool(_), Value::String(s)) => {
+                    serde_json::from_str::<Value>(s).ok().as_ref() == Some(allowed)
+                }
+                _ => allowed == value,
+            });
+
+            if !allowed {
+                let values = allowed_values
+                    .iter()
+                    .map(Value::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                messages.push(format!("Must be one of {}", values));
+            }
+        }
+
+        messages
+    }
+}
+
+impl InputSchema {
+    /// Problems with the schema itself, found when the endpoint is saved
+    pub fn check(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+
+        for (field, schema) in &self.fields {
+            let mut push = |message: String| {
+                errors.push(FieldError {
+                    field: field.clone(),
+                    message,
+                })
+            };
+
+            if let Err(e) = resolve_path(&Value::Null, field) {
+                push(e.to_string());
+            }
+
+            if let Some(pattern) = &schema.pattern {
+                if let Err(e) = Regex::new(pattern) {
+                    push(format!("Bad pattern: {}", e));
+                }
+            }
+
+            if let (Some(min), Some(max)) = (schema.min, schema.max) {
+                if min > max {
+                    push("min is greater than max".into());
+                }
+            }
+
+            if let (Some(min), Some(max)) = (schema.min_length, schema.max_length) {
+                if min > max {
+                    push("min_length is greater than max_length".into());
+                }
+            }
+        }
+
+        errors
+    }
+
+    /// Compiles the patterns once, so that requests don't compile them again
+    pub fn compile(&mut self) -> anyhow::Result<()> {
+        for (field, schema) in &mut self.fields {
+            schema.compiled_pattern = match &schema.pattern {
+                Some(pattern) => Some(CompiledPattern(
+                    Regex::new(pattern).with_context(|| format!("Bad pattern of {}", field))?,
+                )),
+                None => None,
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Checks the request, null values count as missing
+    pub fn validate(&self, request: &Value) -> Result<(), InputErrors> {
+        let mut errors = Vec::new();
+
+        for (field, schema) in &self.fields {
+            let value = resolve_path(request, field)
+                .ok()
+                .flatten()
+                .filter(|it| !it.is_null());
+
+            let messages = match value {
+                Some(value) => schema.check_value(value),
+                None if schema.required => vec!["Required".into()],
+                None => vec![],
+            };
+
+            errors.extend(messages.into_iter().map(|message| FieldError {
+                field: field.clone(),
+                message,
+            }));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(InputErrors { errors })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> InputSchema {
+        serde_json::from_value(json!({
+            "title": {"required": true, "type": "string", "min_length": 3, "max_length": 10},
+            "rating": {"type": "integer", "min": 1, "max": 5},
+            "slug": {"pattern": "^[a-z-]+$"},
+            "kind": {"enum": ["post", "page", 7]},
+            "author.id": {"required": true, "type": "integer"},
+        }))
+        .unwrap()
+    }
+
+    fn errors(request: Value) -> Vec<(String, String)> {
+        let mut schema = schema();
+        schema.compile().unwrap();
+
+        match schema.validate(&request) {
+            Ok(()) => vec![],
+            Err(e) => e
+                .errors
+                .into_iter()
+                .map(|it| (it.field, it.message))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn valid_requests() {
+        assert_eq!(
+            errors(json!({
+                "title": "Hello",
+                "rating": 5,
+                "slug": "hello-world",
+                "kind": "page",
+                "author": {"id": 3},
+            })),
+            vec![]
+        );
+
+        // values of forms are strings
+        assert_eq!(
+            errors(json!({"title": "Hello", "rating": "2", "kind": "7", "author": {"id": "3"}})),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn invalid_requests() {
+        let error = |field: &str, message: &str| (field.to_owned(), message.to_owned());
+
+        assert_eq!(
+            errors(json!({"rating": 6, "slug": "Hello World", "kind": "note", "author": {}})),
+            vec![
+                error("author.id", "Required"),
+                error("kind", "Must be one of \"post\", \"page\", 7"),
+                error("rating", "Must be at most 5"),
+                error("slug", "Must match ^[a-z-]+$"),
+                error("title", "Required"),
+            ]
+        );
+
+        assert_eq!(
+            errors(json!({"title": "Hi", "rating": 2.5, "author": {"id": null}})),
+            vec![
+                error("author.id", "Required"),
+                error("rating", "Must be an integer"),
+                error("title", "Must be at least 3 long"),
+            ]
+        );
+    }
+
+    #[test]
+    fn checking_schema() {
+        let schema: InputSchema = serde_json::from_value(json!({
+            "slug": {"pattern": "(unclosed"},
+            "rating": {"min": 5, "max": 1, "min_length": 1},
+            "items[0": {},
+        }))
+        .unwrap();
+
+        let fields = schema
+            .check()
+            .into_iter()
+            .map(|it| it.field)
+            .collect::<Vec<_>>();
+
+        assert_eq!(fields, vec!["items[0", "rating", "slug"]);
+        assert!(schema.clone().compile().is_err());
+    }
+
+    #[test]
+    fn compiling_patterns() {
+        let mut compiled = schema();
+        compiled.compile().unwrap();
+
+        assert!(compiled.fields["slug"].compiled_pattern.is_some());
+        assert!(compiled.fields["title"].compiled_pattern.is_none());
+
+        // schemas which weren't compiled still check patterns
+        let request = json!({"slug": "Hello World"});
+        assert_eq!(
+            schema().validate(&request).unwrap_err().errors,
+            compiled.validate(&request).unwrap_err().errors
+        );
+    }
+}
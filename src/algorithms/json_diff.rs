@@ -0,0 +1,101 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+/// Value that differs between two json documents,
+/// `old` is missing for added values and `new` for removed ones
+#[derive(Debug, PartialEq, Serialize)]
+pub struct JsonChange {
+    /// e.g. `endpoints_info[0].sql`
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<Value>,
+}
+
+/// Changes turning `old` into `new`, objects are compared by keys and arrays by indexes
+pub fn json_diff(old: &Value, new: &Value) -> Vec<JsonChange> {
+    let mut changes = Vec::new();
+    diff_values(String::new(), Some(old), Some(new), &mut changes);
+    changes
+}
+
+fn key_path(parent: &str, key: &str) -> String {
+    if parent.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", parent, key)
+    }
+}
+
+fn diff_values(
+    path: String,
+    old: Option<&Value>,
+    new: Option<&Value>,
+    changes: &mut Vec<JsonChange>,
+) {
+    match (old, new) {
+        (Some(Value::Object(old)), Some(Value::Object(new))) => {
+            let keys = old.keys().chain(new.keys()).collect::<BTreeSet<_>>();
+
+            for key in keys {
+                diff_values(key_path(&path, key), old.get(key), new.get(key), changes);
+            }
+        }
+        (Some(Value::Array(old)), Some(Value::Array(new))) => {
+            for i in 0..old.len().max(new.len()) {
+                diff_values(format!("{}[{}]", path, i), old.get(i), new.get(i), changes);
+            }
+        }
+        (old, new) if old != new => changes.push(JsonChange {
+            path,
+            old: old.cloned(),
+            new: new.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn diffing() {
+        let old = json!({
+            "path": "users",
+            "allowed_groups": ["ADMIN", "WRITER"],
+            "endpoints_info": [{"name": "users", "sql": "SELECT 1", "children": []}],
+        });
+        let new = json!({
+            "path": "users",
+            "allowed_groups": ["ADMIN"],
+            "endpoints_info": [{"name": "users", "sql": "SELECT 2", "children": []}],
+            "response": {"status": 201},
+        });
+
+        assert_eq!(
+            json_diff(&old, &new),
+            vec![
+                JsonChange {
+                    path: "allowed_groups[1]".into(),
+                    old: Some("WRITER".into()),
+                    new: None,
+                },
+                JsonChange {
+                    path: "endpoints_info[0].sql".into(),
+                    old: Some("SELECT 1".into()),
+                    new: Some("SELECT 2".into()),
+                },
+                JsonChange {
+                    path: "response".into(),
+                    old: None,
+                    new: Some(json!({"status": 201})),
+                },
+            ]
+        );
+        assert_eq!(json_diff(&old, &old), vec![]);
+    }
+}
@@ -0,0 +1,110 @@
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+#[derive(Debug, PartialEq)]
+enum PathSegment<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+fn parse_path(path: &str) -> Result<Vec<PathSegment<'_>>> {
+    let mut segments = Vec::new();
+
+    for part in path.split('.') {
+        let (key, mut rest) = match part.find('[') {
+            Some(bracket) => part.split_at(bracket),
+            None => (part, ""),
+        };
+
+        if key.is_empty() {
+            return Err(anyhow!("Empty key in path {}", path));
+        }
+        segments.push(PathSegment::Key(key));
+
+        while !rest.is_empty() {
+            let (index, continuation) = rest
+                .strip_prefix('[')
+                .and_then(|it| it.split_once(']'))
+                .ok_or_else(|| anyhow!("Bad index in path {}", path))?;
+
+            let index = index
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("Bad index in path {}", path))?;
+
+            segments.push(PathSegment::Index(index));
+            rest = continuation;
+        }
+    }
+
+    Ok(segments)
+}
+
+/// Looks up a value by a path like `user.id` or `items[0].sku`.
+///
+/// Returns `None` when some part of the path doesn't exist.
+pub fn resolve_path<'a>(value: &'a Value, path: &str) -> Result<Option<&'a Value>> {
+    let mut current = value;
+
+    for segment in parse_path(path)? {
+        let next = match segment {
+            PathSegment::Key(key) => current.get(key),
+            PathSegment::Index(index) => current.get(index),
+        };
+
+        current = match next {
+            Some(it) => it,
+            None => return Ok(None),
+        };
+    }
+
+    Ok(Some(current))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parsing() {
+        assert_eq!(
+            parse_path("items[0][12].sku").unwrap(),
+            vec![
+                PathSegment::Key("items"),
+                PathSegment::Index(0),
+                PathSegment::Index(12),
+                PathSegment::Key("sku"),
+            ]
+        );
+
+        assert!(parse_path("items[0").is_err());
+        assert!(parse_path("items[a]").is_err());
+        assert!(parse_path("items..sku").is_err());
+        assert!(parse_path("[0]").is_err());
+    }
+
+    #[test]
+    fn resolving() {
+        let value = json!({
+            "age": 41,
+            "user": {"id": 3},
+            "items": [{"sku": "A-1"}, {"sku": "B-2"}],
+        });
+
+        assert_eq!(resolve_path(&value, "age").unwrap(), Some(&json!(41)));
+        assert_eq!(resolve_path(&value, "user.id").unwrap(), Some(&json!(3)));
+        assert_eq!(
+            resolve_path(&value, "items[1].sku").unwrap(),
+            Some(&json!("B-2"))
+        );
+        assert_eq!(
+            resolve_path(&value, "items").unwrap(),
+            Some(&value["items"])
+        );
+
+        assert_eq!(resolve_path(&value, "items[2].sku").unwrap(), None);
+        assert_eq!(resolve_path(&value, "user.name").unwrap(), None);
+        assert_eq!(resolve_path(&value, "age.value").unwrap(), None);
+    }
+}
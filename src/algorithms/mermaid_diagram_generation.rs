@@ -20,7 +20,7 @@ impl MermaidDiagram {
             result.push_str("} ");
         }
 
-        fn is_fkey_nullable(fkey: &ForeignKeyInfo, tables: &Vec<TableInfo>) -> Result<bool> {
+        fn is_fkey_nullable(fkey: &ForeignKeyInfo, tables: &[TableInfo]) -> Result<bool> {
             let table = tables
                 .iter()
                 .find(|x| x.table_name == fkey.source_table)
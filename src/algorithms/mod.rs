@@ -1,3 +1,16 @@
+pub mod endpoint_bundle;
 pub mod endpoint_execution;
+pub mod endpoint_response;
+pub mod endpoint_validation;
+pub mod execution_limits;
+pub mod input_schema;
+pub mod json_diff;
+pub mod json_path;
 pub mod mermaid_diagram_generation;
+pub mod openapi;
+pub mod pagination;
+pub mod rate_limit;
+pub mod response_cache;
+pub mod route_pattern;
 pub mod sql_variable_parser;
+pub mod typescript_client;
@@ -0,0 +1,881 @@
+use crate::algorithms::{
+    endpoint_response::EndpointResponse,
+    execution_limits::ExecutionLimits,
+    input_schema::{FieldSchema, FieldType, InputSchema},
+    rate_limit::RateLimit,
+    route_pattern::RoutePattern,
+    sql_variable_parser::{Cardinality, EndpointInfo, NodeKind},
+};
+use crate::types::sql_value::SqlType;
+use serde_json::{json, Map, Value};
+
+/// Prefix of the paths of custom endpoints
+const ENDPOINT_PREFIX: &str = "/endpoint";
+
+const SECURITY_SCHEME: &str = "bearerAuth";
+
+/// Column of a query result, as described by postgres
+#[derive(Debug, Clone)]
+pub struct DescribedColumn {
+    pub name: String,
+    /// Type name reported by sqlx, e.g. `INT4` or `TEXT[]`
+    pub type_name: String,
+    /// `Some(false)` when postgres knows the column can't be null
+    pub nullable: Option<bool>,
+}
+
+/// Query of an endpoint tree with the columns it returns,
+/// `None` when the query couldn't be prepared
+#[derive(Debug)]
+pub struct DescribedQuery<'a> {
+    pub info: &'a EndpointInfo,
+    pub columns: Option<Vec<DescribedColumn>>,
+    pub children: Vec<DescribedQuery<'a>>,
+}
+
+/// Everything the document needs to know about one custom endpoint
+#[derive(Debug)]
+pub struct DocumentedEndpoint<'a> {
+    pub pattern: &'a RoutePattern,
+    /// Lowercase http methods, more than one for `ANY` endpoints
+    pub methods: Vec<String>,
+    pub allowed_groups: &'a [String],
+    pub response: &'a EndpointResponse,
+    pub input_schema: &'a InputSchema,
+    pub rate_limit: Option<&'a RateLimit>,
+    pub cache_ttl: Option<u32>,
+    /// Limits including the server defaults
+    pub limits: ExecutionLimits,
+    pub queries: Vec<DescribedQuery<'a>>,
+}
+
+/// Builds an OpenAPI 3 document of the custom endpoints
+pub fn openapi_document(endpoints: &[DocumentedEndpoint<'_>]) -> Value {
+    let mut paths = Map::new();
+
+    for endpoint in endpoints {
+        let path = format!("{}{}", ENDPOINT_PREFIX, endpoint.pattern.openapi_path());
+        let path_item = paths
+            .entry(path.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+
+        for method in &endpoint.methods {
+            path_item[method.as_str()] = operation(endpoint, method, &path);
+        }
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Custom endpoints",
+            "version": "1.0.0",
+        },
+        "paths": paths,
+        "components": {
+            "securitySchemes": {
+                SECURITY_SCHEME: {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "bearerFormat": "JWT",
+                },
+            },
+        },
+    })
+}
+
+fn operation(endpoint: &DocumentedEndpoint<'_>, method: &str, path: &str) -> Value {
+    let mut operation = Map::new();
+
+    operation.insert("operationId".into(), operation_id(method, path).into());
+
+    let mut parameters = endpoint
+        .pattern
+        .params()
+        .into_iter()
+        .map(|name| {
+            json!({
+                "name": name,
+                "in": "path",
+                "required": true,
+                "schema": {"type": "string"},
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let request = request_schema(endpoint);
+    let has_fields = request["properties"]
+        .as_object()
+        .is_some_and(|it| !it.is_empty());
+
+    if method == "get" {
+        // Query strings are parsed as forms, so only top-level values can be sent,
+        // inputs nested in objects can't be given to GET endpoints
+        let required = request["required"].as_array().cloned().unwrap_or_default();
+
+        if let Some(properties) = request["properties"].as_object() {
+            for (name, schema) in properties {
+                if matches!(schema["type"].as_str(), Some("object" | "array")) {
+                    continue;
+                }
+
+                parameters.push(json!({
+                    "name": name,
+                    "in": "query",
+                    "required": required.contains(&Value::String(name.clone())),
+                    "schema": schema,
+                }));
+            }
+        }
+    } else if has_fields {
+        // Only GET reads the query string, DELETE takes a body like the others
+        operation.insert(
+            "requestBody".into(),
+            json!({
+                "required": true,
+                "content": {
+                    "application/json": {"schema": request},
+                    "application/x-www-form-urlencoded": {"schema": request},
+                },
+            }),
+        );
+    }
+
+    if !parameters.is_empty() {
+        operation.insert("parameters".into(), parameters.into());
+    }
+
+    let is_public = endpoint.allowed_groups.iter().any(|it| it == "PUBLIC");
+    let uses_user = any_query(&endpoint.queries, &|query| {
+        query
+            .info
+            .variables
+            .iter()
+            .any(|it| it.starts_with("user."))
+    });
+
+    let security = match (is_public, uses_user) {
+        (true, false) => None,
+        // Anonymous callers get a 401 when a query needs the user
+        (true, true) => Some(json!([{}, {SECURITY_SCHEME: []}])),
+        (false, _) => Some(json!([{SECURITY_SCHEME: []}])),
+    };
+
+    if let Some(security) = security {
+        operation.insert("security".into(), security);
+    }
+
+    operation.insert(
+        "x-allowed-groups".into(),
+        json!(endpoint.allowed_groups.to_vec()),
+    );
+    operation.insert(
+        "responses".into(),
+        responses(endpoint, !is_public || uses_user).into(),
+    );
+
+    operation.into()
+}
+
+fn operation_id(method: &str, path: &str) -> String {
+    let parts = path
+        .split('/')
+        .filter(|it| !it.is_empty())
+        .map(|segment| match segment.strip_prefix('{') {
+            Some(param) => format!("by_{}", param.trim_end_matches('}')),
+            None => segment.replace(|c: char| !c.is_ascii_alphanumeric(), "_"),
+        })
+        .collect::<Vec<_>>();
+
+    format!("{}_{}", method, parts.join("_"))
+}
+
+fn any_query(
+    queries: &[DescribedQuery<'_>],
+    predicate: &dyn Fn(&DescribedQuery<'_>) -> bool,
+) -> bool {
+    queries
+        .iter()
+        .any(|query| predicate(query) || any_query(&query.children, predicate))
+}
+
+fn object_schema() -> Value {
+    json!({"type": "object", "properties": {}})
+}
+
+/// Adds a property to an object schema, creating the objects on the way.
+/// Existing keywords of the property are kept, so the input schema wins
+/// over types declared in the sql.
+fn insert_property(object: &mut Value, path: &[&str], schema: Value, required: bool) {
+    let (first, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+
+    // `items[0].id` documents `items` as an array
+    let (name, schema, rest) = match first.split_once('[') {
+        Some((name, _)) => (name, json!({"type": "array"}), &[][..]),
+        None => (*first, schema, rest),
+    };
+
+    let object = object.as_object_mut().unwrap();
+
+    if required {
+        // An empty `required` isn't valid, so the list is only added with its first name
+        let required_names = object.entry("required").or_insert_with(|| json!([]));
+        if !required_names
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|it| it == name)
+        {
+            required_names.as_array_mut().unwrap().push(name.into());
+        }
+    }
+
+    let property = object["properties"]
+        .as_object_mut()
+        .unwrap()
+        .entry(name)
+        .or_insert_with(|| Value::Object(Map::new()));
+
+    if rest.is_empty() {
+        merge_keywords(property, schema);
+    } else {
+        merge_keywords(property, object_schema());
+        insert_property(property, rest, schema, required);
+    }
+}
+
+fn merge_keywords(property: &mut Value, schema: Value) {
+    if let (Some(property), Value::Object(schema)) = (property.as_object_mut(), schema) {
+        for (key, value) in schema {
+            property.entry(key).or_insert(value);
+        }
+    }
+}
+
+fn input_field_schema(field: &FieldSchema) -> Value {
+    let mut schema = Map::new();
+
+    if let Some(field_type) = field.field_type {
+        schema.insert("type".into(), serde_json::to_value(field_type).unwrap());
+    }
+
+    let is_array = field.field_type == Some(FieldType::Array);
+    let (min_length, max_length) = if is_array {
+        ("minItems", "maxItems")
+    } else {
+        ("minLength", "maxLength")
+    };
+
+    let keywords = [
+        ("minimum", field.min.map(Value::from)),
+        ("maximum", field.max.map(Value::from)),
+        (min_length, field.min_length.map(Value::from)),
+        (max_length, field.max_length.map(Value::from)),
+        ("pattern", field.pattern.clone().map(Value::from)),
+        ("enum", field.allowed_values.clone().map(Value::from)),
+    ];
+
+    for (keyword, value) in keywords {
+        if let Some(value) = value {
+            schema.insert(keyword.into(), value);
+        }
+    }
+
+    schema.into()
+}
+
+fn sql_type_schema(sql_type: &SqlType) -> Value {
+    match sql_type {
+        SqlType::Bool => json!({"type": "boolean"}),
+        SqlType::SmallInt | SqlType::Int => json!({"type": "integer", "format": "int32"}),
+        SqlType::BigInt => json!({"type": "integer", "format": "int64"}),
+        SqlType::Real | SqlType::Double | SqlType::Numeric => json!({"type": "number"}),
+        SqlType::Text | SqlType::Time => json!({"type": "string"}),
+        SqlType::Uuid => json!({"type": "string", "format": "uuid"}),
+        SqlType::Date => json!({"type": "string", "format": "date"}),
+        SqlType::Timestamp | SqlType::Timestamptz => {
+            json!({"type": "string", "format": "date-time"})
+        }
+        SqlType::Json | SqlType::Jsonb => json!({}),
+        SqlType::Array(element) => json!({"type": "array", "items": sql_type_schema(element)}),
+    }
+}
+
+/// Request keys read by the queries and the input schema
+fn request_schema(endpoint: &DocumentedEndpoint<'_>) -> Value {
+    let mut schema = object_schema();
+
+    for (field, field_schema) in &endpoint.input_schema.fields {
+        let path = field.split('.').collect::<Vec<_>>();
+        insert_property(
+            &mut schema,
+            &path,
+            input_field_schema(field_schema),
+            field_schema.required,
+        );
+    }
+
+    add_query_variables(&mut schema, &endpoint.queries);
+    schema
+}
+
+fn add_query_variables(schema: &mut Value, queries: &[DescribedQuery<'_>]) {
+    for query in queries {
+        let info = query.info;
+
+        for (i, variable) in info.variables.iter().enumerate() {
+            if let Some(key) = variable.strip_prefix("req.") {
+                let variable_schema = match info.variable_types.get(i) {
+                    Some(Some(sql_type)) => sql_type_schema(sql_type),
+                    _ => json!({}),
+                };
+                let path = key.split('.').collect::<Vec<_>>();
+                // A missing key fails the query, unless it's only read in a condition branch
+                insert_property(schema, &path, variable_schema, true);
+            }
+        }
+
+        if let Some(pagination) = &info.pagination {
+            insert_property(
+                schema,
+                &[&pagination.limit_param],
+                json!({"type": "integer", "minimum": 1, "maximum": pagination.max_page_size}),
+                false,
+            );
+            insert_property(
+                schema,
+                &[&pagination.cursor_param],
+                json!({"type": "string"}),
+                false,
+            );
+        }
+
+        add_query_variables(schema, &query.children);
+    }
+}
+
+fn pg_type_schema(type_name: &str) -> Value {
+    if let Some(element) = type_name.strip_suffix("[]") {
+        return json!({"type": "array", "items": pg_type_schema(element)});
+    }
+
+    match type_name {
+        "BOOL" => json!({"type": "boolean"}),
+        "INT2" | "INT4" => json!({"type": "integer", "format": "int32"}),
+        "INT8" => json!({"type": "integer", "format": "int64"}),
+        "FLOAT4" | "FLOAT8" | "NUMERIC" => json!({"type": "number"}),
+        "DATE" => json!({"type": "string", "format": "date"}),
+        "TIMESTAMP" | "TIMESTAMPTZ" => json!({"type": "string", "format": "date-time"}),
+        "UUID" => json!({"type": "string", "format": "uuid"}),
+        "JSON" | "JSONB" => json!({}),
+        // Text types, times and enum labels
+        _ => json!({"type": "string"}),
+    }
+}
+
+fn column_schema(column: &DescribedColumn) -> Value {
+    let mut schema = pg_type_schema(&column.type_name);
+
+    if column.nullable != Some(false) {
+        schema["nullable"] = true.into();
+    }
+
+    schema
+}
+
+fn visible_columns<'a>(query: &'a DescribedQuery<'_>) -> impl Iterator<Item = &'a DescribedColumn> {
+    query
+        .columns
+        .iter()
+        .flatten()
+        .filter(|it| !it.name.starts_with("private_"))
+}
+
+/// Results of the queries of one level of the tree, keyed by query names
+fn level_schema(queries: &[DescribedQuery<'_>]) -> Value {
+    let mut schema = object_schema();
+    add_level_properties(&mut schema, queries, true);
+    schema
+}
+
+fn add_level_properties(schema: &mut Value, queries: &[DescribedQuery<'_>], required: bool) {
+    for query in queries {
+        match query.info.kind {
+            NodeKind::Query => {}
+            NodeKind::Guard { .. } => continue,
+            // Results of the children are only there when the condition holds
+            NodeKind::Condition => {
+                add_level_properties(schema, &query.children, false);
+                continue;
+            }
+        }
+
+        // Empty lists are left out of the response
+        let always_present =
+            query.info.pagination.is_some() || query.info.cardinality != Cardinality::Many;
+
+        insert_property(
+            schema,
+            &[&query.info.name],
+            node_schema(query),
+            required && always_present,
+        );
+    }
+}
+
+fn node_schema(query: &DescribedQuery<'_>) -> Value {
+    let row = row_schema(query);
+
+    if query.info.pagination.is_some() {
+        return json!({
+            "type": "object",
+            "properties": {
+                "rows": {"type": "array", "items": row},
+                "next_cursor": {"type": "string", "nullable": true},
+            },
+            "required": ["rows", "next_cursor"],
+        });
+    }
+
+    match query.info.cardinality {
+        Cardinality::Many => json!({"type": "array", "items": row}),
+        Cardinality::One => row,
+        Cardinality::OptionalOne => {
+            let mut row = row;
+            row["nullable"] = true.into();
+            row
+        }
+        Cardinality::Scalar => {
+            let mut schema = visible_columns(query)
+                .next()
+                .map_or_else(|| json!({}), column_schema);
+            schema["nullable"] = true.into();
+            schema
+        }
+        Cardinality::AffectedRows => json!({"type": "integer", "format": "int64"}),
+    }
+}
+
+fn row_schema(query: &DescribedQuery<'_>) -> Value {
+    let mut data = object_schema();
+
+    if query.columns.is_none() {
+        data["description"] = "The query couldn't be prepared, its columns are unknown".into();
+    }
+
+    for column in visible_columns(query) {
+        insert_property(&mut data, &[&column.name], column_schema(column), true);
+    }
+
+    if query.info.flatten {
+        add_level_properties(&mut data, &query.children, true);
+        return data;
+    }
+
+    json!({
+        "type": "object",
+        "properties": {
+            "data": data,
+            "children": level_schema(&query.children),
+        },
+        "required": ["data", "children"],
+    })
+}
+
+fn responses(endpoint: &DocumentedEndpoint<'_>, needs_token: bool) -> Map<String, Value> {
+    let mut responses = Map::new();
+    let status = endpoint.response.status_code();
+
+    let mut success = json!({
+        "description": status.canonical_reason().unwrap_or("Success"),
+    });
+
+    if endpoint.response.has_body() {
+        success["content"] = json!({
+            "application/json": {"schema": level_schema(&endpoint.queries)},
+        });
+    }
+
+    if endpoint.response.location.is_some() {
+        success["headers"]["Location"] = json!({"schema": {"type": "string"}});
+    }
+
+    if let Some(ttl) = endpoint.cache_ttl {
+        success["headers"]["ETag"] = json!({"schema": {"type": "string"}});
+        success["headers"]["Cache-Control"] = json!({
+            "description": format!("Responses are cached for {} seconds", ttl),
+            "schema": {"type": "string"},
+        });
+    }
+
+    responses.insert(status.as_str().into(), success);
+
+    if endpoint.cache_ttl.is_some() {
+        responses.insert(
+            "304".into(),
+            json!({"description": "The response didn't change since the `If-None-Match` ETag"}),
+        );
+    }
+    responses.insert(
+        "400".into(),
+        json!({"description": "The request couldn't be parsed"}),
+    );
+
+    if needs_token {
+        responses.insert(
+            "401".into(),
+            json!({"description": "The caller isn't allowed to call the endpoint"}),
+        );
+    }
+
+    if any_query(&endpoint.queries, &|query| {
+        query.info.kind == NodeKind::Query && query.info.cardinality == Cardinality::One
+    }) {
+        responses.insert("404".into(), json!({"description": "A row wasn't found"}));
+    }
+
+    add_guard_responses(&mut responses, &endpoint.queries);
+
+    if !endpoint.input_schema.fields.is_empty() {
+        responses.insert(
+            "422".into(),
+            json!({
+                "description": "The request doesn't match the input schema",
+                "content": {
+                    "application/json": {
+                        "schema": {
+                            "type": "object",
+                            "properties": {
+                                "errors": {
+                                    "type": "array",
+                                    "items": {
+                                        "type": "object",
+                                        "properties": {
+                                            "field": {"type": "string"},
+                                            "message": {"type": "string"},
+                                        },
+                                        "required": ["field", "message"],
+                                    },
+                                },
+                            },
+                            "required": ["errors"],
+                        },
+                    },
+                },
+            }),
+        );
+    }
+
+    if endpoint.limits.max_rows.is_some() || endpoint.limits.max_response_bytes.is_some() {
+        responses.insert(
+            "413".into(),
+            json!({"description": "The response exceeds the row or size limit"}),
+        );
+    }
+
+    if let Some(timeout) = endpoint.limits.statement_timeout_ms {
+        responses.insert(
+            "504".into(),
+            json!({"description": format!("A statement ran longer than {} ms", timeout)}),
+        );
+    }
+
+    if let Some(rate_limit) = endpoint.rate_limit {
+        responses.insert(
+            "429".into(),
+            json!({
+                "description": format!(
+                    "More than {} calls in {} seconds",
+                    rate_limit.requests, rate_limit.per_seconds
+                ),
+                "headers": {
+                    "Retry-After": {
+                        "description": "Seconds until the endpoint can be called again",
+                        "schema": {"type": "integer"},
+                    },
+                },
+            }),
+        );
+    }
+
+    responses
+}
+
+/// Guards document their status with their message, messages sharing a status are joined
+fn add_guard_responses(responses: &mut Map<String, Value>, queries: &[DescribedQuery<'_>]) {
+    for query in queries {
+        if let NodeKind::Guard { status, message } = &query.info.kind {
+            let response = responses
+                .entry(status.to_string())
+                .or_insert_with(|| json!({"description": ""}));
+
+            let description = match response["description"].as_str() {
+                Some("") | None => message.clone(),
+                Some(existing) => format!("{}; {}", existing, message),
+            };
+            response["description"] = description.into();
+        }
+
+        add_guard_responses(responses, &query.children);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::sql_variable_parser::EndpointInfoCreateRequest;
+
+    fn column(name: &str, type_name: &str, nullable: Option<bool>) -> DescribedColumn {
+        DescribedColumn {
+            name: name.into(),
+            type_name: type_name.into(),
+            nullable,
+        }
+    }
+
+    fn info(
+        name: &str,
+        sql: &str,
+        children: Vec<EndpointInfoCreateRequest>,
+    ) -> EndpointInfoCreateRequest {
+        EndpointInfoCreateRequest {
+            name: name.into(),
+            sql: sql.into(),
+            kind: NodeKind::Query,
+            pagination: None,
+            cardinality: Cardinality::Many,
+            flatten: false,
+            children,
+        }
+    }
+
+    #[test]
+    fn documenting_endpoints() {
+        let mut user = info(
+            "user",
+            "SELECT id, name, id AS private_id FROM users WHERE id = ${path.id:int}",
+            vec![info(
+                "posts",
+                "SELECT title FROM posts WHERE user_fk = ${super.private_id} AND title LIKE ${req.search} AND kind = ${req.filter.kind}",
+                vec![],
+            )],
+        );
+        user.cardinality = Cardinality::One;
+        let mut guard = info("guard", "SELECT ${req.token:text} = 'x'", vec![]);
+        guard.kind = NodeKind::Guard {
+            status: 403,
+            message: "Bad token".into(),
+        };
+        let infos = [
+            EndpointInfo::from_request(guard).unwrap(),
+            EndpointInfo::from_request(user).unwrap(),
+        ];
+
+        let pattern = RoutePattern::parse("users/:id").unwrap();
+        let allowed_groups = vec!["WRITER".to_owned()];
+        let response = EndpointResponse::default();
+        let input_schema: InputSchema = serde_json::from_value(json!({
+            "search": {"required": true, "type": "string", "max_length": 10},
+        }))
+        .unwrap();
+
+        let endpoint = DocumentedEndpoint {
+            pattern: &pattern,
+            methods: vec!["get".into()],
+            allowed_groups: &allowed_groups,
+            response: &response,
+            input_schema: &input_schema,
+            rate_limit: None,
+            cache_ttl: Some(60),
+            limits: ExecutionLimits {
+                statement_timeout_ms: Some(1000),
+                max_rows: Some(100),
+                max_response_bytes: None,
+            },
+            queries: vec![
+                DescribedQuery {
+                    info: &infos[0],
+                    columns: Some(vec![column("?column?", "BOOL", None)]),
+                    children: vec![],
+                },
+                DescribedQuery {
+                    info: &infos[1],
+                    columns: Some(vec![
+                        column("id", "INT4", Some(false)),
+                        column("name", "TEXT", None),
+                        column("private_id", "INT4", Some(false)),
+                    ]),
+                    children: vec![DescribedQuery {
+                        info: &infos[1].children[0],
+                        columns: Some(vec![column("title", "TEXT", Some(false))]),
+                        children: vec![],
+                    }],
+                },
+            ],
+        };
+
+        let document = openapi_document(&[endpoint]);
+        let operation = &document["paths"]["/endpoint/users/{id}"]["get"];
+
+        assert_eq!(operation["operationId"], "get_endpoint_users_by_id");
+        assert_eq!(operation["security"], json!([{"bearerAuth": []}]));
+        // filter.kind can't be sent in a query string
+        assert_eq!(
+            operation["parameters"],
+            json!([
+                {"name": "id", "in": "path", "required": true, "schema": {"type": "string"}},
+                {
+                    "name": "search",
+                    "in": "query",
+                    "required": true,
+                    "schema": {"type": "string", "maxLength": 10},
+                },
+                {
+                    "name": "token",
+                    "in": "query",
+                    "required": true,
+                    "schema": {"type": "string"},
+                },
+            ])
+        );
+
+        let responses = &operation["responses"];
+        assert_eq!(responses["403"]["description"], "Bad token");
+        assert!(responses["404"].is_object());
+        assert!(responses["422"].is_object());
+        assert!(responses.get("429").is_none());
+        assert!(responses["304"].is_object());
+        assert!(responses["413"].is_object());
+        assert_eq!(
+            responses["504"]["description"],
+            "A statement ran longer than 1000 ms"
+        );
+        assert!(responses["200"]["headers"]["ETag"].is_object());
+        assert_eq!(
+            responses["200"]["content"]["application/json"]["schema"],
+            json!({
+                "type": "object",
+                "properties": {
+                    "user": {
+                        "type": "object",
+                        "properties": {
+                            "data": {
+                                "type": "object",
+                                "properties": {
+                                    "id": {"type": "integer", "format": "int32"},
+                                    "name": {"type": "string", "nullable": true},
+                                },
+                                "required": ["id", "name"],
+                            },
+                            "children": {
+                                "type": "object",
+                                "properties": {
+                                    "posts": {
+                                        "type": "array",
+                                        "items": {
+                                            "type": "object",
+                                            "properties": {
+                                                "data": {
+                                                    "type": "object",
+                                                    "properties": {"title": {"type": "string"}},
+                                                    "required": ["title"],
+                                                },
+                                                "children": {"type": "object", "properties": {}},
+                                            },
+                                            "required": ["data", "children"],
+                                        },
+                                    },
+                                },
+                            },
+                        },
+                        "required": ["data", "children"],
+                    },
+                },
+                "required": ["user"],
+            })
+        );
+    }
+
+    #[test]
+    fn documenting_request_bodies() {
+        let mut posts = info(
+            "posts",
+            "SELECT id, title FROM posts WHERE user_fk = ${req.author.id:int}",
+            vec![],
+        );
+        posts.pagination = Some(serde_json::from_value(json!({"order_by": "id"})).unwrap());
+        posts.flatten = true;
+        let infos = [EndpointInfo::from_request(posts).unwrap()];
+
+        let pattern = RoutePattern::parse("posts").unwrap();
+        let allowed_groups = vec!["PUBLIC".to_owned()];
+        let response = EndpointResponse {
+            status: Some(201),
+            location: Some("/endpoint/posts".into()),
+        };
+        let input_schema = InputSchema::default();
+        let rate_limit = RateLimit {
+            requests: 10,
+            per_seconds: 60,
+            burst: None,
+        };
+
+        let endpoint = DocumentedEndpoint {
+            pattern: &pattern,
+            methods: vec!["post".into(), "delete".into()],
+            allowed_groups: &allowed_groups,
+            response: &response,
+            input_schema: &input_schema,
+            rate_limit: Some(&rate_limit),
+            cache_ttl: None,
+            limits: ExecutionLimits::default(),
+            queries: vec![DescribedQuery {
+                info: &infos[0],
+                columns: None,
+                children: vec![],
+            }],
+        };
+
+        let document = openapi_document(&[endpoint]);
+        let operation = &document["paths"]["/endpoint/posts"]["post"];
+
+        assert!(operation.get("security").is_none());
+        assert!(operation["responses"].get("401").is_none());
+        assert!(operation["responses"].get("504").is_none());
+        assert_eq!(
+            operation["responses"]["429"]["description"],
+            "More than 10 calls in 60 seconds"
+        );
+        assert_eq!(
+            operation["requestBody"]["content"]["application/json"]["schema"],
+            json!({
+                "type": "object",
+                "properties": {
+                    "author": {
+                        "type": "object",
+                        "properties": {"id": {"type": "integer", "format": "int32"}},
+                        "required": ["id"],
+                    },
+                    "limit": {"type": "integer", "minimum": 1, "maximum": 100},
+                    "cursor": {"type": "string"},
+                },
+                "required": ["author"],
+            })
+        );
+
+        // the server reads the body of DELETE requests, not their query string
+        let delete = &document["paths"]["/endpoint/posts"]["delete"];
+        assert!(delete.get("parameters").is_none());
+        assert_eq!(delete["requestBody"], operation["requestBody"]);
+
+        let success = &operation["responses"]["201"];
+        assert!(success["headers"]["Location"].is_object());
+        assert_eq!(
+            success["content"]["application/json"]["schema"]["properties"]["posts"]["properties"]
+                ["rows"]["items"]["description"],
+            "The query couldn't be prepared, its columns are unknown"
+        );
+    }
+}
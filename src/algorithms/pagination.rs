@@ -0,0 +1,173 @@
+use crate::types::sql_value::{SqlType, SqlValue};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+fn default_page_size() -> u32 {
+    20
+}
+
+fn max_page_size() -> u32 {
+    100
+}
+
+fn limit_param() -> String {
+    "limit".into()
+}
+
+fn cursor_param() -> String {
+    "cursor".into()
+}
+
+/// Keyset pagination of a top-level query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pagination {
+    /// Column returned by the query, unique and not null
+    pub order_by: String,
+    #[serde(default)]
+    pub descending: bool,
+    /// Type the cursor is converted to, needed when the column
+    /// isn't a number or text, e.g. a timestamp
+    #[serde(default)]
+    pub order_type: Option<SqlType>,
+    #[serde(default = "default_page_size")]
+    pub default_page_size: u32,
+    #[serde(default = "max_page_size")]
+    pub max_page_size: u32,
+    /// Request keys holding the page size and the cursor
+    #[serde(default = "limit_param")]
+    pub limit_param: String,
+    #[serde(default = "cursor_param")]
+    pub cursor_param: String,
+}
+
+impl Pagination {
+    /// Page size requested by the client, at most `max_page_size`
+    pub fn limit(&self, request: &Value) -> Result<u32> {
+        let limit = match request.get(&self.limit_param) {
+            None | Some(Value::Null) => return Ok(self.default_page_size.min(self.max_page_size)),
+            Some(Value::Number(n)) => n.as_u64(),
+            Some(Value::String(s)) => s.trim().parse().ok(),
+            Some(_) => None,
+        };
+
+        match limit {
+            Some(limit) if limit > 0 => Ok(limit.min(self.max_page_size as u64) as u32),
+            _ => Err(anyhow!("{} must be a positive number", self.limit_param)),
+        }
+    }
+
+    /// Value of the order column after which the page starts
+    pub fn cursor(&self, request: &Value) -> Result<Option<SqlValue>> {
+        let cursor = match request.get(&self.cursor_param) {
+            None | Some(Value::Null) => return Ok(None),
+            Some(Value::String(s)) if s.is_empty() => return Ok(None),
+            Some(Value::String(s)) => decode_cursor(s),
+            Some(_) => None,
+        }
+        .ok_or_else(|| anyhow!("Bad {}", self.cursor_param))?;
+
+        let value = match &self.order_type {
+            Some(ty) => SqlValue::convert(cursor, ty)?,
+            None => SqlValue::from_json(cursor),
+        };
+
+        Ok(Some(value))
+    }
+
+    /// Wraps the query, `$cursor` and `$limit` are the numbers of the added parameters
+    pub fn paginated_sql(&self, parsed_sql: &str, cursor: Option<usize>, limit: usize) -> String {
+        let column = format!("__b_page.\"{}\"", self.order_by.replace('"', "\"\""));
+        let (comparison, direction) = if self.descending {
+            ("<", "DESC")
+        } else {
+            (">", "ASC")
+        };
+
+        let condition = match cursor {
+            Some(n) => format!(" WHERE {} {} ${}", column, comparison, n),
+            None => String::new(),
+        };
+
+        format!(
+            "SELECT * FROM (\n{}\n) AS __b_page{} ORDER BY {} {} LIMIT ${}",
+            parsed_sql.trim_end().trim_end_matches(';'),
+            condition,
+            column,
+            direction,
+            limit
+        )
+    }
+}
+
+pub fn encode_cursor(value: &Value) -> String {
+    base64::encode_config(value.to_string(), base64::URL_SAFE_NO_PAD)
+}
+
+fn decode_cursor(cursor: &str) -> Option<Value> {
+    let bytes = base64::decode_config(cursor, base64::URL_SAFE_NO_PAD).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn pagination() -> Pagination {
+        serde_json::from_value(json!({"order_by": "id"})).unwrap()
+    }
+
+    #[test]
+    fn limits() {
+        let pagination = pagination();
+
+        assert_eq!(pagination.limit(&json!({})).unwrap(), 20);
+        assert_eq!(pagination.limit(&json!({"limit": 5})).unwrap(), 5);
+        assert_eq!(pagination.limit(&json!({"limit": "7"})).unwrap(), 7);
+        assert_eq!(pagination.limit(&json!({"limit": 5000})).unwrap(), 100);
+
+        assert!(pagination.limit(&json!({"limit": 0})).is_err());
+        assert!(pagination.limit(&json!({"limit": -3})).is_err());
+        assert!(pagination.limit(&json!({"limit": "many"})).is_err());
+    }
+
+    #[test]
+    fn cursors() {
+        let mut pagination = pagination();
+
+        let cursor = encode_cursor(&json!(41));
+        assert_eq!(
+            pagination.cursor(&json!({ "cursor": cursor })).unwrap(),
+            Some(SqlValue::BigInt(41))
+        );
+        assert_eq!(pagination.cursor(&json!({})).unwrap(), None);
+        assert_eq!(pagination.cursor(&json!({"cursor": ""})).unwrap(), None);
+        assert!(pagination.cursor(&json!({"cursor": "???"})).is_err());
+
+        pagination.order_type = Some(SqlType::Date);
+        let cursor = encode_cursor(&json!("2021-12-01"));
+        assert!(matches!(
+            pagination.cursor(&json!({ "cursor": cursor })).unwrap(),
+            Some(SqlValue::Date(_))
+        ));
+    }
+
+    #[test]
+    fn sql() {
+        let mut pagination = pagination();
+
+        assert_eq!(
+            pagination.paginated_sql("select * from posts where user_fk = $1;", Some(2), 3),
+            "SELECT * FROM (\nselect * from posts where user_fk = $1\n) AS __b_page \
+            WHERE __b_page.\"id\" > $2 ORDER BY __b_page.\"id\" ASC LIMIT $3"
+        );
+
+        pagination.descending = true;
+        assert_eq!(
+            pagination.paginated_sql("select * from posts", None, 1),
+            "SELECT * FROM (\nselect * from posts\n) AS __b_page \
+            ORDER BY __b_page.\"id\" DESC LIMIT $1"
+        );
+    }
+}
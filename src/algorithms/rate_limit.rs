@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// How often an endpoint may be called, by each user or, for anonymous callers, each IP.
+///
+/// Calls take tokens from a bucket holding up to `burst` tokens,
+/// which is refilled with `requests` tokens every `per_seconds`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub per_seconds: u32,
+    /// Calls allowed at once after a quiet period, `requests` when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u32>,
+}
+
+impl RateLimit {
+    pub fn capacity(&self) -> f64 {
+        self.burst.unwrap_or(self.requests) as f64
+    }
+
+    /// Tokens added per second
+    pub fn refill_rate(&self) -> f64 {
+        self.requests as f64 / self.per_seconds as f64
+    }
+
+    /// Problems with the settings
+    pub fn check(&self) -> Vec<String> {
+        let mut messages = Vec::new();
+
+        if self.requests == 0 {
+            messages.push("requests must be at least 1".to_owned());
+        }
+        if self.per_seconds == 0 {
+            messages.push("per_seconds must be at least 1".to_owned());
+        }
+        if self.burst == Some(0) {
+            messages.push("burst must be at least 1".to_owned());
+        }
+
+        messages
+    }
+}
+
+/// State of the bucket of one caller, `updated_at` is in seconds since any fixed point
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenBucket {
+    pub tokens: f64,
+    pub updated_at: f64,
+}
+
+impl TokenBucket {
+    pub fn full(limit: &RateLimit, now: f64) -> Self {
+        Self {
+            tokens: limit.capacity(),
+            updated_at: now,
+        }
+    }
+
+    /// Takes a token for a call, when the bucket is empty
+    /// returns how long to wait until a token is available
+    pub fn take(&mut self, limit: &RateLimit, now: f64) -> Result<(), Duration> {
+        let elapsed = (now - self.updated_at).max(0.0);
+        self.tokens = (self.tokens + elapsed * limit.refill_rate()).min(limit.capacity());
+        self.updated_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            let missing = 1.0 - self.tokens;
+            Err(Duration::from_secs_f64(missing / limit.refill_rate()))
+        }
+    }
+
+    /// When the bucket is full again, after which it can be forgotten
+    pub fn refilled_at(&self, limit: &RateLimit) -> f64 {
+        self.updated_at + (limit.capacity() - self.tokens).max(0.0) / limit.refill_rate()
+    }
+}
+
+/// Value of the `Retry-After` header, in whole seconds
+pub fn retry_after_seconds(wait: Duration) -> u64 {
+    wait.as_secs_f64().ceil().max(1.0) as u64
+}
+
+/// Address of the client in a header like `X-Forwarded-For`, to which each proxy appends
+/// the address it got the request from. Only the last one, added by the proxy
+/// in front of the server, can be trusted, the others are sent by the client.
+pub fn forwarded_ip(header: &str) -> Option<IpAddr> {
+    header.rsplit(',').next()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn taking_tokens() {
+        let limit = RateLimit {
+            requests: 2,
+            per_seconds: 10,
+            burst: Some(3),
+        };
+        let mut bucket = TokenBucket::full(&limit, 100.0);
+
+        for _ in 0..3 {
+            assert_eq!(bucket.take(&limit, 100.0), Ok(()));
+        }
+        assert_eq!(bucket.take(&limit, 100.0), Err(Duration::from_secs(5)));
+
+        // Half a token was refilled
+        assert_eq!(
+            bucket.take(&limit, 102.5),
+            Err(Duration::from_secs_f64(2.5))
+        );
+        assert_eq!(bucket.take(&limit, 105.0), Ok(()));
+
+        assert_eq!(bucket.refilled_at(&limit), 120.0);
+
+        // Never more than the burst
+        for _ in 0..3 {
+            assert_eq!(bucket.take(&limit, 1000.0), Ok(()));
+        }
+        assert!(bucket.take(&limit, 1000.0).is_err());
+
+        assert_eq!(retry_after_seconds(Duration::from_secs_f64(2.1)), 3);
+        assert_eq!(retry_after_seconds(Duration::from_millis(10)), 1);
+    }
+
+    #[test]
+    fn checking_settings() {
+        let limit = RateLimit {
+            requests: 0,
+            per_seconds: 60,
+            burst: Some(0),
+        };
+
+        assert_eq!(
+            limit.check(),
+            vec!["requests must be at least 1", "burst must be at least 1"]
+        );
+    }
+
+    #[test]
+    fn forwarded_ips() {
+        assert_eq!(
+            forwarded_ip("203.0.113.7, 10.0.0.2"),
+            Some("10.0.0.2".parse().unwrap())
+        );
+        assert_eq!(
+            forwarded_ip("2001:db8::1"),
+            Some("2001:db8::1".parse().unwrap())
+        );
+        assert_eq!(forwarded_ip("10.0.0.2, unknown"), None);
+        assert_eq!(forwarded_ip(""), None);
+    }
+}
@@ -0,0 +1,73 @@
+use crate::algorithms::sql_variable_parser::EndpointInfo;
+use sha2::{Digest, Sha256};
+
+/// Strong `ETag` of a response body, the first 128 bits of its SHA-256,
+/// so tags stay the same across restarts, instances and versions of the server
+pub fn etag(body: &str) -> String {
+    let hash = Sha256::digest(body.as_bytes());
+    let hex = hash[..16]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    format!("\"{}\"", hex)
+}
+
+/// Whether some query reads `${user.}` variables, then responses differ between
+/// users of the same group and can only be reused for the same user
+pub fn uses_user_variables(endpoint_infos: &[EndpointInfo]) -> bool {
+    endpoint_infos.iter().any(|info| {
+        info.variables.iter().any(|it| it.starts_with("user."))
+            || uses_user_variables(&info.children)
+    })
+}
+
+/// Part of the cache key identifying who sees the response, `username` is `None`
+/// for callers without a token. Responses are shared by the users of a group,
+/// unless they are `per_user`, then only callers without a token share them.
+pub fn cache_caller(username: Option<&str>, group: &str, per_user: bool) -> String {
+    match username {
+        Some(username) if per_user => format!("user:{}", username),
+        _ => format!("group:{}", group),
+    }
+}
+
+/// Key of a cached response, `caller` is the user group or the username
+pub fn cache_key(path: &str, caller: &str, arguments: &serde_json::Value) -> String {
+    // Keys of json objects are sorted, so equal arguments give equal keys
+    format!("{}\n{}\n{}", path, caller, arguments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn etags_and_keys() {
+        let tag = etag(r#"{"rows":[]}"#);
+
+        assert_eq!(tag, etag(r#"{"rows":[]}"#));
+        assert_ne!(tag, etag(r#"{"rows":[1]}"#));
+        assert!(tag.parse::<headers::ETag>().is_ok());
+        assert_eq!(etag(""), "\"e3b0c44298fc1c149afbf4c8996fb924\"");
+
+        assert_eq!(
+            cache_key("stats/1", "WRITER", &json!({"b": 1, "a": 2})),
+            "stats/1\nWRITER\n{\"a\":2,\"b\":1}"
+        );
+    }
+
+    #[test]
+    fn users_of_one_group() {
+        let alice = cache_caller(Some("alice"), "WRITER", false);
+        assert_eq!(alice, cache_caller(Some("bob"), "WRITER", false));
+        assert_eq!(alice, "group:WRITER");
+
+        // like with row level security, where policies can read the username
+        let alice = cache_caller(Some("alice"), "WRITER", true);
+        assert_ne!(alice, cache_caller(Some("bob"), "WRITER", true));
+        assert_eq!(alice, "user:alice");
+
+        assert_eq!(cache_caller(None, "PUBLIC", true), "group:PUBLIC");
+    }
+}
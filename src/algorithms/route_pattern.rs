@@ -0,0 +1,190 @@
+use anyhow::{anyhow, Result};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+enum RouteSegment {
+    Static(String),
+    Param(String),
+}
+
+/// Path of a custom endpoint, e.g. `users/:id/posts/:post_id`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoutePattern {
+    segments: Vec<RouteSegment>,
+}
+
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|it| !it.is_empty())
+}
+
+impl RoutePattern {
+    pub fn parse(path: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+
+        for segment in split_path(path) {
+            match segment.strip_prefix(':') {
+                Some(name) => {
+                    if name.is_empty()
+                        || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                    {
+                        return Err(anyhow!("Bad path parameter name :{} in {}", name, path));
+                    }
+
+                    if segments.contains(&RouteSegment::Param(name.to_owned())) {
+                        return Err(anyhow!("Path parameter :{} used twice in {}", name, path));
+                    }
+
+                    segments.push(RouteSegment::Param(name.to_owned()));
+                }
+                None => segments.push(RouteSegment::Static(segment.to_owned())),
+            }
+        }
+
+        Ok(Self { segments })
+    }
+
+    /// Returns the values of path parameters if the path matches the pattern
+    pub fn matches(&self, path: &str) -> Option<HashMap<String, String>> {
+        let parts = split_path(path).collect::<Vec<_>>();
+
+        if parts.len() != self.segments.len() {
+            return None;
+        }
+
+        let mut params = HashMap::new();
+
+        for (segment, part) in self.segments.iter().zip(parts) {
+            match segment {
+                RouteSegment::Static(s) if s == part => {}
+                RouteSegment::Static(_) => return None,
+                RouteSegment::Param(name) => {
+                    params.insert(name.clone(), part.to_owned());
+                }
+            }
+        }
+
+        Some(params)
+    }
+
+    /// Orders patterns matching the same path, more specific first.
+    ///
+    /// The first segment where the patterns differ decides,
+    /// a static segment wins over a parameter.
+    pub fn precedence(&self, other: &Self) -> Ordering {
+        for (a, b) in self.segments.iter().zip(&other.segments) {
+            match (a, b) {
+                (RouteSegment::Static(_), RouteSegment::Param(_)) => return Ordering::Less,
+                (RouteSegment::Param(_), RouteSegment::Static(_)) => return Ordering::Greater,
+                _ => {}
+            }
+        }
+
+        Ordering::Equal
+    }
+
+    /// Names of the path parameters, in order
+    pub fn params(&self) -> Vec<&str> {
+        self.segments
+            .iter()
+            .filter_map(|segment| match segment {
+                RouteSegment::Param(name) => Some(name.as_str()),
+                RouteSegment::Static(_) => None,
+            })
+            .collect()
+    }
+
+    /// Path in OpenAPI notation, e.g. `/users/{id}/posts`
+    pub fn openapi_path(&self) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                RouteSegment::Static(s) => format!("/{}", s),
+                RouteSegment::Param(name) => format!("/{{{}}}", name),
+            })
+            .collect()
+    }
+
+    /// Two patterns conflict when they match exactly the same paths,
+    /// so neither of them can take precedence.
+    pub fn conflicts_with(&self, other: &Self) -> bool {
+        self.segments.len() == other.segments.len()
+            && self
+                .segments
+                .iter()
+                .zip(&other.segments)
+                .all(|pair| match pair {
+                    (RouteSegment::Static(a), RouteSegment::Static(b)) => a == b,
+                    (RouteSegment::Param(_), RouteSegment::Param(_)) => true,
+                    _ => false,
+                })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maplit::hashmap;
+
+    #[test]
+    fn matching() {
+        let pattern = RoutePattern::parse("users/:id/posts/:post_id").unwrap();
+
+        assert_eq!(
+            pattern.matches("/users/42/posts/7"),
+            Some(hashmap! {
+                "id".to_owned() => "42".to_owned(),
+                "post_id".to_owned() => "7".to_owned(),
+            })
+        );
+        assert_eq!(pattern.matches("/users/42/comments/7"), None);
+        assert_eq!(pattern.matches("/users/42/posts"), None);
+        assert_eq!(pattern.matches("/users/42/posts/7/likes"), None);
+    }
+
+    #[test]
+    fn slashes_are_ignored() {
+        let pattern = RoutePattern::parse("/users/").unwrap();
+
+        assert_eq!(pattern.matches("users"), Some(hashmap! {}));
+        assert_eq!(pattern.matches("/users"), Some(hashmap! {}));
+        assert_eq!(pattern.matches("/users/"), Some(hashmap! {}));
+    }
+
+    #[test]
+    fn openapi_paths() {
+        let pattern = RoutePattern::parse("users/:id/posts/:post_id").unwrap();
+
+        assert_eq!(pattern.openapi_path(), "/users/{id}/posts/{post_id}");
+        assert_eq!(pattern.params(), vec!["id", "post_id"]);
+    }
+
+    #[test]
+    fn bad_patterns() {
+        assert!(RoutePattern::parse("users/:").is_err());
+        assert!(RoutePattern::parse("users/:user-id").is_err());
+        assert!(RoutePattern::parse("users/:id/posts/:id").is_err());
+    }
+
+    #[test]
+    fn static_segments_take_precedence() {
+        let me = RoutePattern::parse("users/me").unwrap();
+        let by_id = RoutePattern::parse("users/:id").unwrap();
+        let any_posts = RoutePattern::parse(":kind/posts").unwrap();
+
+        assert_eq!(me.precedence(&by_id), Ordering::Less);
+        assert_eq!(by_id.precedence(&me), Ordering::Greater);
+        assert_eq!(by_id.precedence(&any_posts), Ordering::Less);
+        assert_eq!(by_id.precedence(&by_id), Ordering::Equal);
+    }
+
+    #[test]
+    fn conflicts() {
+        let by_id = RoutePattern::parse("users/:id").unwrap();
+
+        assert!(by_id.conflicts_with(&RoutePattern::parse("/users/:user_id").unwrap()));
+        assert!(!by_id.conflicts_with(&RoutePattern::parse("users/me").unwrap()));
+        assert!(!by_id.conflicts_with(&RoutePattern::parse("users/:id/posts").unwrap()));
+        assert!(!by_id.conflicts_with(&RoutePattern::parse(":kind/42").unwrap()));
+    }
+}
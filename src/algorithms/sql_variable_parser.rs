@@ -1,26 +1,87 @@
-use std::collections::HashMap;
-
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 
+use crate::algorithms::pagination::Pagination;
+use crate::types::sql_value::SqlType;
+
 pub struct SqlWithVariables {
     pub sql: String,
     pub variables: Vec<String>,
+    /// Types declared with `${name:type}`, one entry per variable
+    pub variable_types: Vec<Option<SqlType>>,
+}
+
+/// How many rows a query returns and how they are shaped in the response
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Cardinality {
+    /// A list of rows
+    #[default]
+    Many,
+    /// A single row, responds with 404 when there's none
+    One,
+    /// A single row or null
+    OptionalOne,
+    /// Value of the only column of a single row or null
+    Scalar,
+    /// Number of rows changed by a statement without RETURNING
+    AffectedRows,
+}
+
+fn guard_status() -> u16 {
+    403
+}
+
+/// What a node of the endpoint tree does with its query
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum NodeKind {
+    /// Returns the rows in the response
+    #[default]
+    Query,
+    /// Aborts the request unless the query returns a row which isn't `false`
+    Guard {
+        #[serde(default = "guard_status")]
+        status: u16,
+        message: String,
+    },
+    /// Executes the children only when the query returns a row which isn't `false`,
+    /// their results are returned as if they were siblings of the condition
+    Condition,
 }
 
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub struct EndpointInfoCreateRequest {
     pub name: String,
     pub sql: String,
+    #[serde(default)]
+    pub kind: NodeKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pagination: Option<Pagination>,
+    #[serde(default)]
+    pub cardinality: Cardinality,
+    /// Merge the results of children into the row object
+    #[serde(default)]
+    pub flatten: bool,
     pub children: Vec<EndpointInfoCreateRequest>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct EndpointInfo {
     pub name: String,
     pub original_sql: String,
     pub parsed_sql: String,
     pub variables: Vec<String>,
+    #[serde(default)]
+    pub variable_types: Vec<Option<SqlType>>,
+    #[serde(default)]
+    pub kind: NodeKind,
+    #[serde(default)]
+    pub pagination: Option<Pagination>,
+    #[serde(default)]
+    pub cardinality: Cardinality,
+    #[serde(default)]
+    pub flatten: bool,
     pub children: Vec<EndpointInfo>,
 }
 
@@ -28,15 +89,20 @@ impl EndpointInfo {
     pub fn from_request(req: EndpointInfoCreateRequest) -> Result<Self> {
         let name = req.name;
         let original_sql = req.sql;
+        let kind = req.kind;
+        let pagination = req.pagination;
+        let cardinality = req.cardinality;
+        let flatten = req.flatten;
 
         let sql_with_variables = SqlWithVariables::from_sql(&original_sql)?;
         let parsed_sql = sql_with_variables.sql;
         let variables = sql_with_variables.variables;
+        let variable_types = sql_with_variables.variable_types;
 
         let children_result: Result<Vec<EndpointInfo>> = req
             .children
             .into_iter()
-            .map(|it| EndpointInfo::from_request(it))
+            .map(EndpointInfo::from_request)
             .collect();
 
         let children = children_result?;
@@ -46,73 +112,377 @@ impl EndpointInfo {
             original_sql,
             parsed_sql,
             variables,
+            variable_types,
+            kind,
+            pagination,
+            cardinality,
+            flatten,
             children,
         })
     }
 
+    #[allow(clippy::wrong_self_convention)]
     pub fn to_request(self) -> EndpointInfoCreateRequest {
         EndpointInfoCreateRequest {
             name: self.name,
             sql: self.original_sql,
+            kind: self.kind,
+            pagination: self.pagination,
+            cardinality: self.cardinality,
+            flatten: self.flatten,
             children: self.children.into_iter().map(Self::to_request).collect(),
         }
     }
 }
 
-impl SqlWithVariables {
-    pub fn from_sql(mut sql: &str) -> Result<Self> {
-        fn pass_until(mut s: &str, c: char) -> Option<(&str, &str)> {
-            let original = s;
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
 
-            while s.len() != 0 && s.chars().next() != Some(c) {
-                s = &s[1..];
-            }
+/// Adds the line and column of `chars[index]` to the error message
+fn error_at(chars: &[char], index: usize, message: impl std::fmt::Display) -> anyhow::Error {
+    let before = &chars[..index];
+    let line = before.iter().filter(|c| **c == '\n').count() + 1;
+    let column = before.iter().rev().take_while(|c| **c != '\n').count() + 1;
 
-            let diff = original.len() - s.len();
-            let removed = &original[0..diff];
+    anyhow!("{} at line {}, column {}", message, line, column)
+}
+
+/// Index of the first occurrence of `pattern` in `chars` at or after `from`
+fn find_chars(chars: &[char], from: usize, pattern: &[char]) -> Option<usize> {
+    (from..chars.len()).find(|i| chars[*i..].starts_with(pattern))
+}
 
-            if s.chars().next() == Some(c) {
-                Some((removed, &s[1..]))
+/// Index right after the quoted string or identifier starting at `start`.
+/// A doubled quote is an escaped quote, `backslash_escapes` is for `E'...'` strings.
+fn skip_quoted(chars: &[char], start: usize, backslash_escapes: bool) -> Result<usize> {
+    let quote = chars[start];
+    let mut i = start + 1;
+
+    while i < chars.len() {
+        if backslash_escapes && chars[i] == '\\' {
+            i += 2;
+        } else if chars[i] == quote {
+            if chars.get(i + 1) == Some(&quote) {
+                i += 2;
             } else {
-                None
+                return Ok(i + 1);
             }
+        } else {
+            i += 1;
         }
+    }
 
-        let mut result_sql = String::with_capacity(sql.len());
-        let mut counter = (1_usize..).into_iter();
-        let mut variables: Vec<String> = Vec::new();
+    let what = if quote == '"' { "Identifier" } else { "String" };
+    Err(error_at(chars, start, format!("{} not closed", what)))
+}
+
+/// Index right after the (possibly nested) block comment starting at `start`
+fn skip_block_comment(chars: &[char], start: usize) -> Result<usize> {
+    let mut depth = 0_usize;
+    let mut i = start;
+
+    while i < chars.len() {
+        if chars[i..].starts_with(&['/', '*']) {
+            depth += 1;
+            i += 2;
+        } else if chars[i..].starts_with(&['*', '/']) {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return Ok(i);
+            }
+        } else {
+            i += 1;
+        }
+    }
+
+    Err(error_at(chars, start, "Comment not closed"))
+}
+
+/// The `$tag$` opening a dollar-quoted string at `start`, if there is one
+fn dollar_quote_tag(chars: &[char], start: usize) -> Option<&[char]> {
+    let tag_len = chars[start + 1..]
+        .iter()
+        .take_while(|c| c.is_alphanumeric() || **c == '_')
+        .count();
+    let end = start + 1 + tag_len;
+
+    let starts_with_digit = tag_len > 0 && chars[start + 1].is_ascii_digit();
 
-        while sql != "" {
-            if sql.len() >= 2 && &sql[0..2] == "${" {
-                if let Some((removed, continuation)) = pass_until(sql, '}') {
-                    variables.push((&removed[2..]).to_string()); // Remove "${" from beginning
-                    result_sql.push_str(&format!("${num}", num = counter.next().unwrap()));
-                    sql = continuation;
-                    continue;
+    if !starts_with_digit && chars.get(end) == Some(&'$') {
+        Some(&chars[start..=end])
+    } else {
+        None
+    }
+}
+
+fn parse_variable(variable: &str) -> Result<(String, Option<SqlType>)> {
+    let (name, ty) = match variable.split_once(':') {
+        Some((name, annotation)) => (name, Some(SqlType::from_annotation(annotation)?)),
+        None => (variable, None),
+    };
+
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(anyhow!("Empty variable name"));
+    }
+
+    Ok((name.to_string(), ty))
+}
+
+enum SqlToken {
+    Sql(String),
+    /// `${...}`, with the index of its `$` for error messages
+    Variable(String, usize),
+    /// A positional `$n` parameter, with the index of its `$`
+    Parameter(usize, usize),
+}
+
+/// Splits sql into variables, positional parameters and everything else.
+///
+/// Strings, quoted identifiers, dollar-quoted strings and comments
+/// are kept whole, `\${` outside of them stands for a literal `${`.
+fn tokenize(chars: &[char]) -> Result<Vec<SqlToken>> {
+    let mut tokens = Vec::new();
+    let mut sql = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let rest = &chars[i..];
+        let previous = if i > 0 { Some(chars[i - 1]) } else { None };
+
+        let end = match rest {
+            ['\\', '$', '{', ..] => {
+                sql.push_str("${");
+                i += 3;
+                continue;
+            }
+            ['$', '{', ..] => {
+                let close = find_chars(chars, i + 2, &['}'])
+                    .ok_or_else(|| error_at(chars, i, "Variable block not closed"))?;
+
+                tokens.push(SqlToken::Sql(std::mem::take(&mut sql)));
+                tokens.push(SqlToken::Variable(chars[i + 2..close].iter().collect(), i));
+                i = close + 1;
+                continue;
+            }
+            ['\'', ..] => {
+                let backslash_escapes = matches!(previous, Some('E' | 'e'))
+                    && (i < 2 || !is_identifier_char(chars[i - 2]));
+                skip_quoted(chars, i, backslash_escapes)?
+            }
+            ['"', ..] => skip_quoted(chars, i, false)?,
+            ['-', '-', ..] => find_chars(chars, i, &['\n']).unwrap_or(chars.len()),
+            ['/', '*', ..] => skip_block_comment(chars, i)?,
+            ['$', ..] if !previous.is_some_and(is_identifier_char) => {
+                if let Some(tag) = dollar_quote_tag(chars, i) {
+                    let close = find_chars(chars, i + tag.len(), tag)
+                        .ok_or_else(|| error_at(chars, i, "Dollar-quoted string not closed"))?;
+                    close + tag.len()
                 } else {
-                    return Err(anyhow!("Variable block not closed"));
+                    let digits = rest[1..]
+                        .iter()
+                        .take_while(|c| c.is_ascii_digit())
+                        .collect::<String>();
+
+                    match digits.parse() {
+                        Ok(number) => {
+                            tokens.push(SqlToken::Sql(std::mem::take(&mut sql)));
+                            tokens.push(SqlToken::Parameter(number, i));
+                            i += 1 + digits.len();
+                            continue;
+                        }
+                        Err(_) => i + 1,
+                    }
                 }
             }
+            _ => i + 1,
+        };
 
-            result_sql.push(sql.chars().next().unwrap());
-            sql = &sql[1..];
+        sql.extend(&chars[i..end]);
+        i = end;
+    }
+
+    tokens.push(SqlToken::Sql(sql));
+    Ok(tokens)
+}
+
+/// Replaces positional `$n` parameters of already parsed sql
+pub fn replace_parameters(
+    sql: &str,
+    mut replacement: impl FnMut(usize) -> String,
+) -> Result<String> {
+    let chars = sql.chars().collect::<Vec<_>>();
+    let mut result = String::with_capacity(sql.len());
+
+    for token in tokenize(&chars)? {
+        match token {
+            SqlToken::Sql(sql) => result.push_str(&sql),
+            // escaped with `\${` in the original sql
+            SqlToken::Variable(variable, _) => result.push_str(&format!("${{{}}}", variable)),
+            SqlToken::Parameter(number, _) => result.push_str(&replacement(number)),
         }
+    }
 
-        Ok(Self {
-            sql: result_sql,
-            variables,
-        })
+    Ok(result)
+}
+
+/// Words outside of strings and comments, lowercased, with their depth of parentheses
+fn words(chars: &[char]) -> Result<Vec<(usize, String)>> {
+    let mut words = Vec::new();
+    let mut depth = 0_usize;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let previous = if i > 0 { Some(chars[i - 1]) } else { None };
+
+        i = match &chars[i..] {
+            ['\'', ..] => {
+                let backslash_escapes = matches!(previous, Some('E' | 'e'))
+                    && (i < 2 || !is_identifier_char(chars[i - 2]));
+                skip_quoted(chars, i, backslash_escapes)?
+            }
+            ['"', ..] => skip_quoted(chars, i, false)?,
+            ['-', '-', ..] => find_chars(chars, i, &['\n']).unwrap_or(chars.len()),
+            ['/', '*', ..] => skip_block_comment(chars, i)?,
+            ['$', ..] if !previous.is_some_and(is_identifier_char) => {
+                match dollar_quote_tag(chars, i) {
+                    Some(tag) => {
+                        let close = find_chars(chars, i + tag.len(), tag)
+                            .ok_or_else(|| error_at(chars, i, "Dollar-quoted string not closed"))?;
+                        close + tag.len()
+                    }
+                    None => i + 1,
+                }
+            }
+            ['(', ..] => {
+                depth += 1;
+                i + 1
+            }
+            [')', ..] => {
+                depth = depth.saturating_sub(1);
+                i + 1
+            }
+            [c, ..] if is_identifier_char(*c) => {
+                let end = i + chars[i..]
+                    .iter()
+                    .take_while(|c| is_identifier_char(**c))
+                    .count();
+                let word = chars[i..end].iter().collect::<String>().to_lowercase();
+                words.push((depth, word));
+                end
+            }
+            _ => i + 1,
+        };
     }
 
-    pub fn get_bind_vec<'a>(&self, data: &'a HashMap<String, String>) -> Option<Vec<&'a str>> {
-        let mut result = Vec::with_capacity(self.variables.len());
+    Ok(words)
+}
 
-        for variable in &self.variables {
-            let value = data.get(variable)?.as_str();
-            result.push(value);
+/// Words outside of parentheses, strings and comments, lowercased
+fn top_level_words(chars: &[char]) -> Result<Vec<String>> {
+    Ok(words(chars)?
+        .into_iter()
+        .filter(|(depth, _)| *depth == 0)
+        .map(|(_, word)| word)
+        .collect())
+}
+
+/// Whether the statement sorts its result, with an ORDER BY that isn't
+/// inside of a subquery, a window or an aggregate
+pub fn is_ordered(sql: &str) -> Result<bool> {
+    let chars = sql.chars().collect::<Vec<_>>();
+    let words = top_level_words(&chars)?;
+
+    Ok(words.windows(2).any(|it| it[0] == "order" && it[1] == "by"))
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StatementKind {
+    /// `SELECT`, `VALUES`, `TABLE` or `WITH` reading data, it can be a subquery
+    Query,
+    /// `INSERT`, `UPDATE` or `DELETE`, it can be a common table expression
+    Modification,
+    /// `WITH` modifying data, which can't be nested, and other statements
+    Other,
+}
+
+pub fn statement_kind(sql: &str) -> Result<StatementKind> {
+    let chars = sql.chars().collect::<Vec<_>>();
+    let words = words(&chars)?;
+
+    // `FOR UPDATE` and `FOR NO KEY UPDATE` only lock rows
+    let modifies = words.iter().enumerate().any(|(i, (_, word))| {
+        let previous = i.checked_sub(1).map(|it| words[it].1.as_str());
+        matches!(word.as_str(), "insert" | "delete" | "merge")
+            || (word == "update" && !matches!(previous, Some("for" | "key")))
+    });
+
+    let kind = match words.first().map(|(_, word)| word.as_str()) {
+        Some("select" | "values" | "table") => StatementKind::Query,
+        Some("with") if !modifies => StatementKind::Query,
+        Some("insert" | "update" | "delete") => StatementKind::Modification,
+        _ => StatementKind::Other,
+    };
+
+    Ok(kind)
+}
+
+impl SqlWithVariables {
+    /// Replaces `${name}` and `${name:type}` with `$n` parameters.
+    ///
+    /// Repeated variables with the same type share one parameter.
+    pub fn from_sql(sql: &str) -> Result<Self> {
+        let chars = sql.chars().collect::<Vec<_>>();
+
+        let mut result_sql = String::with_capacity(sql.len());
+        let mut variables: Vec<String> = Vec::new();
+        let mut variable_types: Vec<Option<SqlType>> = Vec::new();
+
+        for token in tokenize(&chars)? {
+            match token {
+                SqlToken::Sql(sql) => result_sql.push_str(&sql),
+                // they would be bound to the values of variables
+                SqlToken::Parameter(number, position) => {
+                    return Err(error_at(
+                        &chars,
+                        position,
+                        format!(
+                            "Positional parameter ${} isn't allowed, use ${{...}}",
+                            number
+                        ),
+                    ))
+                }
+                SqlToken::Variable(variable, position) => {
+                    let (name, ty) = parse_variable(&variable).map_err(|e| {
+                        error_at(&chars, position, format!("{} in ${{{}}}", e, variable))
+                    })?;
+
+                    let existing = variables
+                        .iter()
+                        .zip(&variable_types)
+                        .position(|(n, t)| *n == name && *t == ty);
+
+                    let number = match existing {
+                        Some(index) => index + 1,
+                        None => {
+                            variables.push(name);
+                            variable_types.push(ty);
+                            variables.len()
+                        }
+                    };
+
+                    result_sql.push_str(&format!("${}", number));
+                }
+            }
         }
 
-        Some(result)
+        Ok(Self {
+            sql: result_sql,
+            variables,
+            variable_types,
+        })
     }
 }
 
@@ -135,9 +505,177 @@ mod tests {
 
         assert_eq!(
             &parsed.sql,
-            "select * from users where name=$1 and age=$2 or name = upper($3)"
+            "select * from users where name=$1 and age=$2 or name = upper($1)"
+        );
+        assert_eq!(&parsed.variables, &vec!["req.name", "req.age"]);
+    }
+
+    #[test]
+    fn parsing_types() {
+        let sql = "select * from posts where id=${req.id:int} and tags && ${req.tags : text[]} and author=${super.private_id}";
+        let parsed = SqlWithVariables::from_sql(sql).unwrap();
+
+        assert_eq!(
+            &parsed.sql,
+            "select * from posts where id=$1 and tags && $2 and author=$3"
+        );
+        assert_eq!(
+            &parsed.variables,
+            &vec!["req.id", "req.tags", "super.private_id"]
+        );
+        assert_eq!(
+            parsed.variable_types,
+            vec![
+                Some(SqlType::Int),
+                Some(SqlType::Array(Box::new(SqlType::Text))),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_type_is_an_error() {
+        let error = SqlWithVariables::from_sql("select ${req.id:integr}")
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Unknown type integr in ${req.id:integr} at line 1, column 8"
+        );
+    }
+
+    #[test]
+    fn strings_and_comments_are_not_parsed() {
+        let sql = r#"select '${a}', E'it\'s ${b}', "${c}", $$ ${d} $$, $fn$ '${e}' $fn$ -- ${f}
+            /* ${g} /* nested */ ${h} */ from t where x = ${req.x}"#;
+        let parsed = SqlWithVariables::from_sql(sql).unwrap();
+
+        assert_eq!(parsed.sql, sql.replace("${req.x}", "$1"));
+        assert_eq!(&parsed.variables, &vec!["req.x"]);
+    }
+
+    #[test]
+    fn positional_parameters_are_not_dollar_quotes() {
+        let parsed = SqlWithVariables::from_sql("select a$b$ from t where x=${req.x}").unwrap();
+        assert_eq!(&parsed.sql, "select a$b$ from t where x=$1");
+
+        let error = SqlWithVariables::from_sql("select a$b$,\n  $1 from t where x=${req.x}")
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Positional parameter $1 isn't allowed, use ${...} at line 2, column 3"
+        );
+    }
+
+    #[test]
+    fn escaped_variable() {
+        let parsed = SqlWithVariables::from_sql(r"select \${literal}, ${req.x}").unwrap();
+
+        assert_eq!(&parsed.sql, "select ${literal}, $1");
+        assert_eq!(&parsed.variables, &vec!["req.x"]);
+    }
+
+    #[test]
+    fn repeated_variables_with_different_types() {
+        let parsed =
+            SqlWithVariables::from_sql("select ${req.id:int}, ${req.id}, ${req.id: int}").unwrap();
+
+        assert_eq!(&parsed.sql, "select $1, $2, $1");
+        assert_eq!(&parsed.variables, &vec!["req.id", "req.id"]);
+        assert_eq!(parsed.variable_types, vec![Some(SqlType::Int), None]);
+    }
+
+    #[test]
+    fn replacing_parameters() {
+        let parsed = SqlWithVariables::from_sql(
+            r"select ${super.id}, '$1', \${literal} -- $2
+            , ${super.name}, ${super.id}",
+        )
+        .unwrap();
+
+        assert_eq!(
+            replace_parameters(&parsed.sql, |n| format!("v{}", n)).unwrap(),
+            "select v1, '$1', ${literal} -- $2\n            , v2, v1"
+        );
+    }
+
+    #[test]
+    fn ordered_statements() {
+        let ordered = |sql| is_ordered(sql).unwrap();
+
+        assert!(ordered(
+            "select * from posts where user_fk = $1 ORDER  BY id desc"
+        ));
+        assert!(ordered("with t as (select 1) select * from t order\nby 1"));
+        assert!(!ordered("select * from posts where title = 'order by'"));
+        assert!(!ordered(
+            "select * from (select * from posts order by id) p"
+        ));
+        assert!(!ordered(
+            "select string_agg(title, ',' order by id), row_number() over (order by id) from posts"
+        ));
+        assert!(!ordered("select 1 -- order by 1\n/* order by 1 */"));
+    }
+
+    #[test]
+    fn statement_kinds() {
+        let kind = |sql| statement_kind(sql).unwrap();
+
+        assert_eq!(kind("select * from posts for update"), StatementKind::Query);
+        assert_eq!(kind("(select 1) union (select 2)"), StatementKind::Query);
+        assert_eq!(
+            kind("with t as (select 1 as \"update\") select * from t"),
+            StatementKind::Query
+        );
+        assert_eq!(
+            kind("/* select */ INSERT INTO posts (title) VALUES ('a') RETURNING id"),
+            StatementKind::Modification
+        );
+        assert_eq!(
+            kind("with t as (delete from posts returning id) select * from t"),
+            StatementKind::Other
+        );
+        assert_eq!(kind("lock table posts"), StatementKind::Other);
+    }
+
+    #[test]
+    fn multibyte_characters() {
+        let parsed =
+            SqlWithVariables::from_sql("select 'zażółć' || ${req.gęślą}, ${req.jaźń}").unwrap();
+
+        assert_eq!(&parsed.sql, "select 'zażółć' || $1, $2");
+        assert_eq!(&parsed.variables, &vec!["req.gęślą", "req.jaźń"]);
+    }
+
+    #[test]
+    fn errors_have_positions() {
+        let error = |sql| SqlWithVariables::from_sql(sql).err().unwrap().to_string();
+
+        assert_eq!(
+            error("select 1,\n  ${req.x"),
+            "Variable block not closed at line 2, column 3"
+        );
+        assert_eq!(
+            error("select 'ó', 'abc"),
+            "String not closed at line 1, column 13"
+        );
+        assert_eq!(
+            error("select \"abc"),
+            "Identifier not closed at line 1, column 8"
+        );
+        assert_eq!(
+            error("select $x$ abc $y$"),
+            "Dollar-quoted string not closed at line 1, column 8"
+        );
+        assert_eq!(
+            error("select /* /* */"),
+            "Comment not closed at line 1, column 8"
+        );
+        assert_eq!(
+            error("select ${ :int}"),
+            "Empty variable name in ${ :int} at line 1, column 8"
         );
-        assert_eq!(&parsed.variables, &vec!["req.name", "req.age", "req.name"]);
     }
 
     #[test]
@@ -147,20 +685,13 @@ mod tests {
     }
 
     #[test]
-    fn bind_vec() {
+    fn repeated_variables() {
         let sql = "select ${req.name}, ${req.age}, ${req.food}, ${req.name}";
 
-        let mut data = HashMap::new();
-        data.insert("req.name".into(), "Adam".into());
-        data.insert("req.age".into(), "24".into());
-        data.insert("req.food".into(), "bigos".into());
-
-        let result = SqlWithVariables::from_sql(sql)
-            .unwrap()
-            .get_bind_vec(&data)
-            .unwrap();
+        let parsed = SqlWithVariables::from_sql(sql).unwrap();
 
-        assert_eq!(result, vec!["Adam", "24", "bigos", "Adam"]);
+        assert_eq!(&parsed.sql, "select $1, $2, $3, $1");
+        assert_eq!(&parsed.variables, &vec!["req.name", "req.age", "req.food"]);
     }
 
     #[test]
@@ -168,9 +699,17 @@ mod tests {
         let req = EndpointInfoCreateRequest {
             name: "user".into(),
             sql: "SELECT id as private_id, username FROM users WHERE id=${req.userId}".into(),
+            kind: NodeKind::Query,
+            pagination: None,
+            cardinality: Cardinality::One,
+            flatten: true,
             children: vec![EndpointInfoCreateRequest {
                 name: "posts".into(),
                 sql: "SELECT title, body FROM posts WHERE user_fk=${super.private_id}".into(),
+                kind: NodeKind::Query,
+                pagination: None,
+                cardinality: Cardinality::Many,
+                flatten: false,
                 children: vec![],
             }],
         };
@@ -180,11 +719,21 @@ mod tests {
             original_sql: req.sql.clone(),
             parsed_sql: "SELECT id as private_id, username FROM users WHERE id=$1".into(),
             variables: vec!["req.userId".into()],
+            variable_types: vec![None],
+            kind: NodeKind::Query,
+            pagination: None,
+            cardinality: Cardinality::One,
+            flatten: true,
             children: vec![EndpointInfo {
                 name: "posts".into(),
                 original_sql: req.children[0].sql.clone(),
                 parsed_sql: "SELECT title, body FROM posts WHERE user_fk=$1".into(),
                 variables: vec!["super.private_id".into()],
+                variable_types: vec![None],
+                kind: NodeKind::Query,
+                pagination: None,
+                cardinality: Cardinality::Many,
+                flatten: false,
                 children: vec![],
             }],
         };
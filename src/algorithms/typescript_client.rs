@@ -0,0 +1,393 @@
+use serde_json::{Map, Value};
+use std::fmt::Write;
+
+/// Helpers shared by the generated functions
+const RUNTIME: &str = r#"// Generated from the custom endpoints, changes will be overwritten
+
+export interface ClientOptions {
+  /** Origin of the server, e.g. `https://example.com`, the current origin when empty */
+  baseUrl?: string;
+  /** Token returned by `/api/login` */
+  token?: string;
+}
+
+export class EndpointError extends Error {
+  constructor(public status: number, public body: string) {
+    super(`Endpoint responded with ${status}: ${body}`);
+  }
+}
+
+async function call<T>(
+  options: ClientOptions,
+  method: string,
+  path: string,
+  query?: Record<string, unknown>,
+  body?: unknown,
+): Promise<T> {
+  const url = new URL(path, options.baseUrl || window.location.origin);
+  for (const [key, value] of Object.entries(query ?? {})) {
+    if (value === undefined || value === null) {
+      continue;
+    }
+    // The server reads query strings as forms, which have no nested values
+    if (typeof value === "object") {
+      throw new TypeError(`${key} can't be sent in a query string`);
+    }
+    url.searchParams.set(key, String(value));
+  }
+
+  const headers: Record<string, string> = {};
+  if (options.token) {
+    headers["Authorization"] = `Bearer ${options.token}`;
+  }
+  if (body !== undefined) {
+    headers["Content-Type"] = "application/json";
+  }
+
+  const response = await fetch(url.toString(), {
+    method,
+    headers,
+    body: body === undefined ? undefined : JSON.stringify(body),
+  });
+
+  if (!response.ok) {
+    throw new EndpointError(response.status, await response.text());
+  }
+
+  const text = await response.text();
+  return (text ? JSON.parse(text) : undefined) as T;
+}
+"#;
+
+/// Generates a TypeScript module with one function per operation of the OpenAPI document
+pub fn typescript_client(document: &Value) -> String {
+    let mut module = RUNTIME.to_owned();
+
+    let paths = match document["paths"].as_object() {
+        Some(paths) => paths,
+        None => return module,
+    };
+
+    for (path, path_item) in paths {
+        for (method, operation) in path_item.as_object().into_iter().flatten() {
+            write_operation(&mut module, path, method, operation);
+        }
+    }
+
+    module
+}
+
+fn write_operation(module: &mut String, path: &str, method: &str, operation: &Value) {
+    let name = camel_case(operation["operationId"].as_str().unwrap_or(method));
+    let type_prefix = pascal_case(&name);
+
+    let parameters = operation["parameters"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    let path_params = parameters
+        .iter()
+        .filter(|it| it["in"] == "path")
+        .filter_map(|it| it["name"].as_str())
+        .collect::<Vec<_>>();
+
+    // Only GET requests are read from the query string, the others from the body.
+    // Query parameters and json bodies are both described as an object.
+    let in_query = method == "get";
+    let query_params = parameters
+        .iter()
+        .filter(|it| it["in"] == "query")
+        .collect::<Vec<_>>();
+    let request_schema = if !in_query {
+        operation["requestBody"]["content"]["application/json"]["schema"].clone()
+    } else if query_params.is_empty() {
+        Value::Null
+    } else {
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+
+        for param in &query_params {
+            let param_name = param["name"].as_str().unwrap_or_default().to_owned();
+            if param["required"] == true {
+                required.push(Value::String(param_name.clone()));
+            }
+            properties.insert(param_name, param["schema"].clone());
+        }
+
+        serde_json::json!({"type": "object", "properties": properties, "required": required})
+    };
+
+    let (success_status, success) = operation["responses"]
+        .as_object()
+        .and_then(|responses| responses.iter().find(|(status, _)| status.starts_with('2')))
+        .map(|(status, response)| (status.as_str(), response))
+        .unwrap_or(("200", &Value::Null));
+    let response_schema = &success["content"]["application/json"]["schema"];
+
+    let request_type = format!("{}Request", type_prefix);
+    let response_type = format!("{}Response", type_prefix);
+
+    let _ = writeln!(module);
+
+    let has_request = !request_schema.is_null();
+    if has_request {
+        let _ = writeln!(
+            module,
+            "export type {} = {};\n",
+            request_type,
+            ts_type(&request_schema, 0)
+        );
+    }
+
+    let returns = if response_schema.is_null() || success_status == "204" {
+        "void".to_owned()
+    } else {
+        let _ = writeln!(
+            module,
+            "export type {} = {};\n",
+            response_type,
+            ts_type(response_schema, 0)
+        );
+        response_type
+    };
+
+    let mut arguments = Vec::new();
+    if !path_params.is_empty() {
+        let fields = path_params
+            .iter()
+            .map(|it| format!("{}: string | number", property_key(it)))
+            .collect::<Vec<_>>();
+        arguments.push(format!("path: {{ {} }}", fields.join("; ")));
+    }
+    if has_request {
+        arguments.push(format!("request: {}", request_type));
+    }
+    arguments.push("options: ClientOptions = {}".to_owned());
+
+    let mut url = String::new();
+    for segment in path.split('/').filter(|it| !it.is_empty()) {
+        match segment
+            .strip_prefix('{')
+            .and_then(|it| it.strip_suffix('}'))
+        {
+            Some(param) => {
+                let _ = write!(url, "/${{encodeURIComponent(path.{})}}", param);
+            }
+            None => {
+                url.push('/');
+                url.push_str(segment);
+            }
+        }
+    }
+
+    let (query, body) = match (has_request, in_query) {
+        (false, _) => ("undefined", "undefined"),
+        (true, true) => ("request", "undefined"),
+        (true, false) => ("undefined", "request"),
+    };
+
+    let _ = writeln!(
+        module,
+        "export function {}({}): Promise<{}> {{\n  return call(options, \"{}\", `{}`, {}, {});\n}}",
+        name,
+        arguments.join(", "),
+        returns,
+        method.to_uppercase(),
+        url,
+        query,
+        body
+    );
+}
+
+/// TypeScript type of a json schema, nested objects are indented by `depth`
+fn ts_type(schema: &Value, depth: usize) -> String {
+    let base = match &schema["enum"] {
+        Value::Array(values) => values
+            .iter()
+            .map(Value::to_string)
+            .collect::<Vec<_>>()
+            .join(" | "),
+        _ => match schema["type"].as_str() {
+            Some("string") => "string".to_owned(),
+            Some("integer" | "number") => "number".to_owned(),
+            Some("boolean") => "boolean".to_owned(),
+            Some("array") => match schema.get("items") {
+                Some(items) => format!("Array<{}>", ts_type(items, depth)),
+                None => "Array<unknown>".to_owned(),
+            },
+            Some("object") => object_type(schema, depth),
+            _ => "unknown".to_owned(),
+        },
+    };
+
+    if schema["nullable"] == true {
+        format!("{} | null", base)
+    } else {
+        base
+    }
+}
+
+fn object_type(schema: &Value, depth: usize) -> String {
+    let properties = match schema["properties"].as_object() {
+        Some(properties) if !properties.is_empty() => properties,
+        Some(_) => return "{}".to_owned(),
+        None => return "Record<string, unknown>".to_owned(),
+    };
+
+    let required = schema["required"].as_array().cloned().unwrap_or_default();
+    let indent = "  ".repeat(depth + 1);
+    let mut fields = String::new();
+
+    for (name, property) in properties {
+        let optional = if required.contains(&Value::String(name.clone())) {
+            ""
+        } else {
+            "?"
+        };
+        let _ = writeln!(
+            fields,
+            "{}{}{}: {};",
+            indent,
+            property_key(name),
+            optional,
+            ts_type(property, depth + 1)
+        );
+    }
+
+    format!("{{\n{}{}}}", fields, "  ".repeat(depth))
+}
+
+fn property_key(name: &str) -> String {
+    let is_identifier = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+
+    if is_identifier {
+        name.to_owned()
+    } else {
+        Value::String(name.to_owned()).to_string()
+    }
+}
+
+fn camel_case(name: &str) -> String {
+    let pascal = pascal_case(name);
+    let mut chars = pascal.chars();
+
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => pascal,
+    }
+}
+
+fn pascal_case(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|it| !it.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn generating_functions() {
+        let document = json!({
+            "paths": {
+                "/endpoint/users/{id}": {
+                    "get": {
+                        "operationId": "get_endpoint_users_by_id",
+                        "parameters": [
+                            {"name": "id", "in": "path", "required": true, "schema": {"type": "string"}},
+                            {"name": "search", "in": "query", "required": false, "schema": {"type": "string"}},
+                        ],
+                        "responses": {
+                            "200": {
+                                "content": {"application/json": {"schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "user": {
+                                            "type": "object",
+                                            "properties": {
+                                                "id": {"type": "integer"},
+                                                "kind": {"enum": ["a", "b"], "nullable": true},
+                                                "first name": {"type": "array", "items": {}},
+                                            },
+                                            "required": ["id", "kind"],
+                                        },
+                                    },
+                                    "required": ["user"],
+                                }}},
+                            },
+                        },
+                    },
+                },
+                "/endpoint/posts": {
+                    "delete": {
+                        "operationId": "delete_endpoint_posts",
+                        "requestBody": {"content": {"application/json": {"schema": {
+                            "type": "object",
+                            "properties": {"id": {"type": "integer"}},
+                            "required": ["id"],
+                        }}}},
+                        "responses": {"204": {"description": "No Content"}},
+                    },
+                },
+            },
+        });
+
+        let module = typescript_client(&document);
+        let generated = &module[RUNTIME.len()..];
+
+        assert_eq!(
+            generated,
+            r#"
+export type DeleteEndpointPostsRequest = {
+  id: number;
+};
+
+export function deleteEndpointPosts(request: DeleteEndpointPostsRequest, options: ClientOptions = {}): Promise<void> {
+  return call(options, "DELETE", `/endpoint/posts`, undefined, request);
+}
+
+export type GetEndpointUsersByIdRequest = {
+  search?: string;
+};
+
+export type GetEndpointUsersByIdResponse = {
+  user: {
+    "first name"?: Array<unknown>;
+    id: number;
+    kind: "a" | "b" | null;
+  };
+};
+
+export function getEndpointUsersById(path: { id: string | number }, request: GetEndpointUsersByIdRequest, options: ClientOptions = {}): Promise<GetEndpointUsersByIdResponse> {
+  return call(options, "GET", `/endpoint/users/${encodeURIComponent(path.id)}`, request, undefined);
+}
+"#
+        );
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(
+            camel_case("get_endpoint_users_by_id"),
+            "getEndpointUsersById"
+        );
+        assert_eq!(pascal_case("post_endpoint_posts"), "PostEndpointPosts");
+        assert_eq!(property_key("user_id"), "user_id");
+        assert_eq!(property_key("?column?"), "\"?column?\"");
+    }
+}
@@ -1,16 +1,19 @@
-use argon2::{password_hash::SaltString, Argon2, PasswordHasher};
+use argon2::{
+    password_hash::{PasswordHash, SaltString},
+    Argon2, PasswordHasher, PasswordVerifier,
+};
 use axum::extract::{Extension, Json};
 use hyper::StatusCode;
 use rand_core::OsRng;
 use serde::Deserialize;
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres};
 
 use super::Claims;
 
 #[derive(Deserialize)]
 pub struct ChangePassReq {
     pub new_pass: String,
-    pub old_pass: String, // TODO: check old password
+    pub old_pass: String,
 }
 
 pub async fn change_password(
@@ -19,6 +22,31 @@ pub async fn change_password(
     Extension(db_pool): Extension<PgPool>,
 ) -> Result<String, (StatusCode, String)> {
     let username = &claims.username;
+
+    let (old_hash,) = sqlx::query_as::<Postgres, (String,)>(
+        "SELECT password_hash FROM __B_users WHERE username = $1",
+    )
+    .bind(username)
+    .fetch_one(&db_pool)
+    .await
+    .map_err(|it| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("ERROR reading password: {}", it),
+        )
+    })?;
+
+    let old_hash = PasswordHash::new(&old_hash).map_err(|it| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("ERROR reading password: {}", it),
+        )
+    })?;
+
+    Argon2::default()
+        .verify_password(req.old_pass.as_bytes(), &old_hash)
+        .map_err(|_| (StatusCode::FORBIDDEN, "Old password is incorrect".into()))?;
+
     let salt = SaltString::generate(&mut OsRng);
     let argon2 = Argon2::default();
     let password_hash = argon2
@@ -31,7 +59,7 @@ pub async fn change_password(
         })?
         .to_string();
 
-    sqlx::query("UPDATE TABLE __B_users SET password_hash = $1 WHERE username = $2")
+    sqlx::query("UPDATE __B_users SET password_hash = $1 WHERE username = $2")
         .bind(password_hash)
         .bind(username)
         .execute(&db_pool)
@@ -45,3 +73,51 @@ pub async fn change_password(
 
     Ok("ok".into())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup::setup_internal_tables::init_tables;
+    use crate::test_database::TestDatabase;
+    use serde_json::json;
+
+    async fn change(
+        db_pool: &PgPool,
+        old_pass: &str,
+        new_pass: &str,
+    ) -> Result<String, StatusCode> {
+        let claims = serde_json::from_value(json!({
+            "username": "admin",
+            "user_group": "ADMIN",
+            "exp": 0,
+        }))
+        .unwrap();
+        let req = ChangePassReq {
+            old_pass: old_pass.into(),
+            new_pass: new_pass.into(),
+        };
+
+        change_password(claims, Json(req), Extension(db_pool.clone()))
+            .await
+            .map_err(|(status, _)| status)
+    }
+
+    #[tokio::test]
+    async fn old_password_is_checked() {
+        let db = TestDatabase::create().await;
+        init_tables(&db.pool, None).await.unwrap();
+
+        assert_eq!(
+            change(&db.pool, "wrong", "admin2").await,
+            Err(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(change(&db.pool, "admin1", "admin2").await, Ok("ok".into()));
+        assert_eq!(
+            change(&db.pool, "admin1", "admin3").await,
+            Err(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(change(&db.pool, "admin2", "admin1").await, Ok("ok".into()));
+
+        db.drop().await;
+    }
+}
@@ -1,4 +1,5 @@
 use super::create_users_route::CreateUsersRequest;
+use crate::services::row_level_security;
 use anyhow::{anyhow, Result};
 use argon2::{password_hash::SaltString, Argon2, PasswordHasher};
 use passwords::PasswordGenerator;
@@ -14,11 +15,11 @@ pub struct UsernamePass {
 
 pub async fn create_users(req: &CreateUsersRequest, db_pool: &PgPool) -> Result<Vec<UsernamePass>> {
     let username: &str = req.username.as_ref();
-    let mut counter = (1_usize..).into_iter();
+    let mut counter = 1_usize..;
 
     let mut new_users: Vec<UsernamePass> = Vec::with_capacity(req.amount);
 
-    if req.amount > 1 && username.matches("{}").into_iter().collect::<Vec<_>>().len() != 1 {
+    if req.amount > 1 && username.matches("{}").collect::<Vec<_>>().len() != 1 {
         return Err(anyhow!(
             r#"If creating multiple users,
                username must contain one "{}"
@@ -60,7 +61,7 @@ pub async fn create_user(
     mut password: Option<String>,
     db_pool: &PgPool,
 ) -> Result<String> {
-    if let None = password {
+    if password.is_none() {
         password = Some(
             PasswordGenerator::new()
                 .length(16)
@@ -84,5 +85,9 @@ pub async fn create_user(
         .execute(db_pool)
         .await?;
 
+    if *row_level_security::ENABLED {
+        row_level_security::create_group_role(db_pool, user_group).await?;
+    }
+
     Ok(password.to_string())
 }
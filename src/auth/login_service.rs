@@ -3,13 +3,13 @@ use crate::auth::Claims;
 use super::login_route::{LoginRequest, LoginResponse};
 use anyhow::{anyhow, Result};
 use argon2::{
-    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    password_hash::{PasswordHash, PasswordVerifier},
     Argon2,
 };
-use chrono::{Duration, Utc};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use chrono::Utc;
+use jsonwebtoken::{encode, DecodingKey, EncodingKey, Header};
 use once_cell::sync::Lazy;
-use sqlx::{Executor, FromRow, PgPool, Postgres};
+use sqlx::{FromRow, PgPool, Postgres};
 
 #[derive(Debug)]
 pub struct Keys {
@@ -33,6 +33,7 @@ pub static KEYS: Lazy<Keys> = Lazy::new(|| {
 
 #[derive(FromRow)]
 struct UserInfo {
+    pub id: i32,
     pub username: String,
     pub password_hash: String,
     pub user_group: String,
@@ -40,7 +41,7 @@ struct UserInfo {
 
 pub async fn login(req: &LoginRequest, db_pool: &PgPool) -> Result<LoginResponse> {
     let user_info: UserInfo = sqlx::query_as::<Postgres, UserInfo>(
-        "SELECT username, password_hash, user_group from __B_users where username=$1",
+        "SELECT id, username, password_hash, user_group from __B_users where username=$1",
     )
     .bind(&req.username)
     .fetch_one(db_pool)
@@ -59,6 +60,7 @@ pub async fn login(req: &LoginRequest, db_pool: &PgPool) -> Result<LoginResponse
         .timestamp();
 
     let claims = Claims {
+        user_id: Some(user_info.id),
         username: user_info.username.to_owned(),
         user_group: user_info.user_group.to_owned(),
         exp: expiration as usize,
@@ -7,6 +7,7 @@ use headers::{authorization::Bearer, Authorization};
 use jsonwebtoken::{decode, Validation};
 use login_service::KEYS;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
 pub mod change_password;
 pub mod create_users;
@@ -21,6 +22,9 @@ pub mod login_service;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
+    /// Missing in tokens issued before it was added
+    #[serde(default)]
+    user_id: Option<i32>,
     username: String,
     user_group: String,
     exp: usize,
@@ -41,6 +45,23 @@ impl Claims {
     pub fn user_group(&self) -> &str {
         &self.user_group
     }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn user_id(&self) -> Option<i32> {
+        self.user_id
+    }
+
+    /// Values of the `${user.}` variables of custom endpoints
+    pub fn endpoint_variables(&self) -> Value {
+        json!({
+            "id": self.user_id,
+            "username": self.username,
+            "group": self.user_group,
+        })
+    }
 }
 
 #[async_trait]
@@ -1,3 +0,0 @@
-use sqlx::PgPool;
-
-pub async fn get_tables(pool: &PgPool) {}
@@ -1,5 +1,90 @@
-use axum::http::StatusCode;
+use axum::http::{header, HeaderMap, HeaderValue, Response, StatusCode};
+use axum::response::IntoResponse;
+use serde::Serialize;
+use std::fmt;
 
 pub fn to_internal(e: impl ToString) -> (StatusCode, String) {
     (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
 }
+
+/// Error that should be returned to the client with a specific status code
+#[derive(Debug)]
+pub struct StatusError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+pub fn status_error(status: StatusCode, message: impl ToString) -> anyhow::Error {
+    StatusError {
+        status,
+        message: message.to_string(),
+    }
+    .into()
+}
+
+/// Like `to_internal`, but keeps the status code of a `StatusError`
+pub fn to_status(e: anyhow::Error) -> (StatusCode, String) {
+    match e.downcast_ref::<StatusError>() {
+        Some(status_error) => (status_error.status, status_error.message.clone()),
+        None => to_internal(e),
+    }
+}
+
+/// Error response that also carries headers, e.g. `Allow` for 405
+#[derive(Debug)]
+pub struct ErrorResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub message: String,
+}
+
+impl ErrorResponse {
+    pub fn new(status: StatusCode, message: impl ToString) -> Self {
+        Self {
+            status,
+            headers: HeaderMap::new(),
+            message: message.to_string(),
+        }
+    }
+
+    /// Error with a json body, for errors the client should process
+    pub fn json(status: StatusCode, body: &impl Serialize) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+
+        match serde_json::to_string(body) {
+            Ok(message) => Self {
+                status,
+                headers,
+                message,
+            },
+            Err(e) => to_internal(e).into(),
+        }
+    }
+}
+
+impl From<(StatusCode, String)> for ErrorResponse {
+    fn from((status, message): (StatusCode, String)) -> Self {
+        Self::new(status, message)
+    }
+}
+
+impl IntoResponse for ErrorResponse {
+    type Body = <String as IntoResponse>::Body;
+    type BodyError = <String as IntoResponse>::BodyError;
+
+    fn into_response(self) -> Response<Self::Body> {
+        (self.status, self.headers, self.message).into_response()
+    }
+}
@@ -1,36 +1,28 @@
 use anyhow::Context;
 use axum::{
-    extract::Extension,
-    http::StatusCode,
     routing::{any, get, post},
     AddExtensionLayer, Router,
 };
 use dotenv::dotenv;
+use once_cell::sync::Lazy;
 use routes::schema::schema_editing::create_table_form::create_table_form;
-use serde_json::{json, Value};
-use sqlx::{postgres::PgPoolOptions, Column, Decode, Row};
-use sqlx::{PgPool, Postgres};
+use sqlx::postgres::PgPoolOptions;
 use std::env;
 use std::net::SocketAddr;
 
 use crate::{
-    routes::data_management::get_table_data::GetTableDataRequest,
-    services::schema_info::{
-        fkey_info::get_all_foreign_keys,
-        pkey_info::special_column_info_pkeys,
-        special_column_info::special_column_info,
-        table_info::{self, get_table_info},
-    },
-    types::{arbitrary_sql_array_row::ArbitrarySqlArrayRow, arbitrary_sql_row::ArbitrarySqlRow},
+    services::endpoints::endpoint_registry::EndpointRegistry,
+    services::endpoints::rate_limiter::RateLimiter,
 };
 
 mod algorithms;
 mod auth;
-mod db_utils;
 mod err_utils;
 mod routes;
 mod services;
 mod setup;
+#[cfg(test)]
+mod test_database;
 mod types;
 
 #[tokio::main]
@@ -59,28 +51,33 @@ async fn main() -> anyhow::Result<()> {
         .await?;
     assert_eq!(row.0, 150);
 
-    setup::setup_internal_tables::init_tables(&db_pool).await?;
-
-    let _ = dbg!(special_column_info(&db_pool).await);
-    println!(
-        "{}",
-        serde_json::to_string_pretty(&get_table_info(&db_pool).await?)?
-    );
-
-    println!(
-        "{}",
-        serde_json::to_string(
-            &sqlx::query_as::<Postgres, ArbitrarySqlRow>(
-                "select (10 +10)::text as first, (2*2)::text as second"
-            )
-            .fetch_one(&db_pool)
-            .await?
-        )?
-    );
-
-    use crate::routes::data_management::get_table_data::{
-        GetTableDataRequest, Sorting, WhereClause,
-    };
+    let options = setup::manifest::StartupOptions::from_args()?;
+    let manifest = options
+        .manifest_dir
+        .as_deref()
+        .map(setup::manifest::Manifest::load)
+        .transpose()?;
+
+    // Checked before `init_tables`, which would change the database
+    if options.check {
+        let manifest = manifest.unwrap_or_default();
+        let drift = setup::manifest::check_manifest(&db_pool, &manifest).await?;
+
+        for item in &drift {
+            println!("{}", item);
+        }
+        std::process::exit(if drift.is_empty() { 0 } else { 1 });
+    }
+
+    setup::setup_internal_tables::init_tables(&db_pool, manifest.as_ref()).await?;
+
+    let endpoint_registry = EndpointRegistry::load(&db_pool).await?;
+    endpoint_registry.listen_for_changes(db_pool.clone());
+
+    let rate_limiter = RateLimiter::from_env(&db_pool)?;
+    // Fails at startup rather than on the first call when the header name is bad
+    Lazy::force(&routes::custom_endpoints::CLIENT_IP_HEADER);
+    rate_limiter.prune_periodically();
 
     let app = Router::new()
         .route(
@@ -95,6 +92,22 @@ async fn main() -> anyhow::Result<()> {
             "/api/test-endpoint",
             post(routes::custom_endpoints::endpoint_test::endpoint_test),
         )
+        .route(
+            "/api/test-endpoint-draft",
+            post(routes::custom_endpoints::endpoint_test::endpoint_draft_test),
+        )
+        .route(
+            "/api/save-endpoint-draft",
+            post(routes::custom_endpoints::endpoint_crud::save_endpoint_draft),
+        )
+        .route(
+            "/api/publish-endpoint",
+            post(routes::custom_endpoints::endpoint_crud::publish_endpoint),
+        )
+        .route(
+            "/api/discard-endpoint-draft",
+            post(routes::custom_endpoints::endpoint_crud::discard_endpoint_draft),
+        )
         .route(
             "/api/get-endpoints",
             get(routes::custom_endpoints::endpoint_crud::get_endpoints),
@@ -107,6 +120,34 @@ async fn main() -> anyhow::Result<()> {
             "/api/delete-endpoint",
             post(routes::custom_endpoints::endpoint_crud::delete_endpoint),
         )
+        .route(
+            "/api/endpoint-versions",
+            post(routes::custom_endpoints::endpoint_versions::get_endpoint_versions),
+        )
+        .route(
+            "/api/diff-endpoint-versions",
+            post(routes::custom_endpoints::endpoint_versions::diff_endpoint_versions),
+        )
+        .route(
+            "/api/rollback-endpoint",
+            post(routes::custom_endpoints::endpoint_versions::rollback_endpoint),
+        )
+        .route(
+            "/api/export-endpoints",
+            get(routes::custom_endpoints::endpoint_bundle::export_endpoints),
+        )
+        .route(
+            "/api/import-endpoints",
+            post(routes::custom_endpoints::endpoint_bundle::import_endpoints),
+        )
+        .route(
+            "/api/openapi.json",
+            get(routes::custom_endpoints::openapi::get_openapi),
+        )
+        .route(
+            "/api/client.ts",
+            get(routes::custom_endpoints::openapi::get_typescript_client),
+        )
         .route("/api/create-table", post(create_table_form))
         .route(
             "/api/table-info",
@@ -141,12 +182,15 @@ async fn main() -> anyhow::Result<()> {
             "/api/users-info",
             post(auth::get_users_route::get_users_route),
         )
-        .layer(AddExtensionLayer::new(db_pool));
+        .layer(AddExtensionLayer::new(db_pool))
+        .layer(AddExtensionLayer::new(endpoint_registry))
+        .layer(AddExtensionLayer::new(rate_limiter));
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 8080));
     tracing::debug!("listening on {}", addr);
     axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+        // The address of the client is needed to rate limit anonymous callers
+        .serve(app.into_make_service_with_connect_info::<SocketAddr, _>())
         .await?;
 
     Ok(())
@@ -0,0 +1,96 @@
+use super::endpoint_crud::to_save_error;
+use crate::auth::Claims;
+use crate::err_utils::{to_internal, ErrorResponse};
+use crate::services::endpoints::{
+    endpoint_bundle::{export_bundle, import_bundle, EndpointBundle, ImportReport},
+    endpoint_registry::EndpointRegistry,
+};
+use axum::{
+    extract::{Extension, Query},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    Json,
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    /// `json` or `yaml`, json when not set
+    format: Option<String>,
+}
+
+/// Bundle of all endpoints as a file
+pub async fn export_endpoints(
+    Extension(db_pool): Extension<PgPool>,
+    Query(query): Query<ExportQuery>,
+    claims: Claims,
+) -> Result<(HeaderMap, String), (StatusCode, String)> {
+    claims.must_be_admin()?;
+
+    let bundle = export_bundle(&db_pool).await.map_err(to_internal)?;
+
+    let (content, content_type, file_name) = match query.format.as_deref() {
+        None | Some("json") => (
+            serde_json::to_string_pretty(&bundle).map_err(to_internal)?,
+            "application/json",
+            "endpoints.json",
+        ),
+        Some("yaml") => (
+            serde_yaml::to_string(&bundle).map_err(to_internal)?,
+            "application/yaml",
+            "endpoints.yaml",
+        ),
+        Some(other) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Unknown format {}, use json or yaml", other),
+            ))
+        }
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    if let Ok(value) = HeaderValue::from_str(&format!("attachment; filename=\"{}\"", file_name)) {
+        headers.insert(header::CONTENT_DISPOSITION, value);
+    }
+
+    Ok((headers, content))
+}
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Replaces the endpoints with the ones of a bundle, the body is the json or yaml file
+pub async fn import_endpoints(
+    Extension(db_pool): Extension<PgPool>,
+    Extension(registry): Extension<EndpointRegistry>,
+    Query(query): Query<ImportQuery>,
+    claims: Claims,
+    body: String,
+) -> Result<Json<ImportReport>, ErrorResponse> {
+    claims.must_be_admin()?;
+
+    // Json is also yaml
+    let bundle: EndpointBundle = serde_yaml::from_str(&body).map_err(|e| {
+        ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            format!("Couldn't parse the bundle: {}", e),
+        )
+    })?;
+
+    let report = import_bundle(&db_pool, bundle, query.dry_run, claims.username())
+        .await
+        .map_err(to_save_error)?;
+
+    if !report.dry_run && !report.plan.changes.is_empty() {
+        registry
+            .endpoints_changed(&db_pool)
+            .await
+            .map_err(to_internal)?;
+    }
+
+    Ok(Json(report))
+}
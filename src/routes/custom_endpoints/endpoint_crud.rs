@@ -1,27 +1,44 @@
-use crate::{auth::Claims, err_utils::to_internal};
+use crate::{
+    auth::Claims,
+    err_utils::{to_internal, to_status, ErrorResponse},
+    services::endpoints::{
+        endpoint_registry::EndpointRegistry, endpoint_validation::EndpointValidationErrors,
+    },
+};
 use axum::extract::Extension;
 use axum::Json;
-use hyper::StatusCode;
+use hyper::{Method, StatusCode};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 
-use crate::algorithms::sql_variable_parser::{EndpointInfo, EndpointInfoCreateRequest};
+use crate::algorithms::{
+    endpoint_response::EndpointResponse, execution_limits::ExecutionLimits,
+    input_schema::InputSchema, rate_limit::RateLimit,
+    sql_variable_parser::EndpointInfoCreateRequest,
+};
 
 use crate::services::endpoints::crud_endoints as endpoint_services;
 
-#[derive(Deserialize, Serialize)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub enum CreateEndpointMethod {
     GET,
     POST,
+    PUT,
+    PATCH,
+    DELETE,
     ANY,
 }
 
 impl CreateEndpointMethod {
     pub fn to_string(&self) -> &'static str {
-        match self {
-            &Self::GET => "GET",
-            &Self::POST => "POST",
-            &Self::ANY => "ANY",
+        match *self {
+            Self::GET => "GET",
+            Self::POST => "POST",
+            Self::PUT => "PUT",
+            Self::PATCH => "PATCH",
+            Self::DELETE => "DELETE",
+            Self::ANY => "ANY",
         }
     }
 
@@ -29,10 +46,22 @@ impl CreateEndpointMethod {
         match s {
             "GET" => Ok(Self::GET),
             "POST" => Ok(Self::POST),
+            "PUT" => Ok(Self::PUT),
+            "PATCH" => Ok(Self::PATCH),
+            "DELETE" => Ok(Self::DELETE),
             "ANY" => Ok(Self::ANY),
             a => Err(anyhow::anyhow!("{} is not a method", a)),
         }
     }
+
+    pub fn allows(&self, method: &Method) -> bool {
+        *self == Self::ANY || self.to_string() == method.as_str()
+    }
+
+    /// Whether some request method is allowed by both
+    pub fn overlaps(&self, other: &Self) -> bool {
+        *self == Self::ANY || *other == Self::ANY || self == other
+    }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -41,6 +70,18 @@ pub struct CreateEndpointRequest {
     pub method: CreateEndpointMethod,
     pub endpoints_info: Vec<EndpointInfoCreateRequest>,
     pub allowed_groups: Vec<String>,
+    #[serde(default)]
+    pub response: EndpointResponse,
+    #[serde(default)]
+    pub input_schema: InputSchema,
+    /// Calls aren't limited when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimit>,
+    /// Seconds responses of a GET endpoint are reused, they aren't cached when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_ttl: Option<u32>,
+    #[serde(default, skip_serializing_if = "ExecutionLimits::is_unset")]
+    pub limits: ExecutionLimits,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -50,16 +91,59 @@ pub struct GetEndpointInfo {
     pub method: CreateEndpointMethod,
     pub endpoints_info: Vec<EndpointInfoCreateRequest>,
     pub allowed_groups: Vec<String>,
+    #[serde(default)]
+    pub response: EndpointResponse,
+    #[serde(default)]
+    pub input_schema: InputSchema,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimit>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_ttl: Option<u32>,
+    #[serde(default, skip_serializing_if = "ExecutionLimits::is_unset")]
+    pub limits: ExecutionLimits,
+    /// Unpublished changes of the endpoint
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub draft: Option<CreateEndpointRequest>,
+}
+
+impl From<GetEndpointInfo> for CreateEndpointRequest {
+    fn from(endpoint: GetEndpointInfo) -> Self {
+        Self {
+            path: endpoint.path,
+            method: endpoint.method,
+            endpoints_info: endpoint.endpoints_info,
+            allowed_groups: endpoint.allowed_groups,
+            response: endpoint.response,
+            input_schema: endpoint.input_schema,
+            rate_limit: endpoint.rate_limit,
+            cache_ttl: endpoint.cache_ttl,
+            limits: endpoint.limits,
+        }
+    }
+}
+
+/// Validation errors are sent as json, so they can be shown next to the queries
+pub fn to_save_error(e: anyhow::Error) -> ErrorResponse {
+    match e.downcast::<EndpointValidationErrors>() {
+        Ok(validation_errors) => ErrorResponse::json(StatusCode::BAD_REQUEST, &validation_errors),
+        Err(e) => to_status(e).into(),
+    }
 }
 
 pub async fn create_endpoint(
     Extension(db_pool): Extension<PgPool>,
+    Extension(registry): Extension<EndpointRegistry>,
     Json(req): Json<CreateEndpointRequest>,
     claims: Claims,
-) -> Result<(), (StatusCode, String)> {
+) -> Result<(), ErrorResponse> {
     claims.must_be_admin()?;
 
-    endpoint_services::create_endpoint(&db_pool, req)
+    endpoint_services::create_endpoint(&db_pool, req, claims.username())
+        .await
+        .map_err(to_save_error)?;
+
+    registry
+        .endpoints_changed(&db_pool)
         .await
         .map_err(to_internal)?;
 
@@ -85,9 +169,22 @@ pub struct UpdateEndpointRequest {
     pub method: CreateEndpointMethod,
     pub endpoints_info: Vec<EndpointInfoCreateRequest>,
     pub allowed_groups: Vec<String>,
+    #[serde(default)]
+    pub response: EndpointResponse,
+    #[serde(default)]
+    pub input_schema: InputSchema,
+    /// Calls aren't limited when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimit>,
+    /// Seconds responses of a GET endpoint are reused, they aren't cached when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_ttl: Option<u32>,
+    #[serde(default, skip_serializing_if = "ExecutionLimits::is_unset")]
+    pub limits: ExecutionLimits,
 }
 
 impl UpdateEndpointRequest {
+    #[allow(clippy::wrong_self_convention)]
     pub fn to_create_and_id(self) -> (CreateEndpointRequest, i32) {
         (
             CreateEndpointRequest {
@@ -95,6 +192,11 @@ impl UpdateEndpointRequest {
                 method: self.method,
                 endpoints_info: self.endpoints_info,
                 allowed_groups: self.allowed_groups,
+                response: self.response,
+                input_schema: self.input_schema,
+                rate_limit: self.rate_limit,
+                cache_ttl: self.cache_ttl,
+                limits: self.limits,
             },
             self.id,
         )
@@ -103,17 +205,72 @@ impl UpdateEndpointRequest {
 
 pub async fn update_endpoint(
     Extension(db_pool): Extension<PgPool>,
+    Extension(registry): Extension<EndpointRegistry>,
     Json(update_req): Json<UpdateEndpointRequest>,
     claims: Claims,
-) -> Result<(), (StatusCode, String)> {
+) -> Result<(), ErrorResponse> {
     claims.must_be_admin()?;
     let (req, endpoint_id) = update_req.to_create_and_id();
-    endpoint_services::update_endpoint(&db_pool, endpoint_id, req)
+    endpoint_services::update_endpoint(&db_pool, endpoint_id, req, claims.username())
+        .await
+        .map_err(to_save_error)?;
+
+    registry
+        .endpoints_changed(&db_pool)
         .await
         .map_err(to_internal)?;
     Ok(())
 }
 
+/// Saves the definition as the draft of the endpoint, the live endpoint doesn't change
+pub async fn save_endpoint_draft(
+    Extension(db_pool): Extension<PgPool>,
+    Json(draft_req): Json<UpdateEndpointRequest>,
+    claims: Claims,
+) -> Result<(), ErrorResponse> {
+    claims.must_be_admin()?;
+    let (req, endpoint_id) = draft_req.to_create_and_id();
+    endpoint_services::save_draft(&db_pool, endpoint_id, req)
+        .await
+        .map_err(to_save_error)?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct EndpointDraftRequest {
+    id: i32,
+}
+
+pub async fn publish_endpoint(
+    Extension(db_pool): Extension<PgPool>,
+    Extension(registry): Extension<EndpointRegistry>,
+    Json(req): Json<EndpointDraftRequest>,
+    claims: Claims,
+) -> Result<(), ErrorResponse> {
+    claims.must_be_admin()?;
+    endpoint_services::publish_draft(&db_pool, req.id, claims.username())
+        .await
+        .map_err(to_save_error)?;
+
+    registry
+        .endpoints_changed(&db_pool)
+        .await
+        .map_err(to_internal)?;
+    Ok(())
+}
+
+pub async fn discard_endpoint_draft(
+    Extension(db_pool): Extension<PgPool>,
+    Json(req): Json<EndpointDraftRequest>,
+    claims: Claims,
+) -> Result<(), (StatusCode, String)> {
+    claims.must_be_admin()?;
+    endpoint_services::discard_draft(&db_pool, req.id)
+        .await
+        .map_err(to_status)?;
+    Ok(())
+}
+
 #[derive(Deserialize)]
 pub struct DeleteEndpointRequest {
     id: i32,
@@ -121,13 +278,40 @@ pub struct DeleteEndpointRequest {
 
 pub async fn delete_endpoint(
     Extension(db_pool): Extension<PgPool>,
+    Extension(registry): Extension<EndpointRegistry>,
     Json(req): Json<DeleteEndpointRequest>,
     claims: Claims,
 ) -> Result<(), (StatusCode, String)> {
     claims.must_be_admin()?;
-    endpoint_services::delete_endpoint(&db_pool, req.id)
+    endpoint_services::delete_endpoint(&db_pool, req.id, claims.username())
+        .await
+        .map_err(to_status)?;
+
+    registry
+        .endpoints_changed(&db_pool)
         .await
         .map_err(to_internal)?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn method_matching() {
+        assert!(CreateEndpointMethod::GET.allows(&Method::GET));
+        assert!(!CreateEndpointMethod::GET.allows(&Method::POST));
+        assert!(CreateEndpointMethod::ANY.allows(&Method::PATCH));
+        assert!(CreateEndpointMethod::DELETE.allows(&Method::DELETE));
+    }
+
+    #[test]
+    fn method_overlapping() {
+        assert!(CreateEndpointMethod::PUT.overlaps(&CreateEndpointMethod::PUT));
+        assert!(CreateEndpointMethod::ANY.overlaps(&CreateEndpointMethod::POST));
+        assert!(CreateEndpointMethod::POST.overlaps(&CreateEndpointMethod::ANY));
+        assert!(!CreateEndpointMethod::GET.overlaps(&CreateEndpointMethod::POST));
+    }
+}
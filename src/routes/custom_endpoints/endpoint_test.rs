@@ -1,15 +1,15 @@
-use std::collections::HashMap;
-
 use super::endpoint_crud::CreateEndpointRequest;
-use crate::err_utils::to_internal;
+use crate::err_utils::to_status;
+use crate::services::endpoints::crud_endoints::get_draft;
+use crate::services::endpoints::endpoint_execution::DEFAULT_LIMITS;
 use crate::services::endpoints::endpoint_test::test_endpoint as test_endpoint_service;
 use crate::{algorithms::sql_variable_parser::EndpointInfo, auth::Claims};
 use axum::{
-    body::HttpBody,
     extract::{Extension, Json},
     http::StatusCode,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use sqlx::PgPool;
 
 #[derive(Serialize)]
@@ -21,7 +21,7 @@ pub struct EndpointTestResult {
 #[derive(Deserialize)]
 pub struct EndpointTestRequest {
     pub create_req: CreateEndpointRequest,
-    pub req_variables: HashMap<String, String>,
+    pub req_variables: Value,
 }
 
 pub async fn endpoint_test(
@@ -31,35 +31,68 @@ pub async fn endpoint_test(
 ) -> Result<Json<EndpointTestResult>, (StatusCode, String)> {
     claims.must_be_admin()?;
 
-    let parsed_endpoints_result = req
-        .create_req
+    Ok(Json(
+        run_test(&db_pool, req.create_req, req.req_variables, &claims).await,
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct EndpointDraftTestRequest {
+    pub id: i32,
+    pub req_variables: Value,
+}
+
+/// Tests the draft of a stored endpoint, like `endpoint_test`
+pub async fn endpoint_draft_test(
+    claims: Claims,
+    Json(req): Json<EndpointDraftTestRequest>,
+    Extension(db_pool): Extension<PgPool>,
+) -> Result<Json<EndpointTestResult>, (StatusCode, String)> {
+    claims.must_be_admin()?;
+
+    let draft = get_draft(&db_pool, req.id).await.map_err(to_status)?;
+
+    Ok(Json(
+        run_test(&db_pool, draft, req.req_variables, &claims).await,
+    ))
+}
+
+/// Executes the endpoint in a transaction which is rolled back
+async fn run_test(
+    db_pool: &PgPool,
+    create_req: CreateEndpointRequest,
+    req_variables: Value,
+    claims: &Claims,
+) -> EndpointTestResult {
+    let limits = create_req.limits.or(&DEFAULT_LIMITS);
+
+    let parsed_endpoints_result = create_req
         .endpoints_info
         .into_iter()
         .map(EndpointInfo::from_request)
         .collect::<anyhow::Result<Vec<EndpointInfo>>>();
 
-    if let Err(err) = parsed_endpoints_result {
-        return Ok(Json(EndpointTestResult {
-            ok: false,
-            msg: format!("{}", err),
-        }));
-    }
+    let parsed_endpoints = match parsed_endpoints_result {
+        Ok(parsed_endpoints) => parsed_endpoints,
+        Err(err) => {
+            return EndpointTestResult {
+                ok: false,
+                msg: format!("{}", err),
+            }
+        }
+    };
 
-    let result = test_endpoint_service(
-        &db_pool,
-        parsed_endpoints_result.unwrap(),
-        req.req_variables,
-    )
-    .await;
+    let result =
+        test_endpoint_service(db_pool, parsed_endpoints, req_variables, claims, &limits).await;
 
     match result {
-        Ok(result) => Ok(Json(EndpointTestResult {
+        Ok(result) => EndpointTestResult {
             ok: true,
             msg: serde_json::to_string_pretty(&result).unwrap(),
-        })),
-        Err(err) => Ok(Json(EndpointTestResult {
+        },
+        Err(err) => EndpointTestResult {
             ok: false,
             msg: format!("{}", err),
-        })),
+        },
     }
 }
@@ -0,0 +1,76 @@
+use crate::algorithms::json_diff::JsonChange;
+use crate::auth::Claims;
+use crate::err_utils::{to_internal, to_status, ErrorResponse};
+use crate::services::endpoints::{
+    crud_endoints, endpoint_registry::EndpointRegistry, endpoint_versions,
+    endpoint_versions::EndpointVersion,
+};
+use axum::{extract::Extension, http::StatusCode, Json};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use super::endpoint_crud::to_save_error;
+
+#[derive(Deserialize)]
+pub struct EndpointVersionsRequest {
+    endpoint_id: i32,
+}
+
+pub async fn get_endpoint_versions(
+    Extension(db_pool): Extension<PgPool>,
+    Json(req): Json<EndpointVersionsRequest>,
+    claims: Claims,
+) -> Result<Json<Vec<EndpointVersion>>, (StatusCode, String)> {
+    claims.must_be_admin()?;
+    Ok(Json(
+        endpoint_versions::list_versions(&db_pool, req.endpoint_id)
+            .await
+            .map_err(to_internal)?,
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct DiffEndpointVersionsRequest {
+    endpoint_id: i32,
+    from: i32,
+    to: i32,
+}
+
+pub async fn diff_endpoint_versions(
+    Extension(db_pool): Extension<PgPool>,
+    Json(req): Json<DiffEndpointVersionsRequest>,
+    claims: Claims,
+) -> Result<Json<Vec<JsonChange>>, (StatusCode, String)> {
+    claims.must_be_admin()?;
+    Ok(Json(
+        endpoint_versions::diff_versions(&db_pool, req.endpoint_id, req.from, req.to)
+            .await
+            .map_err(to_status)?,
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct RollbackEndpointRequest {
+    endpoint_id: i32,
+    version: i32,
+}
+
+pub async fn rollback_endpoint(
+    Extension(db_pool): Extension<PgPool>,
+    Extension(registry): Extension<EndpointRegistry>,
+    Json(req): Json<RollbackEndpointRequest>,
+    claims: Claims,
+) -> Result<(), ErrorResponse> {
+    claims.must_be_admin()?;
+
+    crud_endoints::rollback_endpoint(&db_pool, req.endpoint_id, req.version, claims.username())
+        .await
+        .map_err(to_save_error)?;
+
+    registry
+        .endpoints_changed(&db_pool)
+        .await
+        .map_err(to_internal)?;
+
+    Ok(())
+}
@@ -1,91 +1,332 @@
+pub mod endpoint_bundle;
 pub mod endpoint_crud;
 pub mod endpoint_test;
+pub mod endpoint_versions;
+pub mod openapi;
 
-use crate::algorithms::endpoint_execution::ExecutionResult;
+use crate::algorithms::rate_limit::{forwarded_ip, retry_after_seconds};
+use crate::algorithms::response_cache::{cache_caller, cache_key, etag, uses_user_variables};
 use crate::auth::Claims;
-use crate::services::endpoints::endpoint_execution::execute_endpoint;
-use crate::{algorithms::sql_variable_parser::EndpointInfo, err_utils::to_internal};
+use crate::err_utils::{to_internal, to_status, ErrorResponse};
+use crate::services::endpoints::{
+    endpoint_execution::{execute_endpoint, DEFAULT_LIMITS},
+    endpoint_registry::{EndpointMatch, EndpointRegistry, RegisteredEndpoint},
+    rate_limiter::RateLimiter,
+};
+use crate::services::row_level_security::{self, ANONYMOUS_GROUP};
 use axum::{
-    body::HttpBody,
+    async_trait,
     extract::{
         rejection::{FormRejection, JsonRejection},
-        Extension, Form, Json, Path,
+        ConnectInfo, Extension, Form, FromRequest, Json, Path, RequestParts, TypedHeader,
     },
-    http::StatusCode,
+    http::{header, header::HeaderName, HeaderMap, HeaderValue, Method, Response, StatusCode},
+    response::IntoResponse,
 };
+use endpoint_crud::CreateEndpointMethod;
+use headers::{ETag, IfNoneMatch};
+use once_cell::sync::Lazy;
+use serde_json::Value;
 use sqlx::PgPool;
-use sqlx::Postgres;
-use sqlx::{Executor, FromRow};
 use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
 
-#[derive(FromRow)]
-pub struct EndpointExecutionInfo {
-    pub req_method: String,
-    pub handler_info: String,
-    pub allowed_groups: String,
-}
-
+#[allow(clippy::too_many_arguments)]
 pub async fn custom_endpoint(
     path: Path<String>,
+    method: Method,
     Extension(db_pool): Extension<PgPool>,
+    Extension(registry): Extension<EndpointRegistry>,
+    Extension(rate_limiter): Extension<RateLimiter>,
+    ClientIp(client_ip): ClientIp,
     form_result: Result<Form<HashMap<String, String>>, FormRejection>,
-    json_result: Result<Json<HashMap<String, String>>, JsonRejection>,
+    json_result: Result<Json<Value>, JsonRejection>,
     claims_opt: Option<Claims>,
-) -> Result<Json<HashMap<String, Vec<ExecutionResult>>>, (StatusCode, String)> {
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+) -> Result<Response<<String as IntoResponse>::Body>, ErrorResponse> {
+    let path = path.to_string();
+
+    let (endpoint, path_params) = match registry.find(&path, &method) {
+        EndpointMatch::Found(endpoint, path_params) => (endpoint, path_params),
+        EndpointMatch::MethodNotAllowed(allowed_methods) => {
+            return Err(method_not_allowed(&method, &allowed_methods))
+        }
+        EndpointMatch::NotFound => {
+            return Err(ErrorResponse::new(
+                StatusCode::NOT_FOUND,
+                "Endpoint not found",
+            ))
+        }
+    };
+
     let arguments = match (form_result, json_result) {
+        // Bodyless requests, e.g. DELETE with only path parameters
+        (
+            Err(FormRejection::InvalidFormContentType(_)),
+            Err(JsonRejection::MissingJsonContentType(_)),
+        ) => Value::Object(Default::default()),
         (Err(form_err), Err(json_err)) => {
-            return Err((
+            return Err(ErrorResponse::new(
                 StatusCode::BAD_REQUEST,
                 format!("Couldn't parse form ({}) or json ({})", form_err, json_err),
             ))
         }
-        (Ok(Form(form)), Err(_)) => form,
+        (Ok(Form(form)), Err(_)) => form_to_json(form),
         (Err(_), Ok(Json(json))) => json,
 
         // If both json and form, ignore json.
         // Happens when GET request has query params
         // and json body.
-        (Ok(Form(form)), Ok(_json)) => form,
+        (Ok(Form(form)), Ok(_json)) => form_to_json(form),
     };
 
-    let path = path.to_string();
+    if !arguments.is_object() {
+        return Err(ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            "Request body must be a json object",
+        ));
+    }
 
-    dbg!(&path, &arguments, &claims_opt);
+    let caller = claims_opt.as_ref().map(Claims::username);
+    tracing::debug!("Calling {} {} as {:?}", method, path, caller);
 
-    let endpoint_info = sqlx::query_as::<Postgres, EndpointExecutionInfo>(
-        "SELECT req_method, handler_info, allowed_groups FROM __B_endpoints WHERE req_path=$1",
+    if !can_call_endpoint(claims_opt.as_ref(), &endpoint.allowed_groups) {
+        return Err(ErrorResponse::new(
+            StatusCode::UNAUTHORIZED,
+            "You are not authorized to call this endpoint",
+        ));
+    }
+
+    if let Some(rate_limit) = &endpoint.rate_limit {
+        let key = rate_limit_key(endpoint.id, claims_opt.as_ref(), client_ip);
+
+        if let Err(wait) = rate_limiter
+            .take(&key, rate_limit)
+            .await
+            .map_err(to_status)?
+        {
+            return Err(too_many_requests(wait));
+        }
+    }
+
+    if let Err(input_errors) = endpoint.input_schema.validate(&arguments) {
+        return Err(ErrorResponse::json(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            &input_errors,
+        ));
+    }
+
+    let cache_key = endpoint.cache_ttl.map(|_| {
+        // RLS policies can read the username, like `${user.}` variables
+        let per_user =
+            *row_level_security::ENABLED || uses_user_variables(&endpoint.endpoint_infos);
+        let caller = match &claims_opt {
+            Some(claims) => cache_caller(Some(claims.username()), claims.user_group(), per_user),
+            None => cache_caller(None, ANONYMOUS_GROUP, per_user),
+        };
+        cache_key(&path, &caller, &arguments)
+    });
+
+    if let Some(cached) = cache_key.as_deref().and_then(|key| endpoint.cache.get(key)) {
+        return Ok(respond(
+            &endpoint,
+            if_none_match.as_ref(),
+            cached.body,
+            cached.etag,
+            cached.location,
+        ));
+    }
+
+    let result = execute_endpoint(
+        &db_pool,
+        &endpoint.endpoint_infos,
+        arguments.clone(),
+        path_params.clone(),
+        claims_opt.as_ref(),
+        &endpoint.limits.or(&DEFAULT_LIMITS),
     )
-    .bind(&path)
-    .fetch_one(&db_pool)
     .await
-    .map_err(to_internal)?;
+    .map_err(to_status)?;
+
+    let location = endpoint
+        .response
+        .location(&result, &arguments, &path_params)
+        .map_err(to_status)?
+        .map(|location| HeaderValue::from_str(&location))
+        .transpose()
+        .map_err(to_internal)?;
+
+    let body: Arc<str> = serde_json::to_string(&result).map_err(to_internal)?.into();
+    let etag = etag(&body);
+
+    if let (Some(key), Some(ttl)) = (cache_key, endpoint.cache_ttl) {
+        endpoint.cache.insert(
+            key,
+            body.clone(),
+            etag.clone(),
+            location.clone(),
+            Duration::from_secs(ttl.into()),
+        );
+    }
+
+    Ok(respond(
+        &endpoint,
+        if_none_match.as_ref(),
+        body,
+        etag,
+        location,
+    ))
+}
+
+/// Successful response, cached endpoints answer with 304 when the client has the body
+fn respond(
+    endpoint: &RegisteredEndpoint,
+    if_none_match: Option<&TypedHeader<IfNoneMatch>>,
+    body: Arc<str>,
+    etag: String,
+    location: Option<HeaderValue>,
+) -> Response<<String as IntoResponse>::Body> {
+    let mut headers = HeaderMap::new();
+
+    if let Some(location) = location {
+        headers.insert(header::LOCATION, location);
+    }
+
+    if let Some(ttl) = endpoint.cache_ttl {
+        // Responses differ between users, shared caches mustn't keep them
+        if let Ok(value) = HeaderValue::from_str(&format!("private, max-age={}", ttl)) {
+            headers.insert(header::CACHE_CONTROL, value);
+        }
+
+        let not_modified = match (if_none_match, etag.parse::<ETag>()) {
+            (Some(TypedHeader(if_none_match)), Ok(etag)) => {
+                !if_none_match.precondition_passes(&etag)
+            }
+            _ => false,
+        };
+
+        if let Ok(value) = HeaderValue::from_str(&etag) {
+            headers.insert(header::ETAG, value);
+        }
 
-    let allowed_groups =
-        serde_json::from_str::<Vec<String>>(&endpoint_info.allowed_groups).map_err(to_internal)?;
+        if not_modified {
+            return without_body(StatusCode::NOT_MODIFIED, headers);
+        }
+    }
 
-    dbg!(&allowed_groups);
+    let status = endpoint.response.status_code();
+
+    if !endpoint.response.has_body() {
+        return without_body(status, headers);
+    }
 
-    println!(
-        "can call endpoint: {}",
-        can_call_endpoint(claims_opt.as_ref(), allowed_groups.clone())
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
     );
 
-    if !can_call_endpoint(claims_opt.as_ref(), allowed_groups) {
-        return Err((
-            StatusCode::UNAUTHORIZED,
-            "You are not authorized to call this endpoint".into(),
-        ));
+    (status, headers, body.to_string()).into_response()
+}
+
+/// Response with an empty body and no `Content-Type`, which axum sets for strings
+fn without_body(
+    status: StatusCode,
+    headers: HeaderMap,
+) -> Response<<String as IntoResponse>::Body> {
+    let mut response = (status, headers, String::new()).into_response();
+    response.headers_mut().remove(header::CONTENT_TYPE);
+    response
+}
+
+fn method_not_allowed(method: &Method, allowed_methods: &[CreateEndpointMethod]) -> ErrorResponse {
+    let mut allowed = allowed_methods
+        .iter()
+        .map(CreateEndpointMethod::to_string)
+        .collect::<Vec<_>>();
+    allowed.sort_unstable();
+    let allow = allowed.join(", ");
+
+    let mut headers = HeaderMap::new();
+    if let Ok(value) = HeaderValue::from_str(&allow) {
+        headers.insert(header::ALLOW, value);
     }
 
-    let result = execute_endpoint(&db_pool, endpoint_info, arguments)
-        .await
-        .map_err(to_internal)?;
+    ErrorResponse {
+        status: StatusCode::METHOD_NOT_ALLOWED,
+        headers,
+        message: format!("Method {} is not allowed, use one of: {}", method, allow),
+    }
+}
+
+/// Header in which a reverse proxy passes the address of the client, e.g. `X-Forwarded-For`.
+/// Without it callers are told apart by the address of the connection,
+/// which behind a proxy is the same for all of them.
+pub static CLIENT_IP_HEADER: Lazy<Option<HeaderName>> = Lazy::new(|| {
+    std::env::var("CLIENT_IP_HEADER").ok().map(|name| {
+        HeaderName::from_bytes(name.as_bytes()).expect("CLIENT_IP_HEADER must be a header name")
+    })
+});
+
+/// IP of the caller, from `CLIENT_IP_HEADER` when it's set and holds an address
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl<B> FromRequest<B> for ClientIp
+where
+    B: Send,
+{
+    type Rejection = <ConnectInfo<SocketAddr> as FromRequest<B>>::Rejection;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let forwarded = CLIENT_IP_HEADER
+            .as_ref()
+            .and_then(|name| req.headers()?.get_all(name).iter().next_back())
+            .and_then(|value| value.to_str().ok())
+            .and_then(forwarded_ip);
+
+        match forwarded {
+            Some(ip) => Ok(Self(ip)),
+            None => {
+                let ConnectInfo(client_addr) = ConnectInfo::<SocketAddr>::from_request(req).await?;
+                Ok(Self(client_addr.ip()))
+            }
+        }
+    }
+}
 
-    Ok(Json(result))
+/// Authenticated callers are limited by username, anonymous ones by IP
+fn rate_limit_key(endpoint_id: i32, claims_opt: Option<&Claims>, client_ip: IpAddr) -> String {
+    match claims_opt {
+        Some(claims) => format!("{}:user:{}", endpoint_id, claims.username()),
+        None => format!("{}:ip:{}", endpoint_id, client_ip),
+    }
+}
+
+fn too_many_requests(wait: Duration) -> ErrorResponse {
+    let seconds = retry_after_seconds(wait);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+
+    ErrorResponse {
+        status: StatusCode::TOO_MANY_REQUESTS,
+        headers,
+        message: format!("Too many calls, retry in {} seconds", seconds),
+    }
+}
+
+fn form_to_json(form: HashMap<String, String>) -> Value {
+    Value::Object(
+        form.into_iter()
+            .map(|(key, value)| (key, Value::String(value)))
+            .collect(),
+    )
 }
 
-fn can_call_endpoint(claims_opt: Option<&Claims>, allowed_groups: Vec<String>) -> bool {
-    for group in &allowed_groups {
+fn can_call_endpoint(claims_opt: Option<&Claims>, allowed_groups: &[String]) -> bool {
+    for group in allowed_groups {
         if group == "PUBLIC" {
             return true;
         }
@@ -101,7 +342,7 @@ fn can_call_endpoint(claims_opt: Option<&Claims>, allowed_groups: Vec<String>) -
         return true;
     }
 
-    for group in &allowed_groups {
+    for group in allowed_groups {
         if current_group == group {
             return true;
         }
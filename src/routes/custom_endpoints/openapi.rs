@@ -0,0 +1,56 @@
+use super::can_call_endpoint;
+use crate::algorithms::typescript_client::typescript_client;
+use crate::auth::Claims;
+use crate::err_utils::to_internal;
+use crate::services::endpoints::{
+    endpoint_registry::EndpointRegistry, openapi::build_openapi_document,
+};
+use axum::{
+    extract::Extension,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    Json,
+};
+use serde_json::Value;
+use sqlx::PgPool;
+
+/// Document of the custom endpoints the caller is allowed to call
+async fn caller_document(
+    db_pool: &PgPool,
+    registry: &EndpointRegistry,
+    claims_opt: Option<&Claims>,
+) -> Result<Value, (StatusCode, String)> {
+    let mut endpoints = registry.endpoints();
+    endpoints.retain(|endpoint| can_call_endpoint(claims_opt, &endpoint.allowed_groups));
+
+    build_openapi_document(db_pool, &endpoints)
+        .await
+        .map_err(to_internal)
+}
+
+/// OpenAPI document of the custom endpoints the caller is allowed to call
+pub async fn get_openapi(
+    Extension(db_pool): Extension<PgPool>,
+    Extension(registry): Extension<EndpointRegistry>,
+    claims_opt: Option<Claims>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    Ok(Json(
+        caller_document(&db_pool, &registry, claims_opt.as_ref()).await?,
+    ))
+}
+
+/// TypeScript module with a typed function for every endpoint in the OpenAPI document
+pub async fn get_typescript_client(
+    Extension(db_pool): Extension<PgPool>,
+    Extension(registry): Extension<EndpointRegistry>,
+    claims_opt: Option<Claims>,
+) -> Result<(HeaderMap, String), (StatusCode, String)> {
+    let document = caller_document(&db_pool, &registry, claims_opt.as_ref()).await?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/typescript; charset=utf-8"),
+    );
+
+    Ok((headers, typescript_client(&document)))
+}
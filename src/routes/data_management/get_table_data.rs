@@ -1,3 +1,4 @@
+use crate::auth::Claims;
 use crate::err_utils::to_internal;
 use crate::services::data_management::get_table_data::get_table_data as table_data_service;
 use crate::types::arbitrary_sql_array_row::ArbitrarySqlArrayRowsAndNames;
@@ -35,8 +36,9 @@ pub struct GetTableDataRequest {
 pub async fn get_table_data(
     Extension(db_pool): Extension<PgPool>,
     Json(req): Json<GetTableDataRequest>,
+    claims_opt: Option<Claims>,
 ) -> Result<Json<ArbitrarySqlArrayRowsAndNames>, (StatusCode, String)> {
-    let data = table_data_service(&db_pool, &req)
+    let data = table_data_service(&db_pool, &req, claims_opt.as_ref())
         .await
         .map_err(to_internal)?;
 
@@ -1,3 +1,4 @@
+use crate::auth::Claims;
 use crate::services::data_management::insert_data::insert_data as insert_data_service;
 use axum::extract::{Extension, Json};
 use hyper::StatusCode;
@@ -20,8 +21,9 @@ pub struct InsertDataRequest {
 pub async fn insert_data(
     Extension(db_pool): Extension<PgPool>,
     Json(data): Json<InsertDataRequest>,
+    claims_opt: Option<Claims>,
 ) -> Result<(), (StatusCode, String)> {
-    insert_data_service(&db_pool, data)
+    insert_data_service(&db_pool, data, claims_opt.as_ref())
         .await
         .map_err(crate::err_utils::to_internal)?;
     Ok(())
@@ -1,3 +1,4 @@
+use crate::auth::Claims;
 use crate::err_utils::to_internal;
 use crate::types::arbitrary_sql_array_row::ArbitrarySqlArrayRowsAndNames;
 use axum::extract::Extension;
@@ -25,8 +26,11 @@ pub struct ExecuteQueriesResponse {
 pub async fn execute_queries(
     Json(req): Json<ExecuteQueriesRequest>,
     Extension(db_pool): Extension<PgPool>,
+    claims_opt: Option<Claims>,
 ) -> Result<Json<ExecuteQueriesResponse>, (StatusCode, String)> {
     use crate::services::sql_execution::execute_queries;
-    let result = execute_queries(&db_pool, &req).await.map_err(to_internal)?;
+    let result = execute_queries(&db_pool, &req, claims_opt.as_ref())
+        .await
+        .map_err(to_internal)?;
     Ok(Json(result))
 }
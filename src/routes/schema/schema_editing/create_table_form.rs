@@ -8,7 +8,7 @@ use crate::types::table_field_types::TableField;
 
 use serde::Deserialize;
 
-#[derive(Deserialize)]
+#[derive(Debug, Deserialize)]
 pub struct CreateTableFormRequest {
     pub table_name: String,
     pub table_fields: Vec<TableField>,
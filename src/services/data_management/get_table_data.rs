@@ -1,4 +1,6 @@
+use crate::auth::Claims;
 use crate::routes::data_management::get_table_data::{GetTableDataRequest, Sorting, WhereClause};
+use crate::services::row_level_security::begin_as;
 use crate::services::schema_info::table_info::get_table_info;
 use crate::types::arbitrary_sql_array_row::ArbitrarySqlArrayRowsAndNames;
 use crate::types::special_column_info::SpecialColumnType;
@@ -36,10 +38,10 @@ fn build_query(table_info: TableInfo, get_data_request: &GetTableDataRequest) ->
 
     match &get_data_request.sorting {
         Sorting::None => {
-            let first_pk = table_info.columns.iter().find(|x| match x.special_info {
-                Some(SpecialColumnType::PrimaryKey) => true,
-                _ => false,
-            });
+            let first_pk = table_info
+                .columns
+                .iter()
+                .find(|x| matches!(x.special_info, Some(SpecialColumnType::PrimaryKey)));
 
             if let Some(pk) = first_pk {
                 query.push_str(&format!(" ORDER BY {} DESC", pk.name))
@@ -69,6 +71,7 @@ fn build_query(table_info: TableInfo, get_data_request: &GetTableDataRequest) ->
 pub async fn get_table_data(
     db_pool: &PgPool,
     get_data_request: &GetTableDataRequest,
+    user: Option<&Claims>,
 ) -> Result<ArbitrarySqlArrayRowsAndNames> {
     let table_info = get_table_info(db_pool)
         .await?
@@ -77,9 +80,11 @@ pub async fn get_table_data(
         .ok_or(anyhow!("Table name not found"))?;
 
     let query = build_query(table_info, get_data_request);
-    println!("query: {}", query);
+    tracing::debug!("Reading table data: {}", query);
 
-    let rows = sqlx::query(&query).fetch_all(db_pool).await?;
+    let mut transaction = begin_as(db_pool, user).await?;
+    let rows = sqlx::query(&query).fetch_all(&mut transaction).await?;
+    transaction.commit().await?;
 
-    Ok(ArbitrarySqlArrayRowsAndNames::from_row_vec(rows)?)
+    ArbitrarySqlArrayRowsAndNames::from_row_vec(rows)
 }
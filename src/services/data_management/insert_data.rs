@@ -1,5 +1,7 @@
 use super::super::schema_info::table_info::get_table_info;
-use crate::routes::data_management::insert_data::{ColumnValue, InsertDataRequest};
+use crate::auth::Claims;
+use crate::routes::data_management::insert_data::InsertDataRequest;
+use crate::services::row_level_security::begin_as;
 use anyhow::{anyhow, Result};
 use sqlx::PgPool;
 
@@ -18,7 +20,7 @@ pub async fn build_insert_query(
         .find(|it| &it.table_name == table_name)
         .ok_or(anyhow!("Table of that name not found"))?;
 
-    let mut counter = (1..).into_iter();
+    let mut counter = 1..;
 
     for (info, insert_val) in table_info
         .columns
@@ -46,9 +48,13 @@ pub async fn build_insert_query(
     ))
 }
 
-pub async fn insert_data(db_pool: &PgPool, data: InsertDataRequest) -> Result<()> {
+pub async fn insert_data(
+    db_pool: &PgPool,
+    data: InsertDataRequest,
+    user: Option<&Claims>,
+) -> Result<()> {
     let query = build_insert_query(db_pool, &data).await?;
-    println!("{}", query);
+    tracing::debug!("Inserting data: {}", query);
 
     let mut q = sqlx::query(&query);
 
@@ -58,7 +64,9 @@ pub async fn insert_data(db_pool: &PgPool, data: InsertDataRequest) -> Result<()
         }
     }
 
-    q.execute(db_pool).await?;
+    let mut transaction = begin_as(db_pool, user).await?;
+    q.execute(&mut transaction).await?;
+    transaction.commit().await?;
 
     Ok(())
 }
@@ -1,16 +1,25 @@
+use crate::err_utils::status_error;
 use crate::routes::custom_endpoints::endpoint_crud::{CreateEndpointRequest, GetEndpointInfo};
+use crate::services::endpoints::endpoint_validation::validate_endpoints;
+use crate::services::endpoints::endpoint_versions::{get_version, record_version, VersionAction};
 use crate::{
-    algorithms::sql_variable_parser::EndpointInfo,
+    algorithms::{route_pattern::RoutePattern, sql_variable_parser::EndpointInfo},
     routes::custom_endpoints::endpoint_crud::CreateEndpointMethod,
 };
 use anyhow::Result;
-use sqlx::{FromRow, PgPool, Postgres};
+use axum::http::StatusCode;
+use sqlx::{Executor, FromRow, PgPool, Postgres, Transaction};
 
 struct DbEndpoint {
     pub path: String,
     pub method: String,
     pub handler_info_json: String,
     pub allowed_groups_json: String,
+    pub response_json: String,
+    pub input_schema_json: String,
+    pub rate_limit_json: String,
+    pub cache_ttl_json: String,
+    pub limits_json: String,
 }
 
 fn parse_endpoints_vec(req: CreateEndpointRequest) -> Result<DbEndpoint> {
@@ -25,103 +34,583 @@ fn parse_endpoints_vec(req: CreateEndpointRequest) -> Result<DbEndpoint> {
     let parsed_endpoints_json_text = serde_json::to_string(&parsed_endpoints)?;
 
     let allowed_groups_json = serde_json::to_string(&req.allowed_groups)?;
+    let response_json = serde_json::to_string(&req.response)?;
+    let input_schema_json = serde_json::to_string(&req.input_schema)?;
+    let rate_limit_json = serde_json::to_string(&req.rate_limit)?;
+    let cache_ttl_json = serde_json::to_string(&req.cache_ttl)?;
+    let limits_json = serde_json::to_string(&req.limits)?;
 
     Ok(DbEndpoint {
         path: req.path.clone(),
         method: req.method.to_string().to_string(),
         handler_info_json: parsed_endpoints_json_text,
         allowed_groups_json,
+        response_json,
+        input_schema_json,
+        rate_limit_json,
+        cache_ttl_json,
+        limits_json,
     })
 }
 
-pub async fn create_endpoint(db_pool: &PgPool, req: CreateEndpointRequest) -> Result<()> {
+/// Blocks other writes of endpoints until the transaction ends,
+/// reading them is still possible
+pub async fn lock_endpoints(transaction: &mut Transaction<'_, Postgres>) -> Result<()> {
+    transaction
+        .execute("LOCK TABLE __B_endpoints IN SHARE ROW EXCLUSIVE MODE")
+        .await?;
+    Ok(())
+}
+
+/// Makes sure the path is a valid pattern and that no other endpoint
+/// with an overlapping method matches exactly the same paths.
+///
+/// The endpoints stay locked, so that a conflicting endpoint
+/// can't be saved before the transaction commits.
+async fn check_path_conflicts(
+    transaction: &mut Transaction<'_, Postgres>,
+    path: &str,
+    method: &CreateEndpointMethod,
+    endpoint_id: Option<i32>,
+) -> Result<()> {
+    let pattern =
+        RoutePattern::parse(path).map_err(|e| status_error(StatusCode::BAD_REQUEST, e))?;
+
+    lock_endpoints(transaction).await?;
+    let others = sqlx::query_as::<Postgres, (String, String)>(
+        "SELECT req_path, req_method FROM __B_endpoints WHERE id IS DISTINCT FROM $1::int",
+    )
+    .bind(endpoint_id)
+    .fetch_all(&mut *transaction)
+    .await?;
+
+    for (other_path, other_method) in others {
+        let other_method = CreateEndpointMethod::from_str(&other_method)?;
+
+        if method.overlaps(&other_method)
+            && pattern.conflicts_with(&RoutePattern::parse(&other_path)?)
+        {
+            return Err(status_error(
+                StatusCode::CONFLICT,
+                format!(
+                    "{} {} conflicts with existing endpoint {} {}",
+                    method.to_string(),
+                    path,
+                    other_method.to_string(),
+                    other_path
+                ),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Inserts the endpoint with the given id, or with a new one when it's `None`
+pub async fn insert_endpoint(
+    transaction: &mut Transaction<'_, Postgres>,
+    endpoint_id: Option<i32>,
+    req: CreateEndpointRequest,
+) -> Result<i32> {
     let db_endpoint = parse_endpoints_vec(req)?;
 
-    sqlx::query(
+    let (id,) = sqlx::query_as::<Postgres, (i32,)>(
         r#"
             INSERT INTO __B_endpoints 
-            (req_path, req_method, handler_info, allowed_groups)
-            VALUES ($1, $2, $3, $4)
+            (id, req_path, req_method, handler_info, allowed_groups, response, input_schema,
+                rate_limit, cache_ttl, limits)
+            VALUES (
+                COALESCE($1::int, nextval(pg_get_serial_sequence('__B_endpoints', 'id'))),
+                $2, $3, $4, $5, $6, $7, $8, $9, $10
+            )
+            RETURNING id::int
         "#,
     )
+    .bind(endpoint_id)
     .bind(db_endpoint.path)
     .bind(db_endpoint.method)
     .bind(db_endpoint.handler_info_json)
     .bind(db_endpoint.allowed_groups_json)
-    .execute(db_pool)
+    .bind(db_endpoint.response_json)
+    .bind(db_endpoint.input_schema_json)
+    .bind(db_endpoint.rate_limit_json)
+    .bind(db_endpoint.cache_ttl_json)
+    .bind(db_endpoint.limits_json)
+    .fetch_one(transaction)
     .await?;
 
-    Ok(())
+    Ok(id)
 }
 
-pub async fn get_endpoints(db_pool: &PgPool) -> Result<Vec<GetEndpointInfo>> {
-    #[derive(FromRow)]
-    struct DbReadEndpoint {
-        pub id: i32,
-        pub req_path: String,
-        pub req_method: String,
-        pub handler_info: String,
-        pub allowed_groups: String,
-    }
-
-    fn to_endpoint_info(db_read: DbReadEndpoint) -> Result<GetEndpointInfo> {
-        let req = GetEndpointInfo {
-            id: db_read.id,
-            path: db_read.req_path,
-            method: CreateEndpointMethod::from_str(&db_read.req_method)?,
-            allowed_groups: serde_json::from_str(&db_read.allowed_groups)?,
-            endpoints_info: serde_json::from_str::<Vec<EndpointInfo>>(&db_read.handler_info)?
-                .into_iter()
-                .map(EndpointInfo::to_request)
-                .collect(),
-        };
-        Ok(req)
-    }
+/// Overwrites the definition, fails with 404 when the endpoint doesn't exist
+pub async fn overwrite_endpoint(
+    transaction: &mut Transaction<'_, Postgres>,
+    endpoint_id: i32,
+    req: CreateEndpointRequest,
+) -> Result<()> {
+    let db_endpoint = parse_endpoints_vec(req)?;
 
-    let endpoints = sqlx::query_as::<Postgres, DbReadEndpoint>(
+    let updated = sqlx::query(
         r#"
-            SELECT id::int, req_path, req_method, handler_info, allowed_groups
-            FROM __B_endpoints
+            UPDATE __B_endpoints 
+            SET req_path=$1, req_method=$2, handler_info=$3, allowed_groups=$4, response=$5,
+                input_schema=$6, rate_limit=$7, cache_ttl=$8, limits=$9
+            where id=$10::int
         "#,
     )
-    .fetch_all(db_pool)
+    .bind(db_endpoint.path)
+    .bind(db_endpoint.method)
+    .bind(db_endpoint.handler_info_json)
+    .bind(db_endpoint.allowed_groups_json)
+    .bind(db_endpoint.response_json)
+    .bind(db_endpoint.input_schema_json)
+    .bind(db_endpoint.rate_limit_json)
+    .bind(db_endpoint.cache_ttl_json)
+    .bind(db_endpoint.limits_json)
+    .bind(endpoint_id)
+    .execute(transaction)
+    .await?
+    .rows_affected();
+
+    if updated == 0 {
+        return Err(endpoint_not_found(endpoint_id));
+    }
+
+    Ok(())
+}
+
+fn endpoint_not_found(endpoint_id: i32) -> anyhow::Error {
+    status_error(
+        StatusCode::NOT_FOUND,
+        format!("Endpoint {} not found", endpoint_id),
+    )
+}
+
+pub async fn create_endpoint(
+    db_pool: &PgPool,
+    req: CreateEndpointRequest,
+    author: &str,
+) -> Result<i32> {
+    validate_endpoints(db_pool, &req).await?;
+    let definition = serde_json::to_string(&req)?;
+
+    let mut transaction = db_pool.begin().await?;
+    check_path_conflicts(&mut transaction, &req.path, &req.method, None).await?;
+    let endpoint_id = insert_endpoint(&mut transaction, None, req).await?;
+    record_version(
+        &mut transaction,
+        endpoint_id,
+        VersionAction::Create,
+        &definition,
+        author,
+    )
     .await?;
+    transaction.commit().await?;
 
-    Ok(endpoints
+    Ok(endpoint_id)
+}
+
+#[derive(FromRow)]
+struct DbReadEndpoint {
+    pub id: i32,
+    pub req_path: String,
+    pub req_method: String,
+    pub handler_info: String,
+    pub allowed_groups: String,
+    pub response: String,
+    pub input_schema: String,
+    pub rate_limit: String,
+    pub cache_ttl: String,
+    pub limits: String,
+    pub draft: Option<String>,
+}
+
+const SELECT_ENDPOINTS: &str = r#"
+    SELECT id::int, req_path, req_method, handler_info, allowed_groups, response,
+        input_schema, rate_limit, cache_ttl, limits, draft
+    FROM __B_endpoints
+"#;
+
+fn to_endpoint_info(db_read: DbReadEndpoint) -> Result<GetEndpointInfo> {
+    let req = GetEndpointInfo {
+        id: db_read.id,
+        path: db_read.req_path,
+        method: CreateEndpointMethod::from_str(&db_read.req_method)?,
+        allowed_groups: serde_json::from_str(&db_read.allowed_groups)?,
+        endpoints_info: serde_json::from_str::<Vec<EndpointInfo>>(&db_read.handler_info)?
+            .into_iter()
+            .map(EndpointInfo::to_request)
+            .collect(),
+        response: serde_json::from_str(&db_read.response)?,
+        input_schema: serde_json::from_str(&db_read.input_schema)?,
+        rate_limit: serde_json::from_str(&db_read.rate_limit)?,
+        cache_ttl: serde_json::from_str(&db_read.cache_ttl)?,
+        limits: serde_json::from_str(&db_read.limits)?,
+        draft: db_read
+            .draft
+            .as_deref()
+            .map(serde_json::from_str)
+            .transpose()?,
+    };
+    Ok(req)
+}
+
+pub async fn get_endpoints<'e>(
+    executor: impl Executor<'e, Database = Postgres>,
+) -> Result<Vec<GetEndpointInfo>> {
+    let endpoints = sqlx::query_as::<Postgres, DbReadEndpoint>(SELECT_ENDPOINTS)
+        .fetch_all(executor)
+        .await?;
+
+    endpoints
         .into_iter()
         .map(to_endpoint_info)
-        .collect::<Result<Vec<GetEndpointInfo>>>()?)
+        .collect::<Result<Vec<GetEndpointInfo>>>()
+}
+
+pub async fn get_endpoint<'e>(
+    executor: impl Executor<'e, Database = Postgres>,
+    endpoint_id: i32,
+) -> Result<Option<GetEndpointInfo>> {
+    let query = format!("{} WHERE id=$1::int", SELECT_ENDPOINTS);
+
+    sqlx::query_as::<Postgres, DbReadEndpoint>(&query)
+        .bind(endpoint_id)
+        .fetch_optional(executor)
+        .await?
+        .map(to_endpoint_info)
+        .transpose()
 }
 
 pub async fn update_endpoint(
     db_pool: &PgPool,
     endpoint_id: i32,
     req: CreateEndpointRequest,
+    author: &str,
 ) -> Result<()> {
-    let db_endpoint = parse_endpoints_vec(req)?;
+    validate_endpoints(db_pool, &req).await?;
+    let definition = serde_json::to_string(&req)?;
 
-    sqlx::query(
-        r#"
-            UPDATE __B_endpoints 
-            SET req_path=$1, req_method=$2, handler_info=$3, allowed_groups=$4
-            where id=$5::int
-        "#,
+    let mut transaction = db_pool.begin().await?;
+    check_path_conflicts(&mut transaction, &req.path, &req.method, Some(endpoint_id)).await?;
+    overwrite_endpoint(&mut transaction, endpoint_id, req).await?;
+    record_version(
+        &mut transaction,
+        endpoint_id,
+        VersionAction::Update,
+        &definition,
+        author,
     )
-    .bind(db_endpoint.path)
-    .bind(db_endpoint.method)
-    .bind(db_endpoint.handler_info_json)
-    .bind(db_endpoint.allowed_groups_json)
-    .bind(endpoint_id)
-    .execute(db_pool)
     .await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+pub async fn delete_endpoint(db_pool: &PgPool, endpoint_id: i32, author: &str) -> Result<()> {
+    let mut transaction = db_pool.begin().await?;
+    remove_endpoint(&mut transaction, endpoint_id, author).await?;
+    transaction.commit().await?;
 
     Ok(())
 }
 
-pub async fn delete_endpoint(db_pool: &PgPool, endpoint_id: i32) -> Result<()> {
+/// Deletes the endpoint and records the deleted definition,
+/// so that the endpoint can be restored
+pub async fn remove_endpoint(
+    transaction: &mut Transaction<'_, Postgres>,
+    endpoint_id: i32,
+    author: &str,
+) -> Result<()> {
+    let deleted = get_endpoint(&mut *transaction, endpoint_id)
+        .await?
+        .ok_or_else(|| endpoint_not_found(endpoint_id))?;
+    let definition = serde_json::to_string(&CreateEndpointRequest::from(deleted))?;
+
     sqlx::query("DELETE FROM __B_endpoints WHERE id=$1::int")
         .bind(endpoint_id)
-        .execute(db_pool)
+        .execute(&mut *transaction)
+        .await?;
+    record_version(
+        transaction,
+        endpoint_id,
+        VersionAction::Delete,
+        &definition,
+        author,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Restores the definition of a previous version,
+/// deleted endpoints are recreated with their old id
+pub async fn rollback_endpoint(
+    db_pool: &PgPool,
+    endpoint_id: i32,
+    version: i32,
+    author: &str,
+) -> Result<()> {
+    let definition = get_version(db_pool, endpoint_id, version).await?.definition;
+    validate_endpoints(db_pool, &definition).await?;
+    let definition_json = serde_json::to_string(&definition)?;
+
+    let mut transaction = db_pool.begin().await?;
+    check_path_conflicts(
+        &mut transaction,
+        &definition.path,
+        &definition.method,
+        Some(endpoint_id),
+    )
+    .await?;
+
+    if get_endpoint(&mut transaction, endpoint_id).await?.is_some() {
+        overwrite_endpoint(&mut transaction, endpoint_id, definition).await?;
+    } else {
+        insert_endpoint(&mut transaction, Some(endpoint_id), definition).await?;
+    }
+
+    record_version(
+        &mut transaction,
+        endpoint_id,
+        VersionAction::Rollback(version),
+        &definition_json,
+        author,
+    )
+    .await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+/// Saves a definition which doesn't affect the live endpoint until it's published
+pub async fn save_draft(
+    db_pool: &PgPool,
+    endpoint_id: i32,
+    req: CreateEndpointRequest,
+) -> Result<()> {
+    validate_endpoints(db_pool, &req).await?;
+    let definition = serde_json::to_string(&req)?;
+
+    let mut transaction = db_pool.begin().await?;
+    check_path_conflicts(&mut transaction, &req.path, &req.method, Some(endpoint_id)).await?;
+
+    let updated = sqlx::query("UPDATE __B_endpoints SET draft=$1 WHERE id=$2::int")
+        .bind(definition)
+        .bind(endpoint_id)
+        .execute(&mut transaction)
+        .await?
+        .rows_affected();
+
+    if updated == 0 {
+        return Err(endpoint_not_found(endpoint_id));
+    }
+
+    transaction.commit().await?;
+    Ok(())
+}
+
+pub async fn get_draft(db_pool: &PgPool, endpoint_id: i32) -> Result<CreateEndpointRequest> {
+    get_endpoint(db_pool, endpoint_id)
+        .await?
+        .ok_or_else(|| endpoint_not_found(endpoint_id))?
+        .draft
+        .ok_or_else(|| no_draft(endpoint_id))
+}
+
+fn no_draft(endpoint_id: i32) -> anyhow::Error {
+    status_error(
+        StatusCode::CONFLICT,
+        format!("Endpoint {} has no draft", endpoint_id),
+    )
+}
+
+async fn select_draft<'e>(
+    executor: impl Executor<'e, Database = Postgres>,
+    endpoint_id: i32,
+    for_update: bool,
+) -> Result<String> {
+    let query = format!(
+        "SELECT draft FROM __B_endpoints WHERE id=$1::int{}",
+        if for_update { " FOR UPDATE" } else { "" }
+    );
+
+    let (draft,) = sqlx::query_as::<Postgres, (Option<String>,)>(&query)
+        .bind(endpoint_id)
+        .fetch_optional(executor)
+        .await?
+        .ok_or_else(|| endpoint_not_found(endpoint_id))?;
+
+    draft.ok_or_else(|| no_draft(endpoint_id))
+}
+
+/// Replaces the live definition with the draft in one transaction.
+/// The draft is checked again, the database could have changed since it was saved.
+pub async fn publish_draft(db_pool: &PgPool, endpoint_id: i32, author: &str) -> Result<()> {
+    // Checked before the row is locked, the check runs queries on other connections
+    let definition = select_draft(db_pool, endpoint_id, false).await?;
+    let req: CreateEndpointRequest = serde_json::from_str(&definition)?;
+    validate_endpoints(db_pool, &req).await?;
+
+    let mut transaction = db_pool.begin().await?;
+
+    // Locked, so that the draft can't change between here and the publication
+    if select_draft(&mut transaction, endpoint_id, true).await? != definition {
+        return Err(status_error(
+            StatusCode::CONFLICT,
+            format!("Draft of endpoint {} changed while publishing", endpoint_id),
+        ));
+    }
+    check_path_conflicts(&mut transaction, &req.path, &req.method, Some(endpoint_id)).await?;
+
+    overwrite_endpoint(&mut transaction, endpoint_id, req).await?;
+    sqlx::query("UPDATE __B_endpoints SET draft=NULL WHERE id=$1::int")
+        .bind(endpoint_id)
+        .execute(&mut transaction)
         .await?;
+    record_version(
+        &mut transaction,
+        endpoint_id,
+        VersionAction::Publish,
+        &definition,
+        author,
+    )
+    .await?;
+    transaction.commit().await?;
+
     Ok(())
 }
+
+pub async fn discard_draft(db_pool: &PgPool, endpoint_id: i32) -> Result<()> {
+    let updated = sqlx::query("UPDATE __B_endpoints SET draft=NULL WHERE id=$1::int")
+        .bind(endpoint_id)
+        .execute(db_pool)
+        .await?
+        .rows_affected();
+
+    if updated == 0 {
+        return Err(endpoint_not_found(endpoint_id));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::err_utils::to_status;
+    use crate::services::endpoints::endpoint_versions::list_versions;
+    use crate::test_database::TestDatabase;
+    use serde_json::json;
+
+    fn endpoint(path: &str) -> CreateEndpointRequest {
+        serde_json::from_value(json!({
+            "path": path,
+            "method": "GET",
+            "endpoints_info": [],
+            "allowed_groups": ["ADMIN"],
+        }))
+        .unwrap()
+    }
+
+    async fn stored(db_pool: &PgPool, endpoint_id: i32) -> GetEndpointInfo {
+        get_endpoint(db_pool, endpoint_id).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn conflicts_are_checked_against_uncommitted_endpoints() {
+        let db = TestDatabase::with_internal_tables().await;
+
+        let mut transaction = db.pool.begin().await.unwrap();
+        let method = CreateEndpointMethod::GET;
+        check_path_conflicts(&mut transaction, "books/:id", &method, None)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO __B_endpoints (req_path, req_method, handler_info, allowed_groups) \
+             VALUES ('books/:id', 'GET', '[]', '[]')",
+        )
+        .execute(&mut transaction)
+        .await
+        .unwrap();
+
+        // waits for the first transaction, then finds its endpoint
+        let pool = db.pool.clone();
+        let concurrent = tokio::spawn(async move {
+            create_endpoint(&pool, endpoint("books/:book_id"), "test").await
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        transaction.commit().await.unwrap();
+
+        let error = concurrent.await.unwrap().unwrap_err();
+        assert_eq!(to_status(error).0, StatusCode::CONFLICT);
+
+        db.drop().await;
+    }
+
+    #[tokio::test]
+    async fn saving_publishing_and_discarding_drafts() {
+        let db = TestDatabase::with_internal_tables().await;
+        let pool = &db.pool;
+        let id = create_endpoint(pool, endpoint("books"), "test")
+            .await
+            .unwrap();
+
+        let error = publish_draft(pool, id, "test").await.unwrap_err();
+        assert_eq!(to_status(error).0, StatusCode::CONFLICT);
+
+        // a draft doesn't change the live endpoint until it's published
+        save_draft(pool, id, endpoint("novels")).await.unwrap();
+        assert_eq!(get_draft(pool, id).await.unwrap().path, "novels");
+        assert_eq!(stored(pool, id).await.path, "books");
+
+        publish_draft(pool, id, "publisher").await.unwrap();
+        assert_eq!(stored(pool, id).await.path, "novels");
+        assert!(stored(pool, id).await.draft.is_none());
+
+        let latest = &list_versions(pool, id).await.unwrap()[0];
+        assert_eq!(latest.action, "publish");
+        assert_eq!(latest.definition.path, "novels");
+        assert_eq!(latest.author.as_deref(), Some("publisher"));
+
+        save_draft(pool, id, endpoint("poems")).await.unwrap();
+        discard_draft(pool, id).await.unwrap();
+        assert_eq!(stored(pool, id).await.path, "novels");
+        let error = get_draft(pool, id).await.err().unwrap();
+        assert_eq!(to_status(error).0, StatusCode::CONFLICT);
+
+        let error = discard_draft(pool, id + 1).await.unwrap_err();
+        assert_eq!(to_status(error).0, StatusCode::NOT_FOUND);
+
+        db.drop().await;
+    }
+
+    #[tokio::test]
+    async fn invalid_drafts_are_not_published() {
+        let db = TestDatabase::with_internal_tables().await;
+        let id = create_endpoint(&db.pool, endpoint("books"), "test")
+            .await
+            .unwrap();
+
+        db.pool
+            .execute("CREATE TABLE novels (title text)")
+            .await
+            .unwrap();
+        let mut draft = endpoint("novels");
+        draft.endpoints_info = serde_json::from_value(json!([
+            {"name": "novels", "sql": "SELECT title FROM novels", "children": []}
+        ]))
+        .unwrap();
+        save_draft(&db.pool, id, draft).await.unwrap();
+
+        // the draft was valid when it was saved
+        db.pool.execute("DROP TABLE novels").await.unwrap();
+
+        assert!(publish_draft(&db.pool, id, "test").await.is_err());
+        let live = stored(&db.pool, id).await;
+        assert_eq!(live.path, "books");
+        assert!(live.draft.is_some());
+
+        db.drop().await;
+    }
+}
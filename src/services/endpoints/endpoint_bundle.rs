@@ -0,0 +1,195 @@
+use crate::algorithms::{
+    endpoint_bundle::{
+        endpoint_key, plan_import, BundleChange, BundledDefinition, ImportPlan, StoredDefinition,
+    },
+    endpoint_validation::ValidationError,
+    route_pattern::RoutePattern,
+};
+use crate::routes::custom_endpoints::endpoint_crud::{CreateEndpointMethod, CreateEndpointRequest};
+use crate::services::endpoints::{
+    crud_endoints::{
+        get_endpoints, insert_endpoint, lock_endpoints, overwrite_endpoint, remove_endpoint,
+    },
+    endpoint_validation::{validate_endpoints_on, EndpointValidationErrors},
+    endpoint_versions::{record_version, VersionAction},
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sqlx::{Executor, PgConnection, PgPool, Postgres, Transaction};
+
+/// Definitions of all endpoints, without ids, so that they can be copied between servers
+#[derive(Serialize, Deserialize)]
+pub struct EndpointBundle {
+    pub endpoints: Vec<CreateEndpointRequest>,
+}
+
+#[derive(Serialize)]
+pub struct ImportReport {
+    /// Nothing was changed when true
+    pub dry_run: bool,
+    #[serde(flatten)]
+    pub plan: ImportPlan,
+}
+
+fn key_of(req: &CreateEndpointRequest) -> String {
+    endpoint_key(req.method.to_string(), &req.path)
+}
+
+pub async fn export_bundle(db_pool: &PgPool) -> Result<EndpointBundle> {
+    let mut endpoints = get_endpoints(db_pool)
+        .await?
+        .into_iter()
+        .map(CreateEndpointRequest::from)
+        .collect::<Vec<_>>();
+
+    // Stable order, so that bundles can be diffed
+    endpoints.sort_by_key(key_of);
+
+    Ok(EndpointBundle { endpoints })
+}
+
+/// Checks every endpoint of the bundle against the database
+/// and that the paths of the bundle don't conflict with each other
+async fn validate_bundle(connection: &mut PgConnection, bundle: &EndpointBundle) -> Result<()> {
+    let mut errors = Vec::new();
+    let mut patterns: Vec<(String, &CreateEndpointMethod, RoutePattern)> = Vec::new();
+
+    for req in &bundle.endpoints {
+        let key = key_of(req);
+        let mut push = |node: String, message: String| {
+            errors.push(ValidationError {
+                node: format!("{}: {}", key, node),
+                message,
+            })
+        };
+
+        match RoutePattern::parse(&req.path) {
+            Ok(pattern) => {
+                for (other_key, other_method, other_pattern) in &patterns {
+                    if req.method.overlaps(other_method) && pattern.conflicts_with(other_pattern) {
+                        push("path".into(), format!("Conflicts with {}", other_key));
+                    }
+                }
+                patterns.push((key.clone(), &req.method, pattern));
+            }
+            Err(e) => push("path".into(), e.to_string()),
+        }
+
+        if let Err(e) = validate_endpoints_on(connection, req).await {
+            match e.downcast::<EndpointValidationErrors>() {
+                Ok(validation_errors) => {
+                    for error in validation_errors.errors {
+                        push(error.node, error.message);
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(EndpointValidationErrors { errors }.into())
+    }
+}
+
+/// Changes making the stored endpoints equal to the bundle, without validating it
+pub async fn plan_bundle<'e>(
+    executor: impl Executor<'e, Database = Postgres>,
+    bundle: &EndpointBundle,
+) -> Result<ImportPlan> {
+    let stored = get_endpoints(executor)
+        .await?
+        .into_iter()
+        .map(|endpoint| {
+            let id = endpoint.id;
+            let req = CreateEndpointRequest::from(endpoint);
+            Ok(StoredDefinition {
+                id,
+                key: key_of(&req),
+                definition: serde_json::to_value(&req)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    plan_against(&stored, bundle)
+}
+
+/// Changes creating every endpoint of the bundle, for databases without the endpoints table
+pub fn plan_new_bundle(bundle: &EndpointBundle) -> Result<ImportPlan> {
+    plan_against(&[], bundle)
+}
+
+fn plan_against(stored: &[StoredDefinition], bundle: &EndpointBundle) -> Result<ImportPlan> {
+    let bundled = bundle
+        .endpoints
+        .iter()
+        .map(|req| {
+            Ok(BundledDefinition {
+                key: key_of(req),
+                definition: serde_json::to_value(req)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    plan_import(stored, &bundled)
+}
+
+/// Makes the endpoints equal to the bundle in one transaction,
+/// with `dry_run` only reports the changes
+pub async fn import_bundle(
+    db_pool: &PgPool,
+    bundle: EndpointBundle,
+    dry_run: bool,
+    author: &str,
+) -> Result<ImportReport> {
+    let mut transaction = db_pool.begin().await?;
+
+    let plan = if dry_run {
+        validate_bundle(&mut transaction, &bundle).await?;
+        plan_bundle(&mut transaction, &bundle).await?
+    } else {
+        apply_bundle(&mut transaction, bundle, author).await?
+    };
+
+    transaction.commit().await?;
+    Ok(ImportReport { dry_run, plan })
+}
+
+/// Validates the bundle and makes the endpoints equal to it in the transaction,
+/// which may have created the tables the endpoints use
+pub async fn apply_bundle(
+    transaction: &mut Transaction<'_, Postgres>,
+    bundle: EndpointBundle,
+    author: &str,
+) -> Result<ImportPlan> {
+    validate_bundle(transaction, &bundle).await?;
+    lock_endpoints(transaction).await?;
+    let plan = plan_bundle(&mut *transaction, &bundle).await?;
+
+    let mut definitions = bundle.endpoints.into_iter().map(Some).collect::<Vec<_>>();
+
+    for change in &plan.changes {
+        match change {
+            BundleChange::Create { index, .. } => {
+                let req = definitions[*index].take().unwrap();
+                let definition = serde_json::to_string(&req)?;
+                let id = insert_endpoint(transaction, None, req).await?;
+                record_version(transaction, id, VersionAction::Create, &definition, author).await?;
+            }
+            BundleChange::Update { id, index, .. } => {
+                let req = definitions[*index].take().unwrap();
+                let definition = serde_json::to_string(&req)?;
+                overwrite_endpoint(transaction, *id, req).await?;
+                record_version(transaction, *id, VersionAction::Update, &definition, author)
+                    .await?;
+            }
+            BundleChange::Delete { id, .. } => {
+                remove_endpoint(transaction, *id, author).await?;
+            }
+        }
+    }
+
+    Ok(plan)
+}
@@ -1,27 +1,94 @@
-use crate::{
-    algorithms::{
-        endpoint_execution::{EndpointExecutionRuntime, ExecutionResult},
-        sql_variable_parser::EndpointInfo,
-    },
-    routes::custom_endpoints::EndpointExecutionInfo,
+use crate::algorithms::{
+    endpoint_execution::{EndpointExecutionRuntime, NodeResult},
+    execution_limits::ExecutionLimits,
+    sql_variable_parser::EndpointInfo,
 };
+use crate::auth::Claims;
+use crate::err_utils::status_error;
+use crate::services::row_level_security::begin_as;
 use anyhow::Result;
-use sqlx::PgPool;
+use axum::http::StatusCode;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
 use std::collections::HashMap;
+use std::str::FromStr;
+
+/// SQLSTATE of statements canceled by `statement_timeout`
+const QUERY_CANCELED: &str = "57014";
+
+/// Limits of endpoints which don't set their own, from the `STATEMENT_TIMEOUT_MS`,
+/// `MAX_ROWS` and `MAX_RESPONSE_BYTES` variables. Statements time out after 30 seconds
+/// when not set, so that an endpoint can't hold a connection of the pool indefinitely.
+pub static DEFAULT_LIMITS: Lazy<ExecutionLimits> = Lazy::new(|| ExecutionLimits {
+    statement_timeout_ms: Some(env_number("STATEMENT_TIMEOUT_MS").unwrap_or(30_000)),
+    max_rows: env_number("MAX_ROWS"),
+    max_response_bytes: env_number("MAX_RESPONSE_BYTES"),
+});
+
+fn env_number<T: FromStr>(name: &str) -> Option<T> {
+    let value = std::env::var(name).ok()?;
+    let number = value.parse().ok();
+
+    if number.is_none() {
+        tracing::warn!("Ignoring {}, {} isn't a positive number", name, value);
+    }
+
+    number
+}
+
+/// Begins the transaction of a call under the statement timeout of the limits
+pub async fn begin_limited(
+    db_pool: &PgPool,
+    user: Option<&Claims>,
+    limits: &ExecutionLimits,
+) -> Result<Transaction<'static, Postgres>> {
+    let mut transaction = begin_as(db_pool, user).await?;
+
+    if let Some(timeout) = limits.statement_timeout_ms {
+        // SET doesn't take parameters, the value is a number
+        let set_timeout = format!("SET LOCAL statement_timeout = {}", timeout);
+        transaction.execute(set_timeout.as_str()).await?;
+    }
+
+    Ok(transaction)
+}
+
+/// Statements canceled by the timeout are answered with 504
+pub fn timeout_error(e: anyhow::Error, limits: &ExecutionLimits) -> anyhow::Error {
+    let canceled = e
+        .downcast_ref::<sqlx::Error>()
+        .and_then(sqlx::Error::as_database_error)
+        .and_then(|it| it.code())
+        .is_some_and(|code| code == QUERY_CANCELED);
+
+    match limits.statement_timeout_ms {
+        Some(timeout) if canceled => status_error(
+            StatusCode::GATEWAY_TIMEOUT,
+            format!("Endpoint didn't finish within {} ms", timeout),
+        ),
+        _ => e,
+    }
+}
 
 pub async fn execute_endpoint(
     db_pool: &PgPool,
-    execution_info: EndpointExecutionInfo,
-    request_variables: HashMap<String, String>,
-) -> Result<HashMap<String, Vec<ExecutionResult>>> {
-    let mut runtime = EndpointExecutionRuntime::new(request_variables);
-    let endpoint_info_vec =
-        serde_json::from_str::<Vec<EndpointInfo>>(&execution_info.handler_info)?;
-    let mut transaction = db_pool.begin().await?;
+    endpoint_infos: &[EndpointInfo],
+    request_variables: Value,
+    path_params: HashMap<String, String>,
+    user: Option<&Claims>,
+    limits: &ExecutionLimits,
+) -> Result<HashMap<String, NodeResult>> {
+    let mut runtime = EndpointExecutionRuntime::new(request_variables)
+        .with_path_params(path_params)
+        .with_user(user.map(Claims::endpoint_variables))
+        .with_limits(limits);
+    let mut transaction = begin_limited(db_pool, user, limits).await?;
 
     let result = runtime
-        .execute(&mut transaction, &endpoint_info_vec)
-        .await?;
+        .execute(&mut transaction, endpoint_infos)
+        .await
+        .map_err(|e| timeout_error(e, limits))?;
 
     transaction.commit().await?;
     Ok(result)
@@ -0,0 +1,327 @@
+use super::response_cache::ResponseCache;
+use crate::{
+    algorithms::{
+        endpoint_response::EndpointResponse, execution_limits::ExecutionLimits,
+        input_schema::InputSchema, rate_limit::RateLimit, route_pattern::RoutePattern,
+        sql_variable_parser::EndpointInfo,
+    },
+    routes::custom_endpoints::endpoint_crud::CreateEndpointMethod,
+};
+use anyhow::Result;
+use axum::http::Method;
+use sqlx::{postgres::PgListener, FromRow, PgPool, Postgres};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Channel notified whenever `__B_endpoints` changes,
+/// so that every server instance reloads its registry
+const CHANGES_CHANNEL: &str = "__b_endpoints_changed";
+
+/// Endpoint definition parsed once, when the registry is loaded
+#[derive(Debug)]
+pub struct RegisteredEndpoint {
+    pub id: i32,
+    pub pattern: RoutePattern,
+    pub method: CreateEndpointMethod,
+    pub endpoint_infos: Vec<EndpointInfo>,
+    pub allowed_groups: Vec<String>,
+    pub response: EndpointResponse,
+    pub input_schema: InputSchema,
+    pub rate_limit: Option<RateLimit>,
+    pub cache_ttl: Option<u32>,
+    pub cache: ResponseCache,
+    /// Limits set by the endpoint, without the server defaults
+    pub limits: ExecutionLimits,
+    /// Row the endpoint was parsed from, to tell whether it changed
+    source: DbEndpoint,
+}
+
+pub enum EndpointMatch {
+    Found(Arc<RegisteredEndpoint>, HashMap<String, String>),
+    /// The path matches, but none of its endpoints accepts the method
+    MethodNotAllowed(Vec<CreateEndpointMethod>),
+    NotFound,
+}
+
+/// In-memory copy of `__B_endpoints`, shared by all requests
+#[derive(Clone, Default)]
+pub struct EndpointRegistry {
+    endpoints: Arc<RwLock<Vec<Arc<RegisteredEndpoint>>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, FromRow)]
+struct DbEndpoint {
+    id: i32,
+    req_path: String,
+    req_method: String,
+    handler_info: String,
+    allowed_groups: String,
+    response: String,
+    input_schema: String,
+    rate_limit: String,
+    cache_ttl: String,
+    limits: String,
+}
+
+fn parse_endpoint(db_endpoint: DbEndpoint) -> Result<RegisteredEndpoint> {
+    let mut input_schema: InputSchema = serde_json::from_str(&db_endpoint.input_schema)?;
+    input_schema.compile()?;
+
+    Ok(RegisteredEndpoint {
+        id: db_endpoint.id,
+        pattern: RoutePattern::parse(&db_endpoint.req_path)?,
+        method: CreateEndpointMethod::from_str(&db_endpoint.req_method)?,
+        endpoint_infos: serde_json::from_str(&db_endpoint.handler_info)?,
+        allowed_groups: serde_json::from_str(&db_endpoint.allowed_groups)?,
+        response: serde_json::from_str(&db_endpoint.response)?,
+        input_schema,
+        rate_limit: serde_json::from_str(&db_endpoint.rate_limit)?,
+        cache_ttl: serde_json::from_str(&db_endpoint.cache_ttl)?,
+        cache: ResponseCache::default(),
+        limits: serde_json::from_str(&db_endpoint.limits)?,
+        source: db_endpoint,
+    })
+}
+
+impl EndpointRegistry {
+    pub async fn load(db_pool: &PgPool) -> Result<Self> {
+        let registry = Self::default();
+        registry.reload(db_pool).await?;
+        Ok(registry)
+    }
+
+    pub async fn reload(&self, db_pool: &PgPool) -> Result<()> {
+        let db_endpoints = sqlx::query_as::<Postgres, DbEndpoint>(
+            r#"
+                SELECT id::int, req_path, req_method, handler_info, allowed_groups, response,
+                    input_schema, rate_limit, cache_ttl, limits
+                FROM __B_endpoints
+            "#,
+        )
+        .fetch_all(db_pool)
+        .await?;
+
+        self.replace_endpoints(db_endpoints);
+        Ok(())
+    }
+
+    fn replace_endpoints(&self, db_endpoints: Vec<DbEndpoint>) {
+        // Unchanged endpoints are kept, with their cached responses
+        let previous = self.endpoints();
+        let mut endpoints = Vec::with_capacity(db_endpoints.len());
+
+        for db_endpoint in db_endpoints {
+            if let Some(unchanged) = previous.iter().find(|it| it.source == db_endpoint) {
+                endpoints.push(unchanged.clone());
+                continue;
+            }
+
+            let (id, path) = (db_endpoint.id, db_endpoint.req_path.clone());
+
+            // A single broken definition shouldn't take down the other endpoints
+            match parse_endpoint(db_endpoint) {
+                Ok(endpoint) => endpoints.push(Arc::new(endpoint)),
+                Err(e) => tracing::warn!("Skipping endpoint {} ({}): {}", id, path, e),
+            }
+        }
+
+        *self.endpoints.write().unwrap() = endpoints;
+    }
+
+    /// Reloads this registry and tells other server instances to do the same
+    pub async fn endpoints_changed(&self, db_pool: &PgPool) -> Result<()> {
+        self.reload(db_pool).await?;
+
+        sqlx::query("SELECT pg_notify($1, '')")
+            .bind(CHANGES_CHANNEL)
+            .execute(db_pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Keeps the registry in sync with changes made by other server instances
+    pub fn listen_for_changes(&self, db_pool: PgPool) {
+        let registry = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = registry.listen(&db_pool).await {
+                    tracing::error!("Listening for endpoint changes failed: {}", e);
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            }
+        });
+    }
+
+    async fn listen(&self, db_pool: &PgPool) -> Result<()> {
+        let mut listener = PgListener::connect_with(db_pool).await?;
+        listener.listen(CHANGES_CHANNEL).await?;
+
+        // Changes could have been missed while not listening
+        self.reload(db_pool).await?;
+
+        loop {
+            listener.recv().await?;
+            self.reload(db_pool).await?;
+        }
+    }
+
+    pub fn endpoints(&self) -> Vec<Arc<RegisteredEndpoint>> {
+        self.endpoints.read().unwrap().clone()
+    }
+
+    /// Finds the endpoint whose path pattern matches `path` and which accepts `method`.
+    ///
+    /// Static segments take precedence over parameters, so `users/me`
+    /// is chosen over `users/:id` for the path `users/me`.
+    pub fn find(&self, path: &str, method: &Method) -> EndpointMatch {
+        let endpoints = self.endpoints.read().unwrap();
+
+        let mut matching = endpoints
+            .iter()
+            .filter_map(|endpoint| {
+                let path_params = endpoint.pattern.matches(path)?;
+                Some((endpoint, path_params))
+            })
+            .collect::<Vec<_>>();
+
+        matching.sort_by(|(a, _), (b, _)| a.pattern.precedence(&b.pattern));
+
+        // Endpoints with the most specific pattern, they differ only by method
+        let best_pattern = match matching.first() {
+            Some((endpoint, _)) => endpoint.pattern.clone(),
+            None => return EndpointMatch::NotFound,
+        };
+        matching
+            .retain(|(endpoint, _)| endpoint.pattern.precedence(&best_pattern) == Ordering::Equal);
+
+        let mut allowed_methods = Vec::new();
+
+        for (endpoint, path_params) in matching {
+            if endpoint.method.allows(method) {
+                return EndpointMatch::Found(endpoint.clone(), path_params);
+            }
+            allowed_methods.push(endpoint.method.clone());
+        }
+
+        EndpointMatch::MethodNotAllowed(allowed_methods)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db_endpoint(id: i32, path: &str, method: &str) -> DbEndpoint {
+        DbEndpoint {
+            id,
+            req_path: path.into(),
+            req_method: method.into(),
+            handler_info: "[]".into(),
+            allowed_groups: r#"["ADMIN"]"#.into(),
+            response: serde_json::to_string(&EndpointResponse::default()).unwrap(),
+            input_schema: serde_json::to_string(&InputSchema::default()).unwrap(),
+            rate_limit: "null".into(),
+            cache_ttl: "null".into(),
+            limits: "{}".into(),
+        }
+    }
+
+    fn registry(db_endpoints: Vec<DbEndpoint>) -> EndpointRegistry {
+        let registry = EndpointRegistry::default();
+        registry.replace_endpoints(db_endpoints);
+        registry
+    }
+
+    fn found_id(registry: &EndpointRegistry, path: &str, method: Method) -> Option<i32> {
+        match registry.find(path, &method) {
+            EndpointMatch::Found(endpoint, _) => Some(endpoint.id),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn static_segments_take_precedence() {
+        let registry = registry(vec![
+            db_endpoint(1, "users/:id", "GET"),
+            db_endpoint(2, "users/me", "GET"),
+            db_endpoint(3, "users/:id/posts", "ANY"),
+        ]);
+
+        assert_eq!(found_id(&registry, "users/me", Method::GET), Some(2));
+        assert_eq!(found_id(&registry, "users/5/posts", Method::POST), Some(3));
+
+        match registry.find("users/5", &Method::GET) {
+            EndpointMatch::Found(endpoint, path_params) => {
+                assert_eq!(endpoint.id, 1);
+                assert_eq!(path_params["id"], "5");
+            }
+            _ => panic!("users/:id should match"),
+        }
+
+        assert!(matches!(
+            registry.find("posts", &Method::GET),
+            EndpointMatch::NotFound
+        ));
+    }
+
+    #[test]
+    fn other_methods_of_the_best_pattern_are_not_allowed() {
+        let registry = registry(vec![
+            db_endpoint(1, "users/:id", "GET"),
+            db_endpoint(2, "users/me", "POST"),
+            db_endpoint(3, "users/me", "DELETE"),
+        ]);
+
+        // users/:id isn't used as a fallback for users/me
+        match registry.find("users/me", &Method::GET) {
+            EndpointMatch::MethodNotAllowed(methods) => assert_eq!(
+                methods.iter().map(|it| it.to_string()).collect::<Vec<_>>(),
+                vec!["POST", "DELETE"]
+            ),
+            _ => panic!("GET users/me shouldn't be allowed"),
+        }
+        assert_eq!(found_id(&registry, "users/me", Method::DELETE), Some(3));
+    }
+
+    #[test]
+    fn reloading_keeps_unchanged_endpoints() {
+        let registry = registry(vec![
+            db_endpoint(1, "users", "GET"),
+            db_endpoint(2, "posts", "GET"),
+        ]);
+        let before = registry.endpoints();
+
+        let mut changed = db_endpoint(2, "posts", "GET");
+        changed.cache_ttl = "60".into();
+        registry.replace_endpoints(vec![db_endpoint(1, "users", "GET"), changed]);
+        let after = registry.endpoints();
+
+        assert!(Arc::ptr_eq(&before[0], &after[0]));
+        assert!(!Arc::ptr_eq(&before[1], &after[1]));
+        assert_eq!(after[1].cache_ttl, Some(60));
+    }
+
+    #[test]
+    fn broken_definitions_are_skipped() {
+        let mut broken_sql = db_endpoint(2, "posts", "GET");
+        broken_sql.handler_info = "not json".into();
+
+        let registry = registry(vec![
+            db_endpoint(1, "users", "GET"),
+            broken_sql,
+            db_endpoint(3, "users/:id/:id", "GET"),
+            db_endpoint(4, "comments", "OPTIONS"),
+        ]);
+
+        let ids = registry
+            .endpoints()
+            .iter()
+            .map(|it| it.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![1]);
+    }
+}
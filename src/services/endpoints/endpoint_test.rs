@@ -1,21 +1,32 @@
 use crate::algorithms::{
-    endpoint_execution::{EndpointExecutionRuntime, ExecutionResult},
+    endpoint_execution::{EndpointExecutionRuntime, NodeResult},
+    execution_limits::ExecutionLimits,
     sql_variable_parser::EndpointInfo,
 };
+use crate::auth::Claims;
+use crate::services::endpoints::endpoint_execution::{begin_limited, timeout_error};
 use anyhow::Result;
+use serde_json::Value;
 use sqlx::PgPool;
 use std::collections::HashMap;
 
 pub async fn test_endpoint(
     db_pool: &PgPool,
     execution_info: Vec<EndpointInfo>,
-    request_variables: HashMap<String, String>,
-) -> Result<HashMap<String, Vec<ExecutionResult>>> {
-    let mut runtime = EndpointExecutionRuntime::new(request_variables);
+    request_variables: Value,
+    user: &Claims,
+    limits: &ExecutionLimits,
+) -> Result<HashMap<String, NodeResult>> {
+    let mut runtime = EndpointExecutionRuntime::new(request_variables)
+        .with_user(Some(user.endpoint_variables()))
+        .with_limits(limits);
 
-    let mut transaction = db_pool.begin().await?;
+    let mut transaction = begin_limited(db_pool, Some(user), limits).await?;
 
-    let result = runtime.execute(&mut transaction, &execution_info).await?;
+    let result = runtime
+        .execute(&mut transaction, &execution_info)
+        .await
+        .map_err(|e| timeout_error(e, limits))?;
 
     transaction.rollback().await?;
     Ok(result)
@@ -0,0 +1,175 @@
+use crate::algorithms::{
+    endpoint_validation::{check_tree, node_path, ValidationError, ValidationNode},
+    sql_variable_parser::{EndpointInfoCreateRequest, SqlWithVariables},
+};
+use crate::routes::custom_endpoints::endpoint_crud::{CreateEndpointMethod, CreateEndpointRequest};
+use anyhow::Result;
+use async_recursion::async_recursion;
+use serde::Serialize;
+use sqlx::{Column, Connection, Executor, PgConnection, PgPool};
+use std::fmt;
+
+/// Returned by create and update when the endpoint definition is invalid
+#[derive(Debug, Serialize)]
+pub struct EndpointValidationErrors {
+    pub errors: Vec<ValidationError>,
+}
+
+impl fmt::Display for EndpointValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for error in &self.errors {
+            writeln!(f, "{}: {}", error.node, error.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for EndpointValidationErrors {}
+
+/// Prepares every query of the tree (without executing it)
+/// and checks the variables against the returned columns,
+/// then checks the response settings and the input schema
+pub async fn validate_endpoints(db_pool: &PgPool, req: &CreateEndpointRequest) -> Result<()> {
+    let mut connection = db_pool.acquire().await?;
+    validate_endpoints_on(&mut connection, req).await
+}
+
+/// `validate_endpoints` on the connection, which may be in a transaction
+/// which created the tables the endpoint uses
+pub async fn validate_endpoints_on(
+    connection: &mut PgConnection,
+    req: &CreateEndpointRequest,
+) -> Result<()> {
+    let endpoints_info = &req.endpoints_info;
+    let mut errors = Vec::new();
+
+    let nodes = describe_nodes(connection, endpoints_info, "", &mut errors).await;
+    // Statements prepared here have parameter types inferred by postgres,
+    // they mustn't be reused for execution with our own parameter types
+    connection.clear_cached_statements().await?;
+
+    errors.extend(check_tree(&nodes));
+
+    let query_names = endpoints_info
+        .iter()
+        .map(|it| it.name.as_str())
+        .collect::<Vec<_>>();
+    errors.extend(
+        req.response
+            .check(&query_names)
+            .into_iter()
+            .map(|message| ValidationError {
+                node: "response".into(),
+                message,
+            }),
+    );
+    errors.extend(
+        req.input_schema
+            .check()
+            .into_iter()
+            .map(|error| ValidationError {
+                node: format!("input_schema.{}", error.field),
+                message: error.message,
+            }),
+    );
+    if let Some(rate_limit) = &req.rate_limit {
+        errors.extend(
+            rate_limit
+                .check()
+                .into_iter()
+                .map(|message| ValidationError {
+                    node: "rate_limit".into(),
+                    message,
+                }),
+        );
+    }
+    if let Some(cache_ttl) = req.cache_ttl {
+        let mut push = |message: &str| {
+            errors.push(ValidationError {
+                node: "cache_ttl".into(),
+                message: message.into(),
+            })
+        };
+
+        if cache_ttl == 0 {
+            push("cache_ttl must be at least 1");
+        }
+        // Other methods change data, their responses mustn't be reused
+        if req.method != CreateEndpointMethod::GET {
+            push("Only GET endpoints can be cached");
+        }
+    }
+    errors.extend(
+        req.limits
+            .check()
+            .into_iter()
+            .map(|message| ValidationError {
+                node: "limits".into(),
+                message,
+            }),
+    );
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(EndpointValidationErrors { errors }.into())
+    }
+}
+
+/// Columns of the statement, prepared in a savepoint when the connection is in
+/// a transaction, so that an invalid statement doesn't abort the transaction
+async fn describe_columns(connection: &mut PgConnection, sql: &str) -> Result<Vec<String>> {
+    let mut savepoint = Connection::begin(connection).await?;
+    let describe = (&mut savepoint).describe(sql).await;
+    savepoint.rollback().await?;
+
+    Ok(describe?
+        .columns()
+        .iter()
+        .map(|it| it.name().to_owned())
+        .collect())
+}
+
+#[async_recursion]
+async fn describe_nodes(
+    connection: &mut PgConnection,
+    endpoints_info: &[EndpointInfoCreateRequest],
+    parent_path: &str,
+    errors: &mut Vec<ValidationError>,
+) -> Vec<ValidationNode> {
+    let mut nodes = Vec::with_capacity(endpoints_info.len());
+
+    for info in endpoints_info {
+        let path = node_path(parent_path, &info.name);
+        let mut node = ValidationNode {
+            name: info.name.clone(),
+            kind: info.kind.clone(),
+            pagination: info.pagination.clone(),
+            cardinality: info.cardinality,
+            flatten: info.flatten,
+            ..Default::default()
+        };
+
+        match SqlWithVariables::from_sql(&info.sql) {
+            Ok(parsed) => {
+                match describe_columns(connection, &parsed.sql).await {
+                    Ok(columns) => node.columns = Some(columns),
+                    Err(e) => errors.push(ValidationError {
+                        node: path.clone(),
+                        message: e.to_string(),
+                    }),
+                }
+                node.variables = parsed.variables;
+            }
+            Err(e) => errors.push(ValidationError {
+                node: path.clone(),
+                message: e.to_string(),
+            }),
+        }
+
+        node.children = describe_nodes(connection, &info.children, &path, errors).await;
+        nodes.push(node);
+    }
+
+    nodes
+}
@@ -0,0 +1,196 @@
+use crate::algorithms::json_diff::{json_diff, JsonChange};
+use crate::err_utils::status_error;
+use crate::routes::custom_endpoints::endpoint_crud::CreateEndpointRequest;
+use crate::services::endpoints::crud_endoints::get_endpoint;
+use anyhow::Result;
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, PgPool, Postgres, Transaction};
+
+pub enum VersionAction {
+    Create,
+    Update,
+    Delete,
+    /// The draft became the live definition
+    Publish,
+    /// Restored the definition of the given version
+    Rollback(i32),
+}
+
+impl VersionAction {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Create => "create",
+            Self::Update => "update",
+            Self::Delete => "delete",
+            Self::Publish => "publish",
+            Self::Rollback(_) => "rollback",
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct EndpointVersion {
+    pub version: i32,
+    pub action: String,
+    pub definition: CreateEndpointRequest,
+    /// `None` for definitions recorded when the history was introduced
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restored_version: Option<i32>,
+    pub created_at: String,
+}
+
+#[derive(FromRow)]
+struct DbVersion {
+    version: i32,
+    action: String,
+    definition: String,
+    author: Option<String>,
+    restored_version: Option<i32>,
+    created_at: DateTime<Utc>,
+}
+
+fn to_version(db_version: DbVersion) -> Result<EndpointVersion> {
+    Ok(EndpointVersion {
+        version: db_version.version,
+        action: db_version.action,
+        definition: serde_json::from_str(&db_version.definition)?,
+        author: db_version.author,
+        restored_version: db_version.restored_version,
+        created_at: db_version.created_at.to_rfc3339(),
+    })
+}
+
+/// Adds the next version of the endpoint, in the transaction of the change
+pub async fn record_version(
+    transaction: &mut Transaction<'_, Postgres>,
+    endpoint_id: i32,
+    action: VersionAction,
+    definition: &str,
+    author: &str,
+) -> Result<()> {
+    let restored_version = match action {
+        VersionAction::Rollback(version) => Some(version),
+        _ => None,
+    };
+
+    sqlx::query(
+        r#"
+            INSERT INTO __B_endpoint_versions
+            (endpoint_id, version, action, definition, author, restored_version)
+            SELECT $1, COALESCE(MAX(version), 0) + 1, $2, $3, $4, $5
+            FROM __B_endpoint_versions
+            WHERE endpoint_id = $1
+        "#,
+    )
+    .bind(endpoint_id)
+    .bind(action.name())
+    .bind(definition)
+    .bind(author)
+    .bind(restored_version)
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+/// Records the current definition of endpoints created before the history existed
+pub async fn record_missing_versions(db_pool: &PgPool) -> Result<()> {
+    let without_history = sqlx::query_as::<Postgres, (i32,)>(
+        r#"
+            SELECT id::int FROM __B_endpoints e
+            WHERE NOT EXISTS (SELECT 1 FROM __B_endpoint_versions v WHERE v.endpoint_id = e.id)
+        "#,
+    )
+    .fetch_all(db_pool)
+    .await?;
+
+    for (endpoint_id,) in without_history {
+        // A definition which can't be parsed anymore gets its history with the next change
+        let endpoint = match get_endpoint(db_pool, endpoint_id).await {
+            Ok(Some(endpoint)) => endpoint,
+            Ok(None) => continue,
+            Err(e) => {
+                tracing::warn!("Couldn't record version of endpoint {}: {}", endpoint_id, e);
+                continue;
+            }
+        };
+        let definition = serde_json::to_string(&CreateEndpointRequest::from(endpoint))?;
+
+        sqlx::query(
+            r#"
+                INSERT INTO __B_endpoint_versions (endpoint_id, version, action, definition)
+                VALUES ($1, 1, 'create', $2)
+                ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(endpoint_id)
+        .bind(definition)
+        .execute(db_pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Versions of the endpoint, the newest first
+pub async fn list_versions(db_pool: &PgPool, endpoint_id: i32) -> Result<Vec<EndpointVersion>> {
+    sqlx::query_as::<Postgres, DbVersion>(
+        r#"
+            SELECT version, action, definition, author, restored_version, created_at
+            FROM __B_endpoint_versions
+            WHERE endpoint_id = $1
+            ORDER BY version DESC
+        "#,
+    )
+    .bind(endpoint_id)
+    .fetch_all(db_pool)
+    .await?
+    .into_iter()
+    .map(to_version)
+    .collect()
+}
+
+pub async fn get_version(
+    db_pool: &PgPool,
+    endpoint_id: i32,
+    version: i32,
+) -> Result<EndpointVersion> {
+    sqlx::query_as::<Postgres, DbVersion>(
+        r#"
+            SELECT version, action, definition, author, restored_version, created_at
+            FROM __B_endpoint_versions
+            WHERE endpoint_id = $1 AND version = $2
+        "#,
+    )
+    .bind(endpoint_id)
+    .bind(version)
+    .fetch_optional(db_pool)
+    .await?
+    .map(to_version)
+    .transpose()?
+    .ok_or_else(|| {
+        status_error(
+            StatusCode::NOT_FOUND,
+            format!("Version {} of endpoint {} not found", version, endpoint_id),
+        )
+    })
+}
+
+/// Changes of the definition between two versions
+pub async fn diff_versions(
+    db_pool: &PgPool,
+    endpoint_id: i32,
+    from: i32,
+    to: i32,
+) -> Result<Vec<JsonChange>> {
+    let from = get_version(db_pool, endpoint_id, from).await?;
+    let to = get_version(db_pool, endpoint_id, to).await?;
+
+    Ok(json_diff(
+        &serde_json::to_value(&from.definition)?,
+        &serde_json::to_value(&to.definition)?,
+    ))
+}
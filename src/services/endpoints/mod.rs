@@ -1,3 +1,10 @@
 pub mod crud_endoints;
+pub mod endpoint_bundle;
 pub mod endpoint_execution;
+pub mod endpoint_registry;
 pub mod endpoint_test;
+pub mod endpoint_validation;
+pub mod endpoint_versions;
+pub mod openapi;
+pub mod rate_limiter;
+pub mod response_cache;
@@ -0,0 +1,90 @@
+use crate::algorithms::{
+    openapi::{openapi_document, DescribedColumn, DescribedQuery, DocumentedEndpoint},
+    sql_variable_parser::EndpointInfo,
+};
+use crate::routes::custom_endpoints::endpoint_crud::CreateEndpointMethod;
+use crate::services::endpoints::{
+    endpoint_execution::DEFAULT_LIMITS, endpoint_registry::RegisteredEndpoint,
+};
+use anyhow::Result;
+use async_recursion::async_recursion;
+use serde_json::Value;
+use sqlx::{Column, Connection, Executor, PgConnection, PgPool, TypeInfo};
+use std::sync::Arc;
+
+/// Prepares the queries of the endpoints to find the types of their columns
+/// and builds the OpenAPI document of the endpoints
+pub async fn build_openapi_document(
+    db_pool: &PgPool,
+    endpoints: &[Arc<RegisteredEndpoint>],
+) -> Result<Value> {
+    let mut connection = db_pool.acquire().await?;
+    let mut documented = Vec::with_capacity(endpoints.len());
+
+    for endpoint in endpoints {
+        documented.push(DocumentedEndpoint {
+            pattern: &endpoint.pattern,
+            methods: openapi_methods(&endpoint.method),
+            allowed_groups: &endpoint.allowed_groups,
+            response: &endpoint.response,
+            input_schema: &endpoint.input_schema,
+            rate_limit: endpoint.rate_limit.as_ref(),
+            cache_ttl: endpoint.cache_ttl,
+            limits: endpoint.limits.or(&DEFAULT_LIMITS),
+            queries: describe_queries(&mut connection, &endpoint.endpoint_infos).await,
+        });
+    }
+
+    // Same as in validation, the statements have parameter types inferred by postgres
+    connection.clear_cached_statements().await?;
+
+    Ok(openapi_document(&documented))
+}
+
+fn openapi_methods(method: &CreateEndpointMethod) -> Vec<String> {
+    match method {
+        CreateEndpointMethod::ANY => ["get", "post", "put", "patch", "delete"]
+            .iter()
+            .map(|it| it.to_string())
+            .collect(),
+        method => vec![method.to_string().to_lowercase()],
+    }
+}
+
+#[async_recursion]
+async fn describe_queries<'a>(
+    connection: &mut PgConnection,
+    infos: &'a [EndpointInfo],
+) -> Vec<DescribedQuery<'a>> {
+    let mut queries = Vec::with_capacity(infos.len());
+
+    for info in infos {
+        // A query broken by a later schema change is documented without columns
+        let columns = match (&mut *connection).describe(&info.parsed_sql).await {
+            Ok(describe) => Some(
+                describe
+                    .columns()
+                    .iter()
+                    .enumerate()
+                    .map(|(i, column)| DescribedColumn {
+                        name: column.name().to_owned(),
+                        type_name: column.type_info().name().to_owned(),
+                        nullable: describe.nullable(i),
+                    })
+                    .collect(),
+            ),
+            Err(e) => {
+                tracing::warn!("Couldn't describe query {}: {}", info.name, e);
+                None
+            }
+        };
+
+        queries.push(DescribedQuery {
+            info,
+            columns,
+            children: describe_queries(connection, &info.children).await,
+        });
+    }
+
+    queries
+}
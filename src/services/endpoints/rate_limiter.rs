@@ -0,0 +1,181 @@
+use crate::algorithms::rate_limit::{RateLimit, TokenBucket};
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use sqlx::{PgPool, Postgres};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often buckets which are full again are forgotten
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Reference point of the in-memory buckets
+static STARTED: Lazy<Instant> = Lazy::new(Instant::now);
+
+/// Token buckets of the callers of rate limited endpoints.
+///
+/// The in-memory one is used by default, the Postgres one (`RATE_LIMIT_STORE=postgres`)
+/// keeps the buckets in `__B_rate_limits`, so that instances behind a load balancer
+/// share the limits.
+#[derive(Clone)]
+pub enum RateLimiter {
+    Memory(Arc<Mutex<HashMap<String, (TokenBucket, f64)>>>),
+    Postgres(PgPool),
+}
+
+impl RateLimiter {
+    pub fn from_env(db_pool: &PgPool) -> Result<Self> {
+        match std::env::var("RATE_LIMIT_STORE").as_deref() {
+            Err(_) | Ok("memory") => Ok(Self::Memory(Default::default())),
+            Ok("postgres") => Ok(Self::Postgres(db_pool.clone())),
+            Ok(other) => Err(anyhow!(
+                "RATE_LIMIT_STORE must be memory or postgres, not {}",
+                other
+            )),
+        }
+    }
+
+    /// Takes a token from the bucket identified by `key`,
+    /// the inner error is how long to wait when it's empty
+    pub async fn take(&self, key: &str, limit: &RateLimit) -> Result<Result<(), Duration>> {
+        match self {
+            Self::Memory(buckets) => {
+                let now = STARTED.elapsed().as_secs_f64();
+                let mut buckets = buckets.lock().unwrap();
+                let (bucket, refilled_at) = buckets
+                    .entry(key.to_owned())
+                    .or_insert_with(|| (TokenBucket::full(limit, now), now));
+
+                let taken = bucket.take(limit, now);
+                *refilled_at = bucket.refilled_at(limit);
+                Ok(taken)
+            }
+            Self::Postgres(db_pool) => take_stored(db_pool, key, limit).await,
+        }
+    }
+
+    /// Forgets buckets which are full again, they behave like new ones
+    pub async fn prune(&self) -> Result<()> {
+        match self {
+            Self::Memory(buckets) => {
+                let now = STARTED.elapsed().as_secs_f64();
+                buckets
+                    .lock()
+                    .unwrap()
+                    .retain(|_, (_, refilled_at)| *refilled_at > now);
+            }
+            Self::Postgres(db_pool) => {
+                sqlx::query(
+                    "DELETE FROM __B_rate_limits
+                    WHERE refilled_at < EXTRACT(EPOCH FROM clock_timestamp())",
+                )
+                .execute(db_pool)
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn prune_periodically(&self) {
+        let limiter = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(PRUNE_INTERVAL).await;
+
+                if let Err(e) = limiter.prune().await {
+                    tracing::error!("Pruning rate limits failed: {}", e);
+                }
+            }
+        });
+    }
+}
+
+/// Refills and takes from the bucket in one statement, the row is locked by the update,
+/// so that instances take tokens one after another. Like `TokenBucket::take`,
+/// with a new bucket being full and the clock of the database, same for all instances.
+async fn take_stored(
+    db_pool: &PgPool,
+    key: &str,
+    limit: &RateLimit,
+) -> Result<Result<(), Duration>> {
+    let (tokens, allowed) = sqlx::query_as::<Postgres, (f64, bool)>(
+        r#"
+            INSERT INTO __B_rate_limits AS bucket
+                (bucket_key, tokens, updated_at, refilled_at, allowed)
+            SELECT $1, $2 - 1, now, now + 1 / $3, true
+            FROM (SELECT EXTRACT(EPOCH FROM clock_timestamp())::float8 AS now) AS clock
+            ON CONFLICT (bucket_key) DO UPDATE SET
+                (tokens, updated_at, refilled_at, allowed) = (
+                    SELECT
+                        refilled - allowed::int,
+                        EXCLUDED.updated_at,
+                        EXCLUDED.updated_at + ($2 - refilled + allowed::int) / $3,
+                        allowed
+                    FROM (
+                        SELECT refilled, refilled >= 1 AS allowed
+                        FROM (
+                            SELECT LEAST(
+                                $2,
+                                bucket.tokens
+                                    + GREATEST(EXCLUDED.updated_at - bucket.updated_at, 0) * $3
+                            ) AS refilled
+                        ) AS refill
+                    ) AS taken
+                )
+            RETURNING tokens, allowed
+        "#,
+    )
+    .bind(key)
+    .bind(limit.capacity())
+    .bind(limit.refill_rate())
+    .fetch_one(db_pool)
+    .await?;
+
+    if allowed {
+        Ok(Ok(()))
+    } else {
+        let missing = 1.0 - tokens;
+        Ok(Err(Duration::from_secs_f64(missing / limit.refill_rate())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_database::TestDatabase;
+
+    #[tokio::test]
+    async fn taking_stored_tokens() {
+        let db = TestDatabase::with_internal_tables().await;
+        let limiter = RateLimiter::Postgres(db.pool.clone());
+        let limit = RateLimit {
+            requests: 2,
+            per_seconds: 10,
+            burst: Some(3),
+        };
+
+        for _ in 0..3 {
+            assert_eq!(limiter.take("1 alice", &limit).await.unwrap(), Ok(()));
+        }
+
+        // a token is refilled every 5 seconds, less the time the calls took
+        let wait = limiter.take("1 alice", &limit).await.unwrap().unwrap_err();
+        assert!(wait <= Duration::from_secs(5) && wait > Duration::from_secs(4));
+
+        assert_eq!(limiter.take("1 bob", &limit).await.unwrap(), Ok(()));
+
+        // two tokens are refilled in 10 seconds
+        sqlx::query("UPDATE __B_rate_limits SET updated_at = updated_at - 10")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            assert_eq!(limiter.take("1 alice", &limit).await.unwrap(), Ok(()));
+        }
+        assert!(limiter.take("1 alice", &limit).await.unwrap().is_err());
+
+        db.drop().await;
+    }
+}
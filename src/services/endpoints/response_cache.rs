@@ -0,0 +1,66 @@
+use axum::http::HeaderValue;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Responses kept per endpoint, calls with other arguments aren't cached when it's full
+const MAX_ENTRIES: usize = 1000;
+
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    pub body: Arc<str>,
+    pub etag: String,
+    pub location: Option<HeaderValue>,
+    expires_at: Instant,
+}
+
+/// Successful responses of an endpoint with a `cache_ttl`.
+///
+/// Lives as long as the registered endpoint, so changing the definition drops it.
+#[derive(Debug, Default)]
+pub struct ResponseCache {
+    entries: Mutex<HashMap<String, CachedResponse>>,
+}
+
+impl ResponseCache {
+    pub fn get(&self, key: &str) -> Option<CachedResponse> {
+        let mut entries = self.entries.lock().unwrap();
+
+        match entries.get(key) {
+            Some(entry) if entry.expires_at > Instant::now() => Some(entry.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(
+        &self,
+        key: String,
+        body: Arc<str>,
+        etag: String,
+        location: Option<HeaderValue>,
+        ttl: Duration,
+    ) {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+
+        if entries.len() >= MAX_ENTRIES {
+            entries.retain(|_, entry| entry.expires_at > now);
+        }
+
+        if entries.len() < MAX_ENTRIES {
+            entries.insert(
+                key,
+                CachedResponse {
+                    body,
+                    etag,
+                    location,
+                    expires_at: now + ttl,
+                },
+            );
+        }
+    }
+}
@@ -1,5 +1,6 @@
 pub mod data_management;
 pub mod endpoints;
+pub mod row_level_security;
 pub mod schema_editing;
 pub mod schema_info;
 pub mod sql_execution;
@@ -0,0 +1,231 @@
+use crate::auth::Claims;
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use sqlx::{Acquire, Executor, PgPool, Postgres, Transaction};
+
+/// Whether queries of users run under the role of their group,
+/// so that Postgres RLS policies apply to them
+pub static ENABLED: Lazy<bool> = Lazy::new(|| {
+    std::env::var("ROW_LEVEL_SECURITY")
+        .map(|it| it == "true" || it == "1")
+        .unwrap_or(false)
+});
+
+/// Group of callers without a token
+pub const ANONYMOUS_GROUP: &str = "PUBLIC";
+
+/// Tables of the server which users mustn't read or change
+const INTERNAL_TABLES: &str = "__B_users, __B_endpoints, __B_endpoint_versions, __B_rate_limits";
+
+/// Admins keep the role of the server, which owns the tables and bypasses RLS
+pub const ADMIN_GROUP: &str = "ADMIN";
+
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Name of the Postgres role of a user group
+pub fn group_role(group: &str) -> String {
+    format!("__b_group_{}", group)
+}
+
+pub async fn group_role_exists<'e>(
+    executor: impl Executor<'e, Database = Postgres>,
+    group: &str,
+) -> Result<bool> {
+    Ok(sqlx::query_as::<Postgres, (bool,)>(
+        "SELECT EXISTS (SELECT 1 FROM pg_roles WHERE rolname = $1)",
+    )
+    .bind(group_role(group))
+    .fetch_one(executor)
+    .await?
+    .0)
+}
+
+/// Creates the role of the group if it doesn't exist and lets it use the tables
+/// other than the internal ones.
+///
+/// The server's role becomes a member, so that it can `SET ROLE`.
+/// Privileges on tables created later are granted by default privileges.
+/// In a transaction, the role is created in a savepoint of it.
+pub async fn create_group_role<'a>(
+    db: impl Acquire<'a, Database = Postgres>,
+    group: &str,
+) -> Result<()> {
+    if group == ADMIN_GROUP {
+        return Ok(());
+    }
+
+    let mut transaction = db.begin().await?;
+    let exists = group_role_exists(&mut transaction, group).await?;
+    let role = quote_ident(&group_role(group));
+
+    if !exists {
+        transaction
+            .execute(
+                format!(
+                    r#"
+                        CREATE ROLE {role} NOLOGIN;
+                        GRANT {role} TO CURRENT_USER;
+                        GRANT USAGE ON SCHEMA public TO {role};
+                        GRANT SELECT, INSERT, UPDATE, DELETE ON ALL TABLES IN SCHEMA public TO {role};
+                        GRANT USAGE, SELECT ON ALL SEQUENCES IN SCHEMA public TO {role};
+                        ALTER DEFAULT PRIVILEGES IN SCHEMA public
+                            GRANT SELECT, INSERT, UPDATE, DELETE ON TABLES TO {role};
+                        ALTER DEFAULT PRIVILEGES IN SCHEMA public
+                            GRANT USAGE, SELECT ON SEQUENCES TO {role};
+                    "#,
+                    role = role
+                )
+                .as_str(),
+            )
+            .await?;
+    }
+
+    // Also for existing roles, which got default privileges on internal tables added later
+    transaction
+        .execute(format!("REVOKE ALL ON {} FROM {}", INTERNAL_TABLES, role).as_str())
+        .await?;
+
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Creates the roles of the groups of existing users
+pub async fn create_group_roles(db_pool: &PgPool) -> Result<()> {
+    let groups = sqlx::query_as::<Postgres, (String,)>("SELECT DISTINCT user_group FROM __B_users")
+        .fetch_all(db_pool)
+        .await?;
+
+    create_group_role(db_pool, ANONYMOUS_GROUP).await?;
+
+    for (group,) in groups {
+        create_group_role(db_pool, &group).await?;
+    }
+
+    Ok(())
+}
+
+/// Begins a transaction of the user, `None` for callers without a token.
+///
+/// When row level security is enabled, the transaction runs under the role
+/// of the user's group, with `bercik.user_id`, `bercik.username`
+/// and `bercik.user_group` set for RLS policies.
+pub async fn begin_as(
+    db_pool: &PgPool,
+    user: Option<&Claims>,
+) -> Result<Transaction<'static, Postgres>> {
+    if *ENABLED {
+        begin_under_role(db_pool, user).await
+    } else {
+        Ok(db_pool.begin().await?)
+    }
+}
+
+async fn begin_under_role(
+    db_pool: &PgPool,
+    user: Option<&Claims>,
+) -> Result<Transaction<'static, Postgres>> {
+    let mut transaction = db_pool.begin().await?;
+    let group = user.map_or(ANONYMOUS_GROUP, Claims::user_group);
+
+    if group != ADMIN_GROUP {
+        let set_role = format!("SET LOCAL ROLE {}", quote_ident(&group_role(group)));
+        transaction.execute(set_role.as_str()).await?;
+    }
+
+    sqlx::query(
+        r#"
+            SELECT set_config('bercik.user_id', $1, true),
+                   set_config('bercik.username', $2, true),
+                   set_config('bercik.user_group', $3, true)
+        "#,
+    )
+    .bind(
+        user.and_then(Claims::user_id)
+            .map(|it| it.to_string())
+            .unwrap_or_default(),
+    )
+    .bind(user.map(Claims::username).unwrap_or_default())
+    .bind(group)
+    .execute(&mut transaction)
+    .await?;
+
+    Ok(transaction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_database::TestDatabase;
+    use serde_json::json;
+
+    fn user(id: i32, username: &str, group: &str) -> Claims {
+        serde_json::from_value(json!({
+            "user_id": id,
+            "username": username,
+            "user_group": group,
+            "exp": 0,
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn policies_see_the_user_of_the_transaction() {
+        let db = TestDatabase::with_internal_tables().await;
+        // roles are shared by all databases of the server
+        let group = format!("{}_readers", db.name());
+
+        db.pool
+            .execute(
+                r#"
+                    CREATE TABLE notes (id serial PRIMARY KEY, owner text, body text);
+                    ALTER TABLE notes ENABLE ROW LEVEL SECURITY;
+                    CREATE POLICY own_notes ON notes
+                        USING (owner = current_setting('bercik.username'));
+                    INSERT INTO notes (owner, body) VALUES ('ann', 'a'), ('bob', 'b');
+                "#,
+            )
+            .await
+            .unwrap();
+        create_group_role(&db.pool, &group).await.unwrap();
+
+        let ann = user(7, "ann", &group);
+        let mut transaction = begin_under_role(&db.pool, Some(&ann)).await.unwrap();
+
+        let settings = sqlx::query_as::<Postgres, (String, String, String)>(
+            r#"
+                SELECT current_user::text, current_setting('bercik.user_id'),
+                    current_setting('bercik.user_group')
+            "#,
+        )
+        .fetch_one(&mut transaction)
+        .await
+        .unwrap();
+        assert_eq!(settings, (group_role(&group), "7".into(), group.clone()));
+
+        let bodies = sqlx::query_as::<Postgres, (String,)>("SELECT body FROM notes")
+            .fetch_all(&mut transaction)
+            .await
+            .unwrap();
+        assert_eq!(bodies, vec![("a".to_owned(),)]);
+
+        let internal = sqlx::query("SELECT * FROM __B_users")
+            .execute(&mut transaction)
+            .await;
+        assert!(internal.is_err());
+        transaction.rollback().await.unwrap();
+
+        // admins keep the role of the server, which owns the table
+        let admin = user(1, "admin", ADMIN_GROUP);
+        let mut transaction = begin_under_role(&db.pool, Some(&admin)).await.unwrap();
+        let (count,) = sqlx::query_as::<Postgres, (i64,)>("SELECT count(*) FROM notes")
+            .fetch_one(&mut transaction)
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+        transaction.rollback().await.unwrap();
+
+        db.drop().await;
+    }
+}
@@ -1,23 +1,28 @@
 use crate::types::table_field_types::TableField;
 use sqlx::postgres::PgPool;
 
+/// Column definition as used by `CREATE TABLE` and `ADD COLUMN`
+pub fn field_definition(field: &TableField) -> String {
+    let name = &field.name;
+    let f_type = &field.field_type.to_postgres_type();
+    let not_null = if field.not_null { "NOT NULL" } else { "" };
+    let default = &field.default.to_postgres_default_value();
+
+    [name, f_type, not_null, default].join(" ")
+}
+
 fn build_field_lines(fields: &Vec<TableField>) -> Vec<String> {
     let mut lines: Vec<String> = Vec::new();
     lines.push("id SERIAL PRIMARY KEY".into());
 
     for field in fields {
-        let name = &field.name;
-        let f_type = &field.field_type.to_postgres_type();
-        let not_null = if field.not_null { "NOT NULL" } else { "" };
-        let default = &field.default.to_postgres_default_value();
-
-        lines.push([name, f_type, not_null, default].join(" "));
+        lines.push(field_definition(field));
     }
 
     lines
 }
 
-fn build_create_sql(name: &str, fields: &Vec<TableField>) -> String {
+pub fn build_create_sql(name: &str, fields: &Vec<TableField>) -> String {
     let lines = build_field_lines(fields);
     let field_lines = lines.join(", ");
 
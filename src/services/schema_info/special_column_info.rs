@@ -10,6 +10,6 @@ pub async fn special_column_info(db_pool: &PgPool) -> Result<SpecialColumnMap> {
     let pkey_info = special_column_info_pkeys(db_pool).await?;
 
     Ok(SpecialColumnMap::build(
-        fkey_info.into_iter().chain(pkey_info.into_iter()).collect(),
+        fkey_info.into_iter().chain(pkey_info).collect(),
     ))
 }
@@ -1,7 +1,6 @@
 use crate::services::schema_info::special_column_info::special_column_info;
 use crate::types::column_info::ColumnInfo;
 use crate::types::column_info::ColumnInfoWithSpecial;
-use crate::types::column_info::ForeignKeyMap;
 use crate::types::special_column_info::SpecialColumnMap;
 use crate::types::special_column_info::SpecialColumnMapKey;
 use crate::types::table_info::TableInfo;
@@ -11,9 +10,6 @@ use sqlx::FromRow;
 use sqlx::PgPool;
 use sqlx::Postgres;
 
-use super::fkey_info::foreign_key_map;
-use super::fkey_info::get_all_foreign_keys;
-
 pub async fn get_table_names(db_pool: &PgPool) -> Result<Vec<String>> {
     #[derive(FromRow)]
     struct TableName {
@@ -36,7 +32,7 @@ pub async fn get_table_names(db_pool: &PgPool) -> Result<Vec<String>> {
         .collect())
 }
 
-pub async fn get_table_columns<'a>(db_pool: &PgPool, table_name: &str) -> Result<Vec<ColumnInfo>> {
+pub async fn get_table_columns(db_pool: &PgPool, table_name: &str) -> Result<Vec<ColumnInfo>> {
     let query = r#"
     SELECT column_name as name, data_type, is_nullable::bool, coalesce(column_default, '') as column_default
         FROM information_schema.columns
@@ -4,7 +4,9 @@ use sqlx::Postgres;
 use sqlx::Transaction;
 
 use crate::algorithms::mermaid_diagram_generation::MermaidDiagram;
+use crate::auth::Claims;
 use crate::routes::data_management::sql_editor::{ExecuteQueriesRequest, ExecuteQueriesResponse};
+use crate::services::row_level_security::begin_as;
 use crate::types::arbitrary_sql_array_row::ArbitrarySqlArrayRowsAndNames;
 
 mod transaction_table_info;
@@ -13,9 +15,9 @@ async fn get_query_diff<'a>(
     transaction: &mut Transaction<'a, Postgres>,
     diff_query: &str,
 ) -> Result<ArbitrarySqlArrayRowsAndNames> {
-    Ok(ArbitrarySqlArrayRowsAndNames::from_row_vec(
+    ArbitrarySqlArrayRowsAndNames::from_row_vec(
         sqlx::query(diff_query).fetch_all(transaction).await?,
-    )?)
+    )
 }
 
 async fn get_mermaid_diff<'a>(transaction: &mut Transaction<'a, Postgres>) -> Result<String> {
@@ -30,12 +32,13 @@ async fn get_mermaid_diff<'a>(transaction: &mut Transaction<'a, Postgres>) -> Re
 pub async fn execute_queries(
     db_pool: &PgPool,
     req: &ExecuteQueriesRequest,
+    user: Option<&Claims>,
 ) -> Result<ExecuteQueriesResponse> {
     let mut query_results = Vec::<ArbitrarySqlArrayRowsAndNames>::new();
     let mut query_diff: Option<Vec<ArbitrarySqlArrayRowsAndNames>> = None;
     let mut mermaid_diff: Option<Vec<String>> = None;
 
-    let mut transaction = db_pool.begin().await?;
+    let mut transaction = begin_as(db_pool, user).await?;
 
     if req.should_diff_mermaid {
         mermaid_diff = Some(vec![get_mermaid_diff(&mut transaction).await?]);
@@ -105,7 +105,7 @@ async fn special_column_info<'a>(
     let pkey_info = special_column_info_pkeys(db_pool).await?;
 
     Ok(SpecialColumnMap::build(
-        fkey_info.into_iter().chain(pkey_info.into_iter()).collect(),
+        fkey_info.into_iter().chain(pkey_info).collect(),
     ))
 }
 
@@ -0,0 +1,651 @@
+use crate::algorithms::endpoint_bundle::BundleChange;
+use crate::routes::custom_endpoints::endpoint_crud::CreateEndpointRequest;
+use crate::routes::schema::schema_editing::create_table_form::CreateTableFormRequest;
+use crate::services::endpoints::endpoint_bundle::{
+    apply_bundle, plan_bundle, plan_new_bundle, EndpointBundle,
+};
+use crate::services::row_level_security::{
+    self, create_group_role, group_role_exists, ADMIN_GROUP, ANONYMOUS_GROUP,
+};
+use crate::services::schema_editing::form_table_creation::{build_create_sql, field_definition};
+use crate::services::schema_info::table_info::{get_table_columns, get_table_names};
+use crate::setup::setup_internal_tables::INTERNAL_COLUMNS;
+use crate::types::table_field_types::{TableField, TableFieldType};
+use anyhow::{anyhow, Context, Result};
+use serde::de::DeserializeOwned;
+use sqlx::{Executor, PgPool, Postgres};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Recorded as the author of endpoint versions changed by the manifest
+const MANIFEST_AUTHOR: &str = "manifest";
+
+/// Command line options of the server
+pub struct StartupOptions {
+    /// `--manifest <dir>`, or the `MANIFEST_DIR` variable
+    pub manifest_dir: Option<PathBuf>,
+    /// `--check` prints the drift from the manifest and exits
+    pub check: bool,
+}
+
+impl StartupOptions {
+    pub fn from_args() -> Result<Self> {
+        let mut options = Self {
+            manifest_dir: std::env::var_os("MANIFEST_DIR").map(PathBuf::from),
+            check: false,
+        };
+
+        let mut args = std::env::args().skip(1);
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--check" => options.check = true,
+                "--manifest" => {
+                    let dir = args.next().context("--manifest needs a directory")?;
+                    options.manifest_dir = Some(dir.into());
+                }
+                other => return Err(anyhow!("Unknown argument {}", other)),
+            }
+        }
+
+        if options.check && options.manifest_dir.is_none() {
+            return Err(anyhow!("--check needs a manifest directory"));
+        }
+
+        Ok(options)
+    }
+}
+
+/// Configuration kept in a directory:
+///
+/// - `tables/*.yaml` tables as sent to `/api/create-table`, `table_name` and `table_fields`
+/// - `endpoints/*.yaml` one `CreateEndpointRequest` per file
+/// - `groups.yaml` list of user groups
+///
+/// Json files are read as well. Endpoints and groups are only managed
+/// when their directory or file exists, endpoints missing in the directory are deleted.
+#[derive(Default)]
+pub struct Manifest {
+    pub tables: Vec<CreateTableFormRequest>,
+    pub endpoints: Option<Vec<CreateEndpointRequest>>,
+    pub groups: Option<Vec<String>>,
+}
+
+fn is_manifest_file(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|it| it.to_str()),
+        Some("yaml" | "yml" | "json")
+    )
+}
+
+fn read_file<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let content = fs::read_to_string(path)?;
+    // Json is also yaml
+    serde_yaml::from_str(&content).with_context(|| format!("Couldn't parse {}", path.display()))
+}
+
+/// Files of the directory ordered by name, `None` when it doesn't exist
+fn read_dir<T: DeserializeOwned>(dir: &Path) -> Result<Option<Vec<T>>> {
+    if !dir.is_dir() {
+        return Ok(None);
+    }
+
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?;
+    paths.retain(|it| is_manifest_file(it));
+    paths.sort();
+
+    paths
+        .iter()
+        .map(|it| read_file(it))
+        .collect::<Result<_>>()
+        .map(Some)
+}
+
+impl Manifest {
+    pub fn load(dir: &Path) -> Result<Self> {
+        if !dir.is_dir() {
+            return Err(anyhow!("Manifest directory {} not found", dir.display()));
+        }
+
+        let groups_file = ["groups.yaml", "groups.yml", "groups.json"]
+            .iter()
+            .map(|it| dir.join(it))
+            .find(|it| it.is_file());
+
+        Ok(Self {
+            tables: read_dir(&dir.join("tables"))?.unwrap_or_default(),
+            endpoints: read_dir(&dir.join("endpoints"))?,
+            groups: groups_file.as_deref().map(read_file).transpose()?,
+        })
+    }
+}
+
+/// Difference between the database and the manifest
+#[derive(Debug)]
+pub enum Drift<'a> {
+    /// Tables of the server, created when it starts
+    MissingInternalTable(&'static str),
+    MissingInternalColumn {
+        table: &'static str,
+        column: &'static str,
+    },
+    MissingTable(&'a CreateTableFormRequest),
+    MissingColumn {
+        table: &'a str,
+        field: &'a TableField,
+    },
+    /// Columns are never dropped, the manifest has to be updated
+    ExtraColumn {
+        table: String,
+        column: String,
+    },
+    MissingGroupRole(&'a str),
+    /// Users are never changed, the manifest has to be updated
+    UndeclaredGroup(String),
+    Endpoint(BundleChange),
+}
+
+impl Drift<'_> {
+    /// Whether applying the manifest removes the drift
+    pub fn is_applied(&self) -> bool {
+        !matches!(self, Self::ExtraColumn { .. } | Self::UndeclaredGroup(_))
+    }
+}
+
+impl fmt::Display for Drift<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingInternalTable(table) => {
+                write!(f, "Table {} of the server is missing", table)
+            }
+            Self::MissingInternalColumn { table, column } => {
+                write!(f, "Column {}.{} of the server is missing", table, column)
+            }
+            Self::MissingTable(table) => write!(f, "Table {} is missing", table.table_name),
+            Self::MissingColumn { table, field } => {
+                write!(f, "Column {}.{} is missing", table, field.name)
+            }
+            Self::ExtraColumn { table, column } => {
+                write!(f, "Column {}.{} isn't in the manifest", table, column)
+            }
+            Self::MissingGroupRole(group) => write!(f, "Role of group {} is missing", group),
+            Self::UndeclaredGroup(group) => {
+                write!(
+                    f,
+                    "Users of group {} exist, the group isn't in the manifest",
+                    group
+                )
+            }
+            Self::Endpoint(BundleChange::Create { endpoint, .. }) => {
+                write!(f, "Endpoint {} is missing", endpoint)
+            }
+            Self::Endpoint(BundleChange::Update {
+                endpoint, changes, ..
+            }) => {
+                let paths = changes
+                    .iter()
+                    .map(|it| it.path.as_str())
+                    .collect::<Vec<_>>();
+                write!(f, "Endpoint {} differs in {}", endpoint, paths.join(", "))
+            }
+            Self::Endpoint(BundleChange::Delete { endpoint, .. }) => {
+                write!(f, "Endpoint {} isn't in the manifest", endpoint)
+            }
+        }
+    }
+}
+
+/// Orders tables so that tables referenced by foreign keys are created first
+fn creation_order(tables: Vec<&CreateTableFormRequest>) -> Result<Vec<&CreateTableFormRequest>> {
+    let mut remaining = tables;
+    let mut ordered: Vec<&CreateTableFormRequest> = Vec::with_capacity(remaining.len());
+
+    while !remaining.is_empty() {
+        let is_pending = |name: &str| {
+            remaining
+                .iter()
+                .any(|it| it.table_name.eq_ignore_ascii_case(name))
+        };
+
+        let ready = remaining.iter().position(|table| {
+            table
+                .table_fields
+                .iter()
+                .all(|field| match &field.field_type {
+                    TableFieldType::ForeignKey(target) => {
+                        target.eq_ignore_ascii_case(&table.table_name) || !is_pending(target)
+                    }
+                    _ => true,
+                })
+        });
+
+        match ready {
+            Some(index) => ordered.push(remaining.remove(index)),
+            None => {
+                let names = remaining
+                    .iter()
+                    .map(|it| it.table_name.as_str())
+                    .collect::<Vec<_>>();
+                return Err(anyhow!(
+                    "Foreign keys of tables {} form a cycle",
+                    names.join(", ")
+                ));
+            }
+        }
+    }
+
+    Ok(ordered)
+}
+
+/// Internal tables and columns which `init_tables` would create
+async fn internal_drift(db_pool: &PgPool) -> Result<Vec<Drift<'static>>> {
+    let tables = INTERNAL_COLUMNS
+        .iter()
+        .map(|(table, _)| *table)
+        .collect::<Vec<_>>();
+    let existing = sqlx::query_as::<Postgres, (String, String)>(
+        r#"
+            SELECT table_name::text, column_name::text
+            FROM information_schema.columns
+            WHERE table_schema = 'public' AND table_name = ANY($1)
+        "#,
+    )
+    .bind(tables)
+    .fetch_all(db_pool)
+    .await?;
+
+    let mut drift = Vec::new();
+
+    for (table, columns) in INTERNAL_COLUMNS {
+        if !existing.iter().any(|(name, _)| name == table) {
+            drift.push(Drift::MissingInternalTable(table));
+            continue;
+        }
+
+        for column in columns.iter() {
+            if !existing.iter().any(|it| it.0 == *table && it.1 == *column) {
+                drift.push(Drift::MissingInternalColumn { table, column });
+            }
+        }
+    }
+
+    Ok(drift)
+}
+
+/// Whether the internal table is missing or lacks columns
+fn is_incomplete(drift: &[Drift], internal_table: &str) -> bool {
+    drift.iter().any(|item| match item {
+        Drift::MissingInternalTable(table) | Drift::MissingInternalColumn { table, .. } => {
+            *table == internal_table
+        }
+        _ => false,
+    })
+}
+
+async fn table_drift<'a>(db_pool: &PgPool, manifest: &'a Manifest) -> Result<Vec<Drift<'a>>> {
+    let existing = get_table_names(db_pool).await?;
+    let mut drift = Vec::new();
+    let mut missing_tables = Vec::new();
+
+    for table in &manifest.tables {
+        // Unquoted names are folded to lowercase by postgres
+        let name = table.table_name.to_lowercase();
+
+        if !existing.contains(&name) {
+            missing_tables.push(table);
+            continue;
+        }
+
+        let columns = get_table_columns(db_pool, &name)
+            .await?
+            .into_iter()
+            .map(|it| it.name)
+            .collect::<Vec<_>>();
+
+        for field in &table.table_fields {
+            if !columns.contains(&field.name.to_lowercase()) {
+                drift.push(Drift::MissingColumn {
+                    table: &table.table_name,
+                    field,
+                });
+            }
+        }
+
+        for column in columns {
+            let declared = column == "id"
+                || table
+                    .table_fields
+                    .iter()
+                    .any(|it| it.name.eq_ignore_ascii_case(&column));
+
+            if !declared {
+                drift.push(Drift::ExtraColumn {
+                    table: name.clone(),
+                    column,
+                });
+            }
+        }
+    }
+
+    let created = creation_order(missing_tables)?
+        .into_iter()
+        .map(Drift::MissingTable);
+
+    Ok(created.chain(drift).collect())
+}
+
+/// `users_exist` is false when `__B_users` is missing, then only roles are compared
+async fn group_drift<'a>(
+    db_pool: &PgPool,
+    groups: &'a [String],
+    users_exist: bool,
+) -> Result<Vec<Drift<'a>>> {
+    let mut drift = Vec::new();
+
+    if *row_level_security::ENABLED {
+        for group in groups {
+            if group != ADMIN_GROUP && !group_role_exists(db_pool, group).await? {
+                drift.push(Drift::MissingGroupRole(group));
+            }
+        }
+    }
+
+    if !users_exist {
+        return Ok(drift);
+    }
+
+    let user_groups =
+        sqlx::query_as::<Postgres, (String,)>("SELECT DISTINCT user_group FROM __B_users")
+            .fetch_all(db_pool)
+            .await?;
+
+    for (group,) in user_groups {
+        let declared = group == ADMIN_GROUP || group == ANONYMOUS_GROUP || groups.contains(&group);
+
+        if !declared {
+            drift.push(Drift::UndeclaredGroup(group));
+        }
+    }
+
+    Ok(drift)
+}
+
+/// Tables and groups differing from the manifest, `internal` is the drift of internal tables
+async fn schema_drift<'a>(
+    db_pool: &PgPool,
+    manifest: &'a Manifest,
+    internal: &[Drift<'_>],
+) -> Result<Vec<Drift<'a>>> {
+    let mut drift = table_drift(db_pool, manifest).await?;
+
+    if let Some(groups) = &manifest.groups {
+        let users_exist = !is_incomplete(internal, "__b_users");
+        drift.extend(group_drift(db_pool, groups, users_exist).await?);
+    }
+
+    Ok(drift)
+}
+
+fn endpoint_bundle(manifest: &Manifest) -> Result<Option<EndpointBundle>> {
+    // Cloned through json, the manifest is still needed for reporting
+    manifest
+        .endpoints
+        .as_ref()
+        .map(|endpoints| {
+            Ok(EndpointBundle {
+                endpoints: serde_json::from_value(serde_json::to_value(endpoints)?)?,
+            })
+        })
+        .transpose()
+}
+
+/// Everything that differs from the manifest, without changing the database,
+/// so also the internal tables which the server creates when it starts.
+///
+/// Endpoints aren't validated, as they may use tables which the manifest
+/// hasn't created yet, invalid ones are rejected when the manifest is applied.
+/// They aren't compared while columns of `__B_endpoints` are missing.
+pub async fn check_manifest<'a>(
+    db_pool: &PgPool,
+    manifest: &'a Manifest,
+) -> Result<Vec<Drift<'a>>> {
+    let mut drift = internal_drift(db_pool).await?;
+    drift.extend(schema_drift(db_pool, manifest, &drift).await?);
+
+    if let Some(bundle) = endpoint_bundle(manifest)? {
+        let endpoints_missing = drift
+            .iter()
+            .any(|it| matches!(it, Drift::MissingInternalTable("__b_endpoints")));
+
+        let plan = if endpoints_missing {
+            Some(plan_new_bundle(&bundle)?)
+        } else if is_incomplete(&drift, "__b_endpoints") {
+            None
+        } else {
+            Some(plan_bundle(db_pool, &bundle).await?)
+        };
+
+        for change in plan.into_iter().flat_map(|it| it.changes) {
+            drift.push(Drift::Endpoint(change));
+        }
+    }
+
+    Ok(drift)
+}
+
+/// Reconciles the database toward the manifest in one transaction, so that an invalid
+/// endpoint leaves the database unchanged. Drift which can't be applied,
+/// like columns missing in the manifest, is logged.
+pub async fn apply_manifest(db_pool: &PgPool, manifest: &Manifest) -> Result<()> {
+    // Called by `init_tables`, after the internal tables are created
+    let drift = schema_drift(db_pool, manifest, &[]).await?;
+    let bundle = endpoint_bundle(manifest)?;
+    let mut transaction = db_pool.begin().await?;
+
+    for item in &drift {
+        match item {
+            Drift::MissingTable(table) => {
+                let sql = build_create_sql(&table.table_name, &table.table_fields);
+                transaction.execute(sql.as_str()).await?;
+            }
+            Drift::MissingColumn { table, field } => {
+                let sql = format!(
+                    "ALTER TABLE {} ADD COLUMN {}",
+                    table,
+                    field_definition(field)
+                );
+                transaction.execute(sql.as_str()).await?;
+            }
+            Drift::MissingGroupRole(group) => create_group_role(&mut transaction, group).await?,
+            _ => {}
+        }
+    }
+
+    // Validated against the tables created above
+    let plan = match bundle {
+        Some(bundle) => Some(apply_bundle(&mut transaction, bundle, MANIFEST_AUTHOR).await?),
+        None => None,
+    };
+
+    transaction.commit().await?;
+
+    for item in &drift {
+        if item.is_applied() {
+            tracing::info!("Reconciled with the manifest: {}", item);
+        } else {
+            tracing::warn!("Not reconciled with the manifest: {}", item);
+        }
+    }
+
+    for change in plan.into_iter().flat_map(|it| it.changes) {
+        let item = Drift::Endpoint(change);
+        tracing::info!("Reconciled with the manifest: {}", item);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_database::TestDatabase;
+    use crate::types::table_field_types::DefaultValue;
+    use serde_json::json;
+
+    fn table(name: &str, references: &[&str]) -> CreateTableFormRequest {
+        CreateTableFormRequest {
+            table_name: name.into(),
+            table_fields: references
+                .iter()
+                .map(|target| TableField {
+                    name: format!("{}_fk", target),
+                    field_type: TableFieldType::ForeignKey(target.to_string()),
+                    not_null: false,
+                    default: DefaultValue::None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn referenced_tables_are_created_first() {
+        let tables = [
+            table("comments", &["posts", "users"]),
+            table("posts", &["users"]),
+            table("users", &["users", "existing"]),
+        ];
+
+        let names = |tables: Vec<&CreateTableFormRequest>| {
+            tables
+                .into_iter()
+                .map(|it| it.table_name.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names(creation_order(tables.iter().collect()).unwrap()),
+            vec!["users", "posts", "comments"]
+        );
+
+        let cycle = [table("a", &["b"]), table("b", &["a"])];
+        assert!(creation_order(cycle.iter().collect()).is_err());
+    }
+
+    /// Table `books` and an endpoint reading `table`
+    fn manifest(table: &str) -> Manifest {
+        let books = CreateTableFormRequest {
+            table_name: "books".into(),
+            table_fields: vec![TableField {
+                name: "title".into(),
+                field_type: TableFieldType::Text,
+                not_null: false,
+                default: DefaultValue::None,
+            }],
+        };
+
+        Manifest {
+            tables: vec![books],
+            endpoints: Some(vec![serde_json::from_value(json!({
+                "path": "books",
+                "method": "GET",
+                "endpoints_info": [
+                    {"name": "books", "sql": format!("SELECT title FROM {}", table), "children": []}
+                ],
+                "allowed_groups": ["WRITER"],
+            }))
+            .unwrap()]),
+            groups: Some(vec!["WRITER".into()]),
+        }
+    }
+
+    async fn public_tables(db: &TestDatabase) -> Vec<String> {
+        sqlx::query_as::<Postgres, (String,)>(
+            "SELECT table_name::text FROM information_schema.tables WHERE table_schema = 'public'",
+        )
+        .fetch_all(&db.pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|it| it.0)
+        .collect()
+    }
+
+    #[tokio::test]
+    async fn checking_doesnt_change_the_database() {
+        let db = TestDatabase::create().await;
+        let manifest = manifest("books");
+
+        let drift = check_manifest(&db.pool, &manifest).await.unwrap();
+        let drift = drift.iter().map(Drift::to_string).collect::<Vec<_>>();
+
+        assert_eq!(
+            drift,
+            vec![
+                "Table __b_endpoints of the server is missing",
+                "Table __b_endpoint_versions of the server is missing",
+                "Table __b_users of the server is missing",
+                "Table __b_rate_limits of the server is missing",
+                "Table books is missing",
+                "Endpoint GET /books is missing",
+            ]
+        );
+        assert!(public_tables(&db).await.is_empty());
+
+        // a server before drafts, its endpoints can't be compared
+        db.pool
+            .execute(include_str!("setup_internal_tables/init_endpoints.sql"))
+            .await
+            .unwrap();
+        db.pool
+            .execute("ALTER TABLE __B_endpoints DROP COLUMN draft")
+            .await
+            .unwrap();
+
+        let drift = check_manifest(&db.pool, &manifest).await.unwrap();
+        assert_eq!(
+            drift[0].to_string(),
+            "Column __b_endpoints.draft of the server is missing"
+        );
+        assert!(!drift.iter().any(|it| matches!(it, Drift::Endpoint(_))));
+        assert_eq!(public_tables(&db).await, vec!["__b_endpoints"]);
+
+        db.drop().await;
+    }
+
+    #[tokio::test]
+    async fn applied_manifests_have_no_drift() {
+        let db = TestDatabase::with_internal_tables().await;
+        let manifest = manifest("books");
+
+        let drift = check_manifest(&db.pool, &manifest).await.unwrap();
+        assert_eq!(drift.len(), 2);
+
+        // the endpoint is validated against the table created with it
+        apply_manifest(&db.pool, &manifest).await.unwrap();
+
+        let drift = check_manifest(&db.pool, &manifest).await.unwrap();
+        assert!(drift.is_empty(), "{:?}", drift);
+
+        db.drop().await;
+    }
+
+    #[tokio::test]
+    async fn invalid_manifests_change_nothing() {
+        let db = TestDatabase::with_internal_tables().await;
+        let manifest = manifest("novels");
+
+        let error = apply_manifest(&db.pool, &manifest).await.unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("relation \"novels\" does not exist"));
+
+        let drift = check_manifest(&db.pool, &manifest).await.unwrap();
+        assert_eq!(drift.len(), 2);
+        assert!(!public_tables(&db).await.contains(&"books".to_owned()));
+
+        db.drop().await;
+    }
+}
@@ -1 +1,2 @@
+pub mod manifest;
 pub mod setup_internal_tables;
@@ -0,0 +1,18 @@
+-- Every change of __B_endpoints, so that a bad edit can be rolled back
+CREATE TABLE IF NOT EXISTS __B_endpoint_versions (
+    id SERIAL PRIMARY KEY,
+
+    -- Not a foreign key, versions of deleted endpoints are kept
+    endpoint_id INTEGER NOT NULL,
+    version INTEGER NOT NULL,
+    -- create, update, delete, publish or rollback
+    action VARCHAR(50) NOT NULL,
+    -- The definition after the change, for deletions the deleted one
+    definition TEXT NOT NULL,
+    -- NULL for definitions recorded when the history was introduced
+    author VARCHAR(256),
+    restored_version INTEGER,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    UNIQUE (endpoint_id, version)
+);
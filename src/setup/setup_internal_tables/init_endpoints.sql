@@ -7,3 +7,12 @@ CREATE TABLE IF NOT EXISTS __B_endpoints (
     handler_info TEXT NOT NULL,
     allowed_groups TEXT NOT NULL
 );
+
+-- Columns added later, existing databases get them on startup
+ALTER TABLE __B_endpoints ADD COLUMN IF NOT EXISTS response TEXT NOT NULL DEFAULT '{}';
+ALTER TABLE __B_endpoints ADD COLUMN IF NOT EXISTS input_schema TEXT NOT NULL DEFAULT '{}';
+ALTER TABLE __B_endpoints ADD COLUMN IF NOT EXISTS rate_limit TEXT NOT NULL DEFAULT 'null';
+ALTER TABLE __B_endpoints ADD COLUMN IF NOT EXISTS cache_ttl TEXT NOT NULL DEFAULT 'null';
+ALTER TABLE __B_endpoints ADD COLUMN IF NOT EXISTS limits TEXT NOT NULL DEFAULT '{}';
+-- Definition saved by an admin which isn't live until it's published
+ALTER TABLE __B_endpoints ADD COLUMN IF NOT EXISTS draft TEXT;
@@ -0,0 +1,13 @@
+-- Token buckets of callers of rate limited endpoints, shared by server instances.
+-- Unlogged, losing them on a crash only resets the limits
+CREATE UNLOGGED TABLE IF NOT EXISTS __B_rate_limits (
+    -- Endpoint id and the username or IP of the caller
+    bucket_key VARCHAR(2048) PRIMARY KEY,
+    tokens DOUBLE PRECISION NOT NULL,
+    -- Seconds since the epoch, by the clock of the database
+    updated_at DOUBLE PRECISION NOT NULL,
+    -- When the bucket is full again and the row can be deleted
+    refilled_at DOUBLE PRECISION NOT NULL,
+    -- Whether the last call was allowed, only read by the statement taking a token
+    allowed BOOLEAN NOT NULL DEFAULT true
+);
@@ -1,19 +1,69 @@
 use crate::auth::create_users_service::create_user;
+use crate::services::endpoints::endpoint_versions;
+use crate::services::row_level_security;
+use crate::setup::manifest::{apply_manifest, Manifest};
 use anyhow::Result;
-use sqlx::{PgPool, Postgres};
+use sqlx::{Executor, PgPool, Postgres};
 
-async fn create_initial_admin(db_pool: &PgPool) -> Result<()> {
-    Ok(())
-}
+/// Columns of the internal tables which the server reads, as created by the files below
+pub const INTERNAL_COLUMNS: &[(&str, &[&str])] = &[
+    (
+        "__b_endpoints",
+        &[
+            "id",
+            "req_path",
+            "req_method",
+            "handler_info",
+            "allowed_groups",
+            "response",
+            "input_schema",
+            "rate_limit",
+            "cache_ttl",
+            "limits",
+            "draft",
+        ],
+    ),
+    (
+        "__b_endpoint_versions",
+        &[
+            "id",
+            "endpoint_id",
+            "version",
+            "action",
+            "definition",
+            "author",
+            "restored_version",
+            "created_at",
+        ],
+    ),
+    (
+        "__b_users",
+        &["id", "username", "password_hash", "user_group"],
+    ),
+    (
+        "__b_rate_limits",
+        &[
+            "bucket_key",
+            "tokens",
+            "updated_at",
+            "refilled_at",
+            "allowed",
+        ],
+    ),
+];
 
-pub async fn init_tables(db_pool: &PgPool) -> Result<()> {
+/// Creates the internal tables and reconciles the database toward the manifest, if given
+pub async fn init_tables(db_pool: &PgPool, manifest: Option<&Manifest>) -> Result<()> {
     let queries = vec![
         include_str!("./init_endpoints.sql"),
+        include_str!("./init_endpoint_versions.sql"),
         include_str!("./init_users.sql"),
+        include_str!("./init_rate_limits.sql"),
     ];
 
+    // executed as simple queries, so that a file can hold several statements
     for query in queries {
-        sqlx::query(query).execute(db_pool).await?;
+        db_pool.execute(query).await?;
     }
 
     // Create initial admin if not exists
@@ -27,5 +77,15 @@ pub async fn init_tables(db_pool: &PgPool) -> Result<()> {
         create_user("admin", "ADMIN", Some("admin1".into()), db_pool).await?;
     }
 
+    endpoint_versions::record_missing_versions(db_pool).await?;
+
+    if let Some(manifest) = manifest {
+        apply_manifest(db_pool, manifest).await?;
+    }
+
+    if *row_level_security::ENABLED {
+        row_level_security::create_group_roles(db_pool).await?;
+    }
+
     Ok(())
 }
@@ -0,0 +1,84 @@
+use crate::setup::setup_internal_tables::init_tables;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{ConnectOptions, Executor, PgPool, Postgres};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Empty database of a test, created on the server of `DATABASE_URL`
+pub struct TestDatabase {
+    pub pool: PgPool,
+    name: String,
+    server: PgConnectOptions,
+}
+
+impl TestDatabase {
+    pub async fn create() -> Self {
+        dotenv::dotenv().ok();
+        let url = std::env::var("DATABASE_URL").expect("Database url not in .env file");
+        let server = PgConnectOptions::from_str(&url).expect("Bad database url");
+
+        let name = format!(
+            "bercik_test_{}_{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        );
+
+        let mut connection = server.connect().await.expect("Can't connect to postgres");
+        connection
+            .execute(format!("DROP DATABASE IF EXISTS {}", name).as_str())
+            .await
+            .unwrap();
+        connection
+            .execute(format!("CREATE DATABASE {}", name).as_str())
+            .await
+            .unwrap();
+
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect_with(server.clone().database(&name))
+            .await
+            .unwrap();
+
+        Self { pool, name, server }
+    }
+
+    /// Database with the internal tables of the server, as after the first start
+    pub async fn with_internal_tables() -> Self {
+        let db = Self::create().await;
+        init_tables(&db.pool, None).await.unwrap();
+        db
+    }
+
+    /// Name of the database, roles with it in their name are dropped with the database
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Drops the database, it is left behind when a test fails before
+    pub async fn drop(self) {
+        self.pool.close().await;
+
+        let mut connection = self.server.connect().await.unwrap();
+        connection
+            .execute(format!("DROP DATABASE {}", self.name).as_str())
+            .await
+            .unwrap();
+
+        let roles = sqlx::query_as::<Postgres, (String,)>(
+            "SELECT rolname::text FROM pg_roles WHERE strpos(rolname, $1) > 0",
+        )
+        .bind(&self.name)
+        .fetch_all(&mut connection)
+        .await
+        .unwrap();
+
+        for (role,) in roles {
+            connection
+                .execute(format!("DROP ROLE \"{}\"", role).as_str())
+                .await
+                .unwrap();
+        }
+    }
+}
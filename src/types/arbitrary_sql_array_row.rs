@@ -13,8 +13,8 @@ impl ArbitrarySqlArrayRowsAndNames {
         let mut names = Vec::new();
         let mut rows = Vec::new();
 
-        let first_row = input_rows.get(0);
-        if let None = first_row {
+        let first_row = input_rows.first();
+        if first_row.is_none() {
             // Can't get column names from zero length result, return empty;
             return Ok(Self { names, rows });
         }
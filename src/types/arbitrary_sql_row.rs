@@ -1,12 +1,20 @@
 use serde::{Deserialize, Serialize};
-use sqlx::{postgres::PgRow, Column, FromRow, Row};
+use serde_json::Value;
+use sqlx::{
+    postgres::{PgRow, PgTypeKind},
+    types::{
+        chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc},
+        Decimal, Uuid,
+    },
+    Column, FromRow, Row, TypeInfo, ValueRef,
+};
 use std::collections::HashMap;
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct ArbitrarySqlRow(HashMap<String, String>);
+pub struct ArbitrarySqlRow(HashMap<String, Value>);
 
 impl ArbitrarySqlRow {
-    pub fn into_map(self) -> HashMap<String, String> {
+    pub fn into_map(self) -> HashMap<String, Value> {
         self.0
     }
 }
@@ -16,9 +24,102 @@ impl FromRow<'_, PgRow> for ArbitrarySqlRow {
         let mut map = HashMap::new();
 
         for i in 0..row.len() {
-            map.insert(row.try_column(i)?.name().into(), row.try_get(i)?);
+            map.insert(row.try_column(i)?.name().into(), decode_column(row, i)?);
         }
 
         Ok(Self(map))
     }
 }
+
+fn decimal_to_json(decimal: Decimal) -> Value {
+    // Goes through the textual form, so that integral numerics stay integers
+    serde_json::from_str(&decimal.to_string()).unwrap_or_else(|_| decimal.to_string().into())
+}
+
+fn timestamp_to_json(timestamp: NaiveDateTime) -> Value {
+    timestamp.format("%Y-%m-%dT%H:%M:%S%.f").to_string().into()
+}
+
+fn array_to_json<T>(elements: Vec<Option<T>>, to_json: impl Fn(T) -> Value) -> Value {
+    elements
+        .into_iter()
+        .map(|it| it.map(&to_json).unwrap_or(Value::Null))
+        .collect()
+}
+
+/// Decodes a single column into a json value, picking the json type
+/// based on the postgres type of the column.
+///
+/// Types without a json counterpart return an error.
+pub fn decode_column(row: &PgRow, index: usize) -> Result<Value, sqlx::Error> {
+    if row.try_get_raw(index)?.is_null() {
+        return Ok(Value::Null);
+    }
+
+    let column = row.try_column(index)?;
+
+    let value = match column.type_info().name() {
+        "BOOL" => row.try_get::<bool, _>(index)?.into(),
+        "INT2" => row.try_get::<i16, _>(index)?.into(),
+        "INT4" => row.try_get::<i32, _>(index)?.into(),
+        "INT8" => row.try_get::<i64, _>(index)?.into(),
+        "FLOAT4" => row.try_get::<f32, _>(index)?.into(),
+        "FLOAT8" => row.try_get::<f64, _>(index)?.into(),
+        "NUMERIC" => decimal_to_json(row.try_get::<Decimal, _>(index)?),
+        "DATE" => row.try_get::<NaiveDate, _>(index)?.to_string().into(),
+        "TIME" => row.try_get::<NaiveTime, _>(index)?.to_string().into(),
+        "TIMESTAMP" => timestamp_to_json(row.try_get::<NaiveDateTime, _>(index)?),
+        "TIMESTAMPTZ" => row.try_get::<DateTime<Utc>, _>(index)?.to_rfc3339().into(),
+        "UUID" => row.try_get::<Uuid, _>(index)?.to_string().into(),
+        "JSON" | "JSONB" => row.try_get::<Value, _>(index)?,
+
+        "BOOL[]" => array_to_json(row.try_get::<Vec<Option<bool>>, _>(index)?, Value::from),
+        "INT2[]" => array_to_json(row.try_get::<Vec<Option<i16>>, _>(index)?, Value::from),
+        "INT4[]" => array_to_json(row.try_get::<Vec<Option<i32>>, _>(index)?, Value::from),
+        "INT8[]" => array_to_json(row.try_get::<Vec<Option<i64>>, _>(index)?, Value::from),
+        "FLOAT4[]" => array_to_json(row.try_get::<Vec<Option<f32>>, _>(index)?, Value::from),
+        "FLOAT8[]" => array_to_json(row.try_get::<Vec<Option<f64>>, _>(index)?, Value::from),
+        "NUMERIC[]" => array_to_json(
+            row.try_get::<Vec<Option<Decimal>>, _>(index)?,
+            decimal_to_json,
+        ),
+        "TEXT[]" | "VARCHAR[]" | "CHAR[]" | "NAME[]" => {
+            array_to_json(row.try_get::<Vec<Option<String>>, _>(index)?, Value::from)
+        }
+        "DATE[]" => array_to_json(row.try_get::<Vec<Option<NaiveDate>>, _>(index)?, |it| {
+            it.to_string().into()
+        }),
+        "TIMESTAMP[]" => array_to_json(
+            row.try_get::<Vec<Option<NaiveDateTime>>, _>(index)?,
+            timestamp_to_json,
+        ),
+        "TIMESTAMPTZ[]" => {
+            array_to_json(row.try_get::<Vec<Option<DateTime<Utc>>>, _>(index)?, |it| {
+                it.to_rfc3339().into()
+            })
+        }
+        "UUID[]" => array_to_json(row.try_get::<Vec<Option<Uuid>>, _>(index)?, |it| {
+            it.to_string().into()
+        }),
+        "JSON[]" | "JSONB[]" => {
+            array_to_json(row.try_get::<Vec<Option<Value>>, _>(index)?, |it| it)
+        }
+
+        "TEXT" | "VARCHAR" | "CHAR" | "NAME" => row.try_get::<String, _>(index)?.into(),
+
+        // enum labels are sent as utf-8 text
+        _ if matches!(column.type_info().kind(), PgTypeKind::Enum(_)) => {
+            row.try_get_unchecked::<String, _>(index)?.into()
+        }
+
+        other => {
+            return Err(sqlx::Error::ColumnDecode {
+                index: column.name().to_owned(),
+                source: format!("type {} has no json representation, cast it to text", other)
+                    .into(),
+            })
+        }
+    };
+
+    Ok(value)
+}
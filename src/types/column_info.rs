@@ -1,14 +1,7 @@
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
-use std::collections::HashMap;
 
-use super::{
-    special_column_info::SpecialColumnType,
-    table_field_types::{DefaultValue, TableField, TableFieldType},
-    table_info::ForeignKeyInfo,
-};
-
-pub type ForeignKeyMap<'a> = HashMap<(&'a str, &'a str), &'a ForeignKeyInfo>;
+use super::special_column_info::SpecialColumnType;
 
 #[derive(FromRow, Debug, Serialize, Deserialize)]
 pub struct ColumnInfo {
@@ -28,28 +21,6 @@ pub struct ColumnInfoWithSpecial {
 }
 
 impl ColumnInfo {
-    pub fn get_table_field_type(
-        &self,
-        table_name: &str,
-        foreign_key_map: &ForeignKeyMap,
-    ) -> TableFieldType {
-        let data_type = self.data_type.as_str();
-        let find_nextval = self.column_default.find("nextval");
-        let references = foreign_key_map.get(&(table_name, &self.name));
-
-        match (data_type, find_nextval, references) {
-            ("integer", None, None) => TableFieldType::Integer,
-            ("integer", Some(0), None) => TableFieldType::Serial,
-            ("integer", None, Some(other)) => {
-                TableFieldType::ForeignKey(other.target_table.to_string())
-            }
-            ("real", _, None) => TableFieldType::RealNumber,
-            ("character varying", _, None) => TableFieldType::String,
-            ("text", _, None) => TableFieldType::Text,
-            (other, _, _) => TableFieldType::CustomType(other.to_string()),
-        }
-    }
-
     pub fn with_special(self, special_info: Option<SpecialColumnType>) -> ColumnInfoWithSpecial {
         let ColumnInfo {
             name,
@@ -2,5 +2,6 @@ pub mod arbitrary_sql_array_row;
 pub mod arbitrary_sql_row;
 pub mod column_info;
 pub mod special_column_info;
+pub mod sql_value;
 pub mod table_field_types;
 pub mod table_info;
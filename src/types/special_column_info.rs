@@ -1,5 +1,4 @@
 use serde::{Deserialize, Serialize};
-use sqlx::Type;
 use std::collections::HashMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,10 +51,6 @@ impl SpecialColumnInfo {
             None
         }
     }
-
-    pub fn get_type(&self) -> SpecialColumnType {
-        self.special_type.clone()
-    }
 }
 
 #[derive(Debug, Hash, PartialEq, Eq)]
@@ -77,11 +72,7 @@ impl SpecialColumnMap {
 
         for col in cols {
             if let Some((key, val)) = col.external_ref_map_key_val() {
-                if external_ref_map.contains_key(&key) {
-                    external_ref_map.get_mut(&key).unwrap().push(val);
-                } else {
-                    external_ref_map.insert(key, vec![val]);
-                }
+                external_ref_map.entry(key).or_default().push(val);
             }
             map.insert(col.special_column_map_key(), col);
         }
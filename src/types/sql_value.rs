@@ -0,0 +1,560 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{
+    encode::IsNull,
+    postgres::{PgArgumentBuffer, PgTypeInfo},
+    types::{
+        chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc},
+        Decimal, Uuid,
+    },
+    Encode, Postgres, Type,
+};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// Type declared for a variable in endpoint sql, e.g. `${req.id:int}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum SqlType {
+    Bool,
+    SmallInt,
+    Int,
+    BigInt,
+    Real,
+    Double,
+    Numeric,
+    Text,
+    Uuid,
+    Date,
+    Time,
+    Timestamp,
+    Timestamptz,
+    Json,
+    Jsonb,
+    Array(Box<SqlType>),
+}
+
+impl SqlType {
+    pub fn from_annotation(annotation: &str) -> Result<Self> {
+        let annotation = annotation.trim().to_lowercase();
+
+        if let Some(element) = annotation.strip_suffix("[]") {
+            return match Self::from_annotation(element)? {
+                Self::Array(_) => Err(anyhow!("Multidimensional arrays are not supported")),
+                element => Ok(Self::Array(Box::new(element))),
+            };
+        }
+
+        Ok(match annotation.as_str() {
+            "bool" | "boolean" => Self::Bool,
+            "smallint" | "int2" => Self::SmallInt,
+            "int" | "integer" | "int4" => Self::Int,
+            "bigint" | "int8" => Self::BigInt,
+            "real" | "float4" => Self::Real,
+            "double precision" | "double" | "float" | "float8" => Self::Double,
+            "numeric" | "decimal" => Self::Numeric,
+            "text" | "varchar" => Self::Text,
+            "uuid" => Self::Uuid,
+            "date" => Self::Date,
+            "time" => Self::Time,
+            "timestamp" => Self::Timestamp,
+            "timestamptz" => Self::Timestamptz,
+            "json" => Self::Json,
+            "jsonb" => Self::Jsonb,
+            other => return Err(anyhow!("Unknown type {}", other)),
+        })
+    }
+
+    /// Builtin postgres OID of the type
+    fn oid(&self) -> u32 {
+        match self {
+            Self::Bool => 16,
+            Self::SmallInt => 21,
+            Self::Int => 23,
+            Self::BigInt => 20,
+            Self::Real => 700,
+            Self::Double => 701,
+            Self::Numeric => 1700,
+            Self::Text => 25,
+            Self::Uuid => 2950,
+            Self::Date => 1082,
+            Self::Time => 1083,
+            Self::Timestamp => 1114,
+            Self::Timestamptz => 1184,
+            Self::Json => 114,
+            Self::Jsonb => 3802,
+            Self::Array(element) => match **element {
+                Self::Bool => 1000,
+                Self::SmallInt => 1005,
+                Self::Int => 1007,
+                Self::BigInt => 1016,
+                Self::Real => 1021,
+                Self::Double => 1022,
+                Self::Numeric => 1231,
+                Self::Text => 1009,
+                Self::Uuid => 2951,
+                Self::Date => 1182,
+                Self::Time => 1183,
+                Self::Timestamp => 1115,
+                Self::Timestamptz => 1185,
+                Self::Json => 199,
+                Self::Jsonb => 3807,
+                Self::Array(_) => unreachable!("multidimensional arrays are rejected when parsing"),
+            },
+        }
+    }
+
+    pub fn type_info(&self) -> PgTypeInfo {
+        PgTypeInfo::with_oid(self.oid())
+    }
+}
+
+impl fmt::Display for SqlType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Bool => "bool",
+            Self::SmallInt => "smallint",
+            Self::Int => "int",
+            Self::BigInt => "bigint",
+            Self::Real => "real",
+            Self::Double => "double precision",
+            Self::Numeric => "numeric",
+            Self::Text => "text",
+            Self::Uuid => "uuid",
+            Self::Date => "date",
+            Self::Time => "time",
+            Self::Timestamp => "timestamp",
+            Self::Timestamptz => "timestamptz",
+            Self::Json => "json",
+            Self::Jsonb => "jsonb",
+            Self::Array(element) => return write!(f, "{}[]", element),
+        };
+        f.write_str(name)
+    }
+}
+
+impl TryFrom<String> for SqlType {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        Self::from_annotation(&value)
+    }
+}
+
+impl From<SqlType> for String {
+    fn from(ty: SqlType) -> Self {
+        ty.to_string()
+    }
+}
+
+/// A value that can be bound as a query parameter of an endpoint query
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlValue {
+    /// NULL without a declared type, postgres infers
+    /// the type from the place where the parameter is used
+    Null,
+    TypedNull(SqlType),
+    Bool(bool),
+    SmallInt(i16),
+    Int(i32),
+    BigInt(i64),
+    Real(f32),
+    Double(f64),
+    Numeric(Decimal),
+    Text(String),
+    Uuid(Uuid),
+    Date(NaiveDate),
+    Time(NaiveTime),
+    Timestamp(NaiveDateTime),
+    Timestamptz(DateTime<Utc>),
+    Json(Value),
+    Jsonb(Value),
+    Array(SqlType, Vec<SqlValue>),
+}
+
+impl SqlValue {
+    /// Picks the natural postgres type of a json value.
+    ///
+    /// Arrays of a single scalar type become postgres arrays,
+    /// every other array or object is bound as jsonb.
+    pub fn from_json(value: Value) -> Self {
+        match value {
+            Value::Null => Self::Null,
+            Value::Bool(b) => Self::Bool(b),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => Self::BigInt(i),
+                None => Self::Double(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Value::String(s) => Self::Text(s),
+            Value::Array(ref elements) if !elements.is_empty() => {
+                let element_type = if elements.iter().all(Value::is_boolean) {
+                    SqlType::Bool
+                } else if elements.iter().all(Value::is_i64) {
+                    SqlType::BigInt
+                } else if elements.iter().all(Value::is_number) {
+                    SqlType::Double
+                } else if elements.iter().all(Value::is_string) {
+                    SqlType::Text
+                } else {
+                    return Self::Jsonb(value);
+                };
+
+                Self::convert(value, &SqlType::Array(Box::new(element_type)))
+                    .expect("elements were checked to match the array type")
+            }
+            other => Self::Jsonb(other),
+        }
+    }
+
+    /// Converts a json value to the declared type.
+    ///
+    /// Strings are parsed, so that values coming from forms
+    /// and query strings can be used with any declared type.
+    pub fn convert(value: Value, ty: &SqlType) -> Result<Self> {
+        fn parse<T: FromStr>(value: &Value) -> Option<T> {
+            match value {
+                Value::String(s) => s.trim().parse().ok(),
+                Value::Number(n) => n.to_string().parse().ok(),
+                _ => None,
+            }
+        }
+
+        fn integer<T: TryFrom<i64> + FromStr>(value: &Value) -> Option<T> {
+            match value {
+                Value::Number(n) => n.as_i64().and_then(|it| T::try_from(it).ok()),
+                other => parse(other),
+            }
+        }
+
+        let converted = match (ty, &value) {
+            (SqlType::Json, _) => Some(Self::Json(value.clone())),
+            (SqlType::Jsonb, _) => Some(Self::Jsonb(value.clone())),
+            (_, Value::Null) => Some(Self::TypedNull(ty.clone())),
+
+            (SqlType::Bool, Value::Bool(b)) => Some(Self::Bool(*b)),
+            (SqlType::Bool, Value::String(s)) => match s.trim().to_lowercase().as_str() {
+                "true" | "t" | "1" => Some(Self::Bool(true)),
+                "false" | "f" | "0" => Some(Self::Bool(false)),
+                _ => None,
+            },
+            (SqlType::SmallInt, _) => integer(&value).map(Self::SmallInt),
+            (SqlType::Int, _) => integer(&value).map(Self::Int),
+            (SqlType::BigInt, _) => integer(&value).map(Self::BigInt),
+            (SqlType::Real, _) => parse(&value).map(Self::Real),
+            (SqlType::Double, _) => parse(&value).map(Self::Double),
+            (SqlType::Numeric, _) => parse::<String>(&value)
+                .and_then(|it| {
+                    Decimal::from_str(&it)
+                        .or_else(|_| Decimal::from_scientific(&it))
+                        .ok()
+                })
+                .map(Self::Numeric),
+            (SqlType::Text, Value::String(s)) => Some(Self::Text(s.clone())),
+            (SqlType::Text, Value::Number(_) | Value::Bool(_)) => {
+                Some(Self::Text(value.to_string()))
+            }
+            (SqlType::Uuid, _) => parse(&value).map(Self::Uuid),
+            (SqlType::Date, _) => parse(&value).map(Self::Date),
+            (SqlType::Time, _) => parse(&value).map(Self::Time),
+            (SqlType::Timestamp, Value::String(s)) => {
+                NaiveDateTime::parse_from_str(s.trim(), "%Y-%m-%dT%H:%M:%S%.f")
+                    .or_else(|_| NaiveDateTime::parse_from_str(s.trim(), "%Y-%m-%d %H:%M:%S%.f"))
+                    .ok()
+                    .map(Self::Timestamp)
+            }
+            (SqlType::Timestamptz, Value::String(s)) => DateTime::parse_from_rfc3339(s.trim())
+                .ok()
+                .map(|it| Self::Timestamptz(it.with_timezone(&Utc))),
+
+            (SqlType::Array(element), Value::Array(elements)) => {
+                let elements = elements
+                    .iter()
+                    .map(|it| Self::convert(it.clone(), element))
+                    .collect::<Result<Vec<_>>>()?;
+                Some(Self::Array((**element).clone(), elements))
+            }
+            // arrays sent through forms and query strings
+            (SqlType::Array(_), Value::String(s)) => match serde_json::from_str(s) {
+                Ok(array @ Value::Array(_)) => return Self::convert(array, ty),
+                _ => None,
+            },
+
+            _ => None,
+        };
+
+        converted.ok_or_else(|| anyhow!("Expected {}, got {}", ty, value))
+    }
+
+    /// `None` for an untyped `Null`
+    pub fn sql_type(&self) -> Option<SqlType> {
+        let ty = match self {
+            Self::Null => return None,
+            Self::TypedNull(ty) => ty.clone(),
+            Self::Bool(_) => SqlType::Bool,
+            Self::SmallInt(_) => SqlType::SmallInt,
+            Self::Int(_) => SqlType::Int,
+            Self::BigInt(_) => SqlType::BigInt,
+            Self::Real(_) => SqlType::Real,
+            Self::Double(_) => SqlType::Double,
+            Self::Numeric(_) => SqlType::Numeric,
+            Self::Text(_) => SqlType::Text,
+            Self::Uuid(_) => SqlType::Uuid,
+            Self::Date(_) => SqlType::Date,
+            Self::Time(_) => SqlType::Time,
+            Self::Timestamp(_) => SqlType::Timestamp,
+            Self::Timestamptz(_) => SqlType::Timestamptz,
+            Self::Json(_) => SqlType::Json,
+            Self::Jsonb(_) => SqlType::Jsonb,
+            Self::Array(element, _) => SqlType::Array(Box::new(element.clone())),
+        };
+        Some(ty)
+    }
+
+    /// Packs values of one scalar type into an array.
+    ///
+    /// Returns `None` when the types differ, the values are arrays
+    /// or there is no value the type could be taken from.
+    pub fn array_of(values: Vec<SqlValue>) -> Option<Self> {
+        let element = values.iter().find_map(Self::sql_type)?;
+
+        let same_type = values
+            .iter()
+            .all(|it| it.sql_type().is_none_or(|ty| ty == element));
+
+        if matches!(element, SqlType::Array(_)) || !same_type {
+            return None;
+        }
+
+        Some(Self::Array(element, values))
+    }
+
+    fn type_info(&self) -> PgTypeInfo {
+        match self.sql_type() {
+            Some(ty) => ty.type_info(),
+            None => PgTypeInfo::with_oid(0),
+        }
+    }
+}
+
+impl Type<Postgres> for SqlValue {
+    fn type_info() -> PgTypeInfo {
+        // The real type depends on the value, see `Encode::produces`
+        PgTypeInfo::with_oid(0)
+    }
+}
+
+impl Encode<'_, Postgres> for SqlValue {
+    fn produces(&self) -> Option<PgTypeInfo> {
+        Some(self.type_info())
+    }
+
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> IsNull {
+        match self {
+            Self::Null | Self::TypedNull(_) => IsNull::Yes,
+            Self::Bool(v) => v.encode_by_ref(buf),
+            Self::SmallInt(v) => v.encode_by_ref(buf),
+            Self::Int(v) => v.encode_by_ref(buf),
+            Self::BigInt(v) => v.encode_by_ref(buf),
+            Self::Real(v) => v.encode_by_ref(buf),
+            Self::Double(v) => v.encode_by_ref(buf),
+            Self::Numeric(v) => v.encode_by_ref(buf),
+            Self::Text(v) => v.encode_by_ref(buf),
+            Self::Uuid(v) => v.encode_by_ref(buf),
+            Self::Date(v) => v.encode_by_ref(buf),
+            Self::Time(v) => v.encode_by_ref(buf),
+            Self::Timestamp(v) => v.encode_by_ref(buf),
+            Self::Timestamptz(v) => v.encode_by_ref(buf),
+            Self::Json(v) => {
+                // the binary format of json is its text
+                buf.extend(v.to_string().as_bytes());
+                IsNull::No
+            }
+            Self::Jsonb(v) => {
+                // jsonb binary format version, followed by the text
+                buf.push(1);
+                buf.extend(v.to_string().as_bytes());
+                IsNull::No
+            }
+            Self::Array(element, values) => {
+                // https://github.com/postgres/postgres/blob/master/src/backend/utils/adt/arrayfuncs.c (array_send)
+                buf.extend(&1_i32.to_be_bytes()); // number of dimensions
+                buf.extend(&0_i32.to_be_bytes()); // flags
+                buf.extend(&element.oid().to_be_bytes());
+                buf.extend(&(values.len() as i32).to_be_bytes());
+                buf.extend(&1_i32.to_be_bytes()); // lower bound
+
+                for value in values {
+                    let offset = buf.len();
+                    buf.extend(&[0; 4]);
+
+                    let len = match value.encode_by_ref(buf) {
+                        IsNull::No => (buf.len() - offset - 4) as i32,
+                        IsNull::Yes => -1,
+                    };
+                    buf[offset..(offset + 4)].copy_from_slice(&len.to_be_bytes());
+                }
+
+                IsNull::No
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn scalars() {
+        assert_eq!(SqlValue::from_json(json!(null)), SqlValue::Null);
+        assert_eq!(SqlValue::from_json(json!(true)), SqlValue::Bool(true));
+        assert_eq!(SqlValue::from_json(json!(41)), SqlValue::BigInt(41));
+        assert_eq!(SqlValue::from_json(json!(4.5)), SqlValue::Double(4.5));
+        assert_eq!(
+            SqlValue::from_json(json!("41")),
+            SqlValue::Text("41".into())
+        );
+    }
+
+    #[test]
+    fn arrays() {
+        assert_eq!(
+            SqlValue::from_json(json!([1, 2])),
+            SqlValue::Array(
+                SqlType::BigInt,
+                vec![SqlValue::BigInt(1), SqlValue::BigInt(2)]
+            )
+        );
+        assert_eq!(
+            SqlValue::from_json(json!([1, 2.5])),
+            SqlValue::Array(
+                SqlType::Double,
+                vec![SqlValue::Double(1.0), SqlValue::Double(2.5)]
+            )
+        );
+        assert_eq!(
+            SqlValue::from_json(json!(["a", "b"])),
+            SqlValue::Array(
+                SqlType::Text,
+                vec![SqlValue::Text("a".into()), SqlValue::Text("b".into())]
+            )
+        );
+        assert_eq!(
+            SqlValue::from_json(json!([1, "b"])),
+            SqlValue::Jsonb(json!([1, "b"]))
+        );
+        assert_eq!(SqlValue::from_json(json!([])), SqlValue::Jsonb(json!([])));
+    }
+
+    #[test]
+    fn parsing_annotations() {
+        assert_eq!(SqlType::from_annotation("int").unwrap(), SqlType::Int);
+        assert_eq!(SqlType::from_annotation(" INTEGER ").unwrap(), SqlType::Int);
+        assert_eq!(
+            SqlType::from_annotation("text[]").unwrap(),
+            SqlType::Array(Box::new(SqlType::Text))
+        );
+        assert_eq!(SqlType::from_annotation("jsonb").unwrap(), SqlType::Jsonb);
+
+        assert!(SqlType::from_annotation("int[][]").is_err());
+        assert_eq!(
+            SqlType::from_annotation("money").unwrap_err().to_string(),
+            "Unknown type money"
+        );
+    }
+
+    #[test]
+    fn converting_strings() {
+        assert_eq!(
+            SqlValue::convert(json!("41"), &SqlType::Int).unwrap(),
+            SqlValue::Int(41)
+        );
+        assert_eq!(
+            SqlValue::convert(json!("t"), &SqlType::Bool).unwrap(),
+            SqlValue::Bool(true)
+        );
+        assert_eq!(
+            SqlValue::convert(json!("12.30"), &SqlType::Numeric).unwrap(),
+            SqlValue::Numeric(Decimal::new(1230, 2))
+        );
+        assert_eq!(
+            SqlValue::convert(json!("2022-01-02"), &SqlType::Date).unwrap(),
+            SqlValue::Date(NaiveDate::from_ymd(2022, 1, 2))
+        );
+        assert_eq!(
+            SqlValue::convert(
+                json!(r#"["a", null]"#),
+                &SqlType::from_annotation("text[]").unwrap()
+            )
+            .unwrap(),
+            SqlValue::Array(
+                SqlType::Text,
+                vec![
+                    SqlValue::Text("a".into()),
+                    SqlValue::TypedNull(SqlType::Text)
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn converting_json_values() {
+        assert_eq!(
+            SqlValue::convert(json!(41), &SqlType::SmallInt).unwrap(),
+            SqlValue::SmallInt(41)
+        );
+        assert_eq!(
+            SqlValue::convert(json!(null), &SqlType::Int).unwrap(),
+            SqlValue::TypedNull(SqlType::Int)
+        );
+        assert_eq!(
+            SqlValue::convert(json!({"a": 1}), &SqlType::Jsonb).unwrap(),
+            SqlValue::Jsonb(json!({"a": 1}))
+        );
+    }
+
+    #[test]
+    fn conversion_errors() {
+        assert_eq!(
+            SqlValue::convert(json!("abc"), &SqlType::Int)
+                .unwrap_err()
+                .to_string(),
+            r#"Expected int, got "abc""#
+        );
+        assert!(SqlValue::convert(json!(100000), &SqlType::SmallInt).is_err());
+        assert!(SqlValue::convert(json!(4.5), &SqlType::Int).is_err());
+        assert!(SqlValue::convert(json!("not-a-uuid"), &SqlType::Uuid).is_err());
+        assert!(SqlValue::convert(json!({"a": 1}), &SqlType::Text).is_err());
+    }
+
+    #[test]
+    fn packing_arrays() {
+        assert_eq!(
+            SqlValue::array_of(vec![SqlValue::Null, SqlValue::BigInt(1)]),
+            Some(SqlValue::Array(
+                SqlType::BigInt,
+                vec![SqlValue::Null, SqlValue::BigInt(1)]
+            ))
+        );
+        assert_eq!(
+            SqlValue::array_of(vec![SqlValue::TypedNull(SqlType::Int)]),
+            Some(SqlValue::Array(
+                SqlType::Int,
+                vec![SqlValue::TypedNull(SqlType::Int)]
+            ))
+        );
+
+        assert_eq!(SqlValue::array_of(vec![SqlValue::Null]), None);
+        assert_eq!(
+            SqlValue::array_of(vec![SqlValue::BigInt(1), SqlValue::Text("a".into())]),
+            None
+        );
+        assert_eq!(
+            SqlValue::array_of(vec![SqlValue::from_json(json!([1, 2]))]),
+            None
+        );
+    }
+}
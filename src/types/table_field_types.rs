@@ -23,7 +23,7 @@ impl DefaultValue {
     pub fn to_postgres_default_value(&self) -> String {
         match self {
             &Self::None => "".to_string(),
-            &Self::Value(ref v) => format!("DEFAULT {}", v),
+            Self::Value(v) => format!("DEFAULT {}", v),
         }
     }
 }
@@ -39,20 +39,20 @@ pub struct TableField {
 impl TableFieldType {
     pub fn to_postgres_type(&self) -> String {
         match self {
-            &Self::ForeignKey(ref str) => return format!("INT REFERENCES {}(id)", str),
-            &Self::CustomType(ref str) => return str.clone(),
+            Self::ForeignKey(str) => return format!("INT REFERENCES {}(id)", str),
+            Self::CustomType(str) => return str.clone(),
             _ => {}
         };
 
-        String::from(match self {
-            &Self::Integer => "int",
-            &Self::Serial => "serial",
-            &Self::RealNumber => "real",
-            &Self::String => "varchar(255)",
-            &Self::Text => "text",
-            &Self::Date => "date",
-            &Self::ForeignKey(_) => unreachable!(),
-            &Self::CustomType(_) => unreachable!(),
+        String::from(match *self {
+            Self::Integer => "int",
+            Self::Serial => "serial",
+            Self::RealNumber => "real",
+            Self::String => "varchar(255)",
+            Self::Text => "text",
+            Self::Date => "date",
+            Self::ForeignKey(_) => unreachable!(),
+            Self::CustomType(_) => unreachable!(),
         })
     }
 }